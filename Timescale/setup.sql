@@ -1,2 +1,3 @@
+CREATE EXTENSION IF NOT EXISTS timescaledb;
 CREATE USER superset WITH PASSWORD 'superset';
 CREATE USER collector WITH PASSWORD 'collector';
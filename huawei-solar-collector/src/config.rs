@@ -0,0 +1,686 @@
+use std::{collections::HashSet, fs, path::Path, str::FromStr, time::Duration};
+
+use crate::schedule::MissedSlots;
+use cron::Schedule;
+use huawei_solar::{
+    batch::Planner,
+    device::Capabilities,
+    registers::{meter, Access, Gain, Register, Type, PV_STRINGS},
+};
+use serde::{
+    de::{self, Deserializer},
+    Deserialize,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    #[serde(deserialize_with = "duration")]
+    pub db_timeout: Duration,
+    /// Wait between the attempts to connect to the database at startup
+    #[serde(default = "default_db_retry_interval", deserialize_with = "duration")]
+    pub db_retry_interval: Duration,
+    pub modbus: ModbusConfig,
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub missed_slots: MissedSlots,
+    /// Store the tables as TimescaleDB hypertables, plain tables if not set
+    pub timescale: Option<TimescaleConfig>,
+    /// Keep samples on disk while the database is unreachable, dropped if
+    /// not set
+    pub spool: Option<SpoolConfig>,
+    pub queries: Vec<TableConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SpoolConfig {
+    /// File the samples are appended to, next to `<path>.offset`
+    pub path: String,
+    /// Samples kept at most, the oldest are dropped beyond
+    pub max_samples: usize,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct TimescaleConfig {
+    /// Time range covered by each chunk, TimescaleDB's default if not set
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub chunk_interval: Option<Duration>,
+    /// Age after which chunks are compressed
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub compress_after: Option<Duration>,
+    /// Age after which samples are dropped, the aggregates keep theirs
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub retention: Option<Duration>,
+    /// Continuous aggregates of every table
+    #[serde(default)]
+    pub aggregates: Vec<AggregateConfig>,
+    /// Time zone of the site, e.g. `Europe/Berlin`, the daily buckets start
+    /// at its midnight rather than UTC's
+    pub timezone: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AggregateConfig {
+    pub bucket: Bucket,
+    /// Age after which aggregated rows are dropped
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub retention: Option<Duration>,
+}
+
+/// Time bucket of a continuous aggregate, the view of table `t` is named
+/// `t_hourly` or `t_daily`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Bucket {
+    Hourly,
+    Daily,
+}
+
+impl Bucket {
+    pub fn name(self) -> &'static str {
+        match self {
+            Bucket::Hourly => "hourly",
+            Bucket::Daily => "daily",
+        }
+    }
+
+    pub fn interval(self) -> Duration {
+        match self {
+            Bucket::Hourly => Duration::from_secs(3600),
+            Bucket::Daily => Duration::from_secs(86400),
+        }
+    }
+
+    /// Buckets refreshed by each run of the refresh policy, the raw samples
+    /// must be kept at least that long
+    pub fn refresh_window(self) -> Duration {
+        3 * self.interval()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ModbusConfig {
+    #[serde(deserialize_with = "duration")]
+    pub read_timeout: Duration,
+    #[serde(deserialize_with = "duration")]
+    pub connect_timeout: Duration,
+    #[serde(deserialize_with = "duration")]
+    pub write_timeout: Duration,
+    pub host: String,
+    pub port: u16,
+    #[serde(default)]
+    pub unit_id: u8,
+    /// Maximum number of unused registers read to save a request
+    pub max_gap: Option<u16>,
+    /// Address ranges `[start, end)` the inverter refuses to read
+    #[serde(default)]
+    pub holes: Vec<(u16, u16)>,
+    /// File to [record](huawei_solar::capture) all Modbus exchanges to
+    pub record: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TableConfig {
+    pub table: String,
+    /// Device to read the table from, defaults to `modbus.unit_id`
+    pub unit_id: Option<u8>,
+    #[serde(deserialize_with = "schedule")]
+    pub cron: Schedule,
+    /// Registers of a whole device, stored in front of `values`
+    pub preset: Option<Preset>,
+    #[serde(default)]
+    pub values: Vec<RegisterConfig>,
+}
+
+/// Register sets of the library, one column per register
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    /// [meter] registers of the smart power meter
+    Meter,
+    /// Voltage and current of each PV string, limited to the strings of the
+    /// inverter with [Config::retain_supported]
+    PvStrings,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RegisterConfig {
+    pub name: String,
+    pub address: u16,
+    #[serde(deserialize_with = "gain")]
+    pub scale: Gain,
+    pub unit: Option<String>,
+    #[serde(rename = "type", deserialize_with = "typ")]
+    pub typ: Type,
+    /// Only required for types without a fixed size
+    pub quantity: Option<u8>,
+    /// Store the decoded state instead of the number
+    pub decode: Option<Decode>,
+    /// Energy counter reset at midnight, e.g. `ENERGY_YIELD_DAY`
+    #[serde(default)]
+    pub resets_daily: bool,
+}
+
+/// Registers holding a state code rather than a measurement
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Decode {
+    /// [DeviceStatus](huawei_solar::registers::DeviceStatus) code, stored by name
+    DeviceStatus,
+    /// [Alarms](huawei_solar::registers::Alarms) of `ALARM_1` to `ALARM_3`,
+    /// stored as array of the active alarm IDs
+    Alarms,
+}
+
+impl Config {
+    /// Read and validate the configuration file at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, Box<dyn std::error::Error>> {
+        let mut cfg: Config = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+        cfg.expand_presets();
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    fn expand_presets(&mut self) {
+        for table in &mut self.queries {
+            if let Some(preset) = table.preset {
+                table.values.splice(0..0, preset.values());
+            }
+        }
+    }
+
+    /// Drop the columns of registers the inverter with `unit_id` does not
+    /// implement and tables left without columns, returning the columns as
+    /// `table.column`. Tables of other devices are kept as they are.
+    pub fn retain_supported(&mut self, capabilities: &Capabilities, unit_id: u8) -> Vec<String> {
+        let mut dropped = Vec::new();
+        for table in &mut self.queries {
+            if table.unit_id.is_some_and(|id| id != unit_id) {
+                continue;
+            }
+            table.values.retain(|reg| {
+                let supported = capabilities.supports(&reg.register());
+                if !supported {
+                    dropped.push(format!("{}.{}", table.table, reg.name));
+                }
+                supported
+            });
+        }
+        self.queries.retain(|table| !table.values.is_empty());
+        dropped
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(&(start, end)) = self.modbus.holes.iter().find(|(start, end)| start >= end) {
+            return Err(format!("invalid hole [{}, {})", start, end));
+        }
+        if let Some(timescale) = &self.timescale {
+            timescale.validate()?;
+        }
+        if self
+            .spool
+            .as_ref()
+            .is_some_and(|spool| spool.max_samples == 0)
+        {
+            return Err(String::from("spool.max_samples must not be zero"));
+        }
+        let mut tables = HashSet::new();
+        for table in &self.queries {
+            if !is_identifier(&table.table) {
+                return Err(format!("invalid table name '{}'", table.table));
+            }
+            if table.table.eq_ignore_ascii_case(crate::db::METADATA_TABLE) {
+                return Err(format!("table name '{}' is reserved", table.table));
+            }
+            if !tables.insert(table.table.to_lowercase()) {
+                return Err(format!("table '{}' is configured twice", table.table));
+            }
+            if table.values.is_empty() {
+                return Err(format!("table '{}' has no values", table.table));
+            }
+
+            let mut columns = HashSet::new();
+            for reg in &table.values {
+                let context = format!("{}.{}", table.table, reg.name);
+                if !is_identifier(&reg.name) || reg.name.eq_ignore_ascii_case("time") {
+                    return Err(format!("invalid column name '{}'", context));
+                }
+                if !columns.insert(reg.name.to_lowercase()) {
+                    return Err(format!("column '{}' is configured twice", context));
+                }
+                // undecoded values are stored as `real` columns
+                if reg.decode.is_none() && reg.typ.size().is_none() {
+                    return Err(format!(
+                        "{}: type {:?} cannot be stored as number",
+                        context, reg.typ
+                    ));
+                }
+                if reg
+                    .quantity
+                    .is_some_and(|q| reg.typ.size().is_some_and(|size| q != size))
+                {
+                    return Err(format!(
+                        "{}: quantity does not match type {:?}",
+                        context, reg.typ
+                    ));
+                }
+                if reg.decode == Some(Decode::DeviceStatus) && reg.typ != Type::U16 {
+                    return Err(format!("{}: device status must be of type U16", context));
+                }
+                if reg.decode == Some(Decode::Alarms)
+                    && (reg.typ != Type::BF || reg.quantity != Some(3))
+                {
+                    return Err(format!(
+                        "{}: alarms must be of type BF with quantity 3",
+                        context
+                    ));
+                }
+                if reg.resets_daily && !reg.is_energy() {
+                    return Err(format!(
+                        "{}: only energy counters in kWh or Wh reset daily",
+                        context
+                    ));
+                }
+                if reg.address.checked_add(reg.quantity().into()).is_none() {
+                    return Err(format!("{}: address out of range", context));
+                }
+            }
+        }
+        // views of the continuous aggregates share the namespace of the tables
+        for aggregate in self.timescale.iter().flat_map(|t| &t.aggregates) {
+            for table in &self.queries {
+                let view = format!("{}_{}", table.table, aggregate.bucket.name());
+                if tables.contains(&view.to_lowercase()) {
+                    return Err(format!(
+                        "table '{}' clashes with the {} aggregate of '{}'",
+                        view,
+                        aggregate.bucket.name(),
+                        table.table
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TimescaleConfig {
+    fn validate(&self) -> Result<(), String> {
+        let mut buckets = HashSet::new();
+        for aggregate in &self.aggregates {
+            let bucket = aggregate.bucket;
+            if !buckets.insert(bucket) {
+                return Err(format!("{} aggregate is configured twice", bucket.name()));
+            }
+            if self
+                .retention
+                .is_some_and(|retention| retention <= bucket.refresh_window())
+            {
+                return Err(format!(
+                    "retention must exceed the {:?} refreshed by the {} aggregate",
+                    bucket.refresh_window(),
+                    bucket.name()
+                ));
+            }
+        }
+        if self
+            .chunk_interval
+            .is_some_and(|interval| interval.is_zero())
+        {
+            return Err(String::from("chunk interval must not be zero"));
+        }
+        // ends up quoted in SQL statements
+        if let Some(timezone) = &self.timezone {
+            if timezone.is_empty()
+                || !timezone
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "/_+-".contains(c))
+            {
+                return Err(format!("invalid time zone '{}'", timezone));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ModbusConfig {
+    pub fn planner(&self) -> Planner {
+        let planner = self
+            .holes
+            .iter()
+            .fold(Planner::default(), |planner, &(start, end)| {
+                planner.with_hole(start..end)
+            });
+        match self.max_gap {
+            Some(max_gap) => planner.with_max_gap(max_gap),
+            None => planner,
+        }
+    }
+}
+
+impl Preset {
+    fn registers(self) -> Vec<&'static Register<'static>> {
+        match self {
+            Preset::Meter => meter::REGISTERS.to_vec(),
+            Preset::PvStrings => PV_STRINGS
+                .iter()
+                .flat_map(|(voltage, current)| [voltage.erased(), current.erased()])
+                .collect(),
+        }
+    }
+
+    /// Columns named after the registers in lower case
+    fn values(self) -> impl Iterator<Item = RegisterConfig> {
+        self.registers().into_iter().map(|reg| RegisterConfig {
+            name: reg.name.to_lowercase(),
+            address: reg.address,
+            scale: reg.gain,
+            unit: reg.unit.map(String::from),
+            typ: reg.typ,
+            quantity: None,
+            decode: None,
+            resets_daily: false,
+        })
+    }
+}
+
+impl RegisterConfig {
+    pub fn quantity(&self) -> u8 {
+        self.quantity.or(self.typ.size()).unwrap_or(1)
+    }
+
+    /// Whether the register counts energy
+    pub fn is_energy(&self) -> bool {
+        matches!(self.unit.as_deref(), Some("kWh" | "Wh"))
+    }
+
+    /// Postgres column type
+    pub fn sql_type(&self) -> &'static str {
+        match self.decode {
+            None => "real",
+            Some(Decode::DeviceStatus) => "text",
+            Some(Decode::Alarms) => "integer[]",
+        }
+    }
+
+    pub fn register(&self) -> Register<'_> {
+        Register {
+            address: self.address,
+            quantity: self.quantity(),
+            gain: self.scale,
+            unit: self.unit.as_deref(),
+            access: Access::RO,
+            typ: self.typ,
+            name: &self.name,
+        }
+    }
+}
+
+/// Names end up unquoted in SQL statements
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// [Deserialize] a [Schedule] from a [String]
+fn schedule<'de, D>(deserializer: D) -> Result<Schedule, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    Schedule::from_str(&s).map_err(de::Error::custom)
+}
+
+/// Default of [Config::db_retry_interval]
+fn default_db_retry_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+/// [Deserialize] a [Duration] from a [String] using the [parse_duration] crate
+fn duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_duration::parse(&s).map_err(de::Error::custom)
+}
+
+/// [Deserialize] an optional [Duration], see [duration]
+fn optional_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    duration(deserializer).map(Some)
+}
+
+/// [Deserialize] a register [Type] from its name, e.g. `"I32"`
+fn typ<'de, D>(deserializer: D) -> Result<Type, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(de::Error::custom)
+}
+
+/// [Deserialize] a [Gain] from a scale factor, e.g. `0.1` for [Gain::div]`(10)`
+fn gain<'de, D>(deserializer: D) -> Result<Gain, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let scale = f64::deserialize(deserializer)?;
+    scale_to_gain(scale).ok_or_else(|| de::Error::custom(format!("unsupported scale {}", scale)))
+}
+
+fn scale_to_gain(scale: f64) -> Option<Gain> {
+    if !scale.is_finite() || scale <= 0.0 {
+        return None;
+    }
+    let (factor, gain): (f64, fn(u32) -> Gain) = if scale < 1.0 {
+        (1.0 / scale, Gain::div)
+    } else {
+        (scale, Gain::mul)
+    };
+    let rounded = factor.round();
+    if (factor - rounded).abs() > 1e-6 || rounded > u32::MAX as f64 {
+        return None;
+    }
+    Some(gain(rounded as u32))
+}
+
+#[test]
+fn scale_to_gain_test() {
+    assert_eq!(scale_to_gain(1.0), Some(Gain::NONE));
+    assert_eq!(scale_to_gain(0.1), Some(Gain::div(10)));
+    assert_eq!(scale_to_gain(0.001), Some(Gain::div(1000)));
+    assert_eq!(scale_to_gain(10.0), Some(Gain::mul(10)));
+    assert_eq!(scale_to_gain(0.3), None);
+    assert_eq!(scale_to_gain(0.0), None);
+    assert_eq!(scale_to_gain(-1.0), None);
+}
+
+#[test]
+fn load_bundled_config() {
+    let cfg = Config::load(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/resources/config.yaml"
+    ))
+    .unwrap();
+    assert_eq!(cfg.modbus.port, 6607);
+    assert_eq!(cfg.db_retry_interval, Duration::from_secs(10));
+    assert_eq!(cfg.missed_slots, MissedSlots::Skip);
+    assert_eq!(cfg.modbus.planner(), Planner::default().with_max_gap(32));
+    let voltage = cfg.queries[0].values[0].register();
+    assert_eq!(voltage.address, 32016);
+    assert_eq!(voltage.quantity, 1);
+    assert_eq!(voltage.gain, Gain::div(10));
+    assert_eq!(voltage.typ, Type::I16);
+    assert_eq!(cfg.queries[0].unit_id, None);
+    assert_eq!(cfg.spool.unwrap().max_samples, 100000);
+}
+
+#[test]
+fn reject_invalid_config() {
+    let parse = |values: &str| -> Result<(), String> {
+        let yaml = format!(
+            "db_timeout: 2s\nmodbus: {{connect_timeout: 5s, read_timeout: 5s, write_timeout: 5s, host: localhost, port: 502}}\nqueries:\n  - table: t\n    cron: '0 * * * * * *'\n    values: [{}]",
+            values
+        );
+        let cfg: Config = serde_yaml::from_str(&yaml).map_err(|e| e.to_string())?;
+        cfg.validate()
+    };
+    assert!(parse("{name: a, address: 1, scale: 0.1, type: I16}").is_ok());
+    assert!(parse("{name: a, address: 1, scale: 0.1, type: F16}").is_err());
+    assert!(parse("{name: a, address: 1, scale: 0.1, type: STR, quantity: 2}").is_err());
+    assert!(parse("{name: a, address: 1, scale: 0.1, type: I32, quantity: 1}").is_err());
+    assert!(parse("{name: 'a;drop', address: 1, scale: 0.1, type: I16}").is_err());
+    assert!(parse(
+        "{name: a, address: 1, scale: 0.1, type: I16}, {name: A, address: 2, scale: 1, type: U16}"
+    )
+    .is_err());
+    assert!(parse("{name: a, address: 65535, scale: 1, type: U32}").is_err());
+    let cfg: Config = serde_yaml::from_str(
+        "db_timeout: 2s\nmodbus: {connect_timeout: 5s, read_timeout: 5s, write_timeout: 5s, host: localhost, port: 502, holes: [[10, 5]]}\nqueries: []",
+    )
+    .unwrap();
+    assert!(cfg.validate().is_err());
+    assert!(parse("{name: a, address: 1, scale: 1, type: U16, decode: device_status}").is_ok());
+    assert!(parse("{name: a, address: 1, scale: 1, type: I32, decode: device_status}").is_err());
+    assert!(parse("{name: a, address: 1, scale: 1, type: U16, decode: alarms}").is_err());
+    assert!(
+        parse("{name: a, address: 1, scale: 1, type: BF, quantity: 3, decode: alarms}").is_ok()
+    );
+    assert!(
+        parse("{name: a, address: 1, scale: 1, type: BF, quantity: 2, decode: alarms}").is_err()
+    );
+    assert!(parse("{name: a, address: 1, scale: 1, type: BF, quantity: 3}").is_err());
+    assert!(
+        parse("{name: a, address: 1, scale: 0.01, unit: kWh, type: U32, resets_daily: true}")
+            .is_ok()
+    );
+    assert!(
+        parse("{name: a, address: 1, scale: 1, unit: kW, type: U32, resets_daily: true}").is_err()
+    );
+    let cfg: Config = serde_yaml::from_str(
+        "db_timeout: 2s\nmodbus: {connect_timeout: 5s, read_timeout: 5s, write_timeout: 5s, host: localhost, port: 502}\nspool: {path: spool.txt, max_samples: 0}\nqueries: []",
+    )
+    .unwrap();
+    assert!(cfg.validate().is_err());
+    let cfg: Config = serde_yaml::from_str(
+        "db_timeout: 2s\nmodbus: {connect_timeout: 5s, read_timeout: 5s, write_timeout: 5s, host: localhost, port: 502}\nqueries: [{table: register_metadata, cron: '0 * * * * * *', values: [{name: a, address: 1, scale: 1, type: U16}]}]",
+    )
+    .unwrap();
+    assert!(cfg.validate().is_err());
+}
+
+#[test]
+fn timescale_config() {
+    let parse = |timescale: &str| -> Result<Config, String> {
+        let yaml = format!(
+            "db_timeout: 2s\nmodbus: {{connect_timeout: 5s, read_timeout: 5s, write_timeout: 5s, host: localhost, port: 502}}\ntimescale: {}\nqueries: [{{table: t, cron: '0 * * * * * *', values: [{{name: a, address: 1, scale: 1, type: U16}}]}}, {{table: u, cron: '0 * * * * * *', values: [{{name: a, address: 1, scale: 1, type: U16}}]}}]",
+            timescale
+        );
+        let cfg: Config = serde_yaml::from_str(&yaml).map_err(|e| e.to_string())?;
+        cfg.validate()?;
+        Ok(cfg)
+    };
+    let cfg = parse("{chunk_interval: 1 day, compress_after: 7 days, retention: 1 year, aggregates: [{bucket: hourly, retention: 5 years}, {bucket: daily}]}").unwrap();
+    let timescale = cfg.timescale.unwrap();
+    assert_eq!(timescale.chunk_interval, Some(Duration::from_secs(86400)));
+    assert_eq!(timescale.aggregates[0].bucket, Bucket::Hourly);
+    assert_eq!(timescale.aggregates[1].retention, None);
+    assert!(parse("{}").unwrap().timescale.unwrap().retention.is_none());
+    assert!(parse("{retention: 3 days, aggregates: [{bucket: hourly}]}").is_ok());
+    assert!(parse("{retention: 3 days, aggregates: [{bucket: daily}]}").is_err());
+    assert!(parse("{aggregates: [{bucket: daily}, {bucket: daily}]}").is_err());
+    assert!(parse("{chunk_interval: 0s}").is_err());
+    assert!(parse("{aggregates: [{bucket: weekly}]}").is_err());
+    assert_eq!(
+        parse("{timezone: Europe/Berlin}")
+            .unwrap()
+            .timescale
+            .unwrap()
+            .timezone
+            .as_deref(),
+        Some("Europe/Berlin")
+    );
+    assert!(parse("{timezone: \"UTC'; DROP TABLE t; --\"}").is_err());
+
+    let cfg: Config = serde_yaml::from_str(
+        "db_timeout: 2s\nmodbus: {connect_timeout: 5s, read_timeout: 5s, write_timeout: 5s, host: localhost, port: 502}\ntimescale: {aggregates: [{bucket: daily}]}\nqueries: [{table: t, cron: '0 * * * * * *', values: [{name: a, address: 1, scale: 1, type: U16}]}, {table: T_Daily, cron: '0 * * * * * *', values: [{name: a, address: 1, scale: 1, type: U16}]}]",
+    )
+    .unwrap();
+    assert!(cfg.validate().is_err());
+}
+
+#[test]
+fn expand_meter_preset() {
+    let yaml = "db_timeout: 2s\nmodbus: {connect_timeout: 5s, read_timeout: 5s, write_timeout: 5s, host: localhost, port: 502}\nqueries:\n  - table: meter\n    cron: '0 * * * * * *'\n    preset: meter\n    values: [{name: note, address: 1, scale: 1, type: U16}]";
+    let mut cfg: Config = serde_yaml::from_str(yaml).unwrap();
+    cfg.expand_presets();
+    cfg.validate().unwrap();
+    let values = &cfg.queries[0].values;
+    assert_eq!(values.len(), meter::REGISTERS.len() + 1);
+    assert_eq!(values[0].name, "meter_status");
+    let power = values
+        .iter()
+        .find(|v| v.name == "meter_active_power")
+        .unwrap();
+    let power = power.register();
+    assert_eq!(power.address, meter::ACTIVE_POWER.address);
+    assert_eq!(power.gain, meter::ACTIVE_POWER.gain);
+    assert_eq!(power.typ, Type::I32);
+    assert_eq!(power.unit, Some("W"));
+    assert_eq!(values.last().unwrap().name, "note");
+
+    // presets count against duplicate columns
+    let mut cfg: Config =
+        serde_yaml::from_str(&yaml.replace("name: note", "name: meter_status")).unwrap();
+    cfg.expand_presets();
+    assert!(cfg.validate().is_err());
+}
+
+#[test]
+fn pv_strings_preset() {
+    let yaml = "db_timeout: 2s\nmodbus: {connect_timeout: 5s, read_timeout: 5s, write_timeout: 5s, host: localhost, port: 502}\nqueries:\n  - table: pv\n    cron: '0 * * * * * *'\n    preset: pv_strings\n  - table: other\n    cron: '0 * * * * * *'\n    values: [{name: soc, address: 37004, scale: 0.1, type: U16}, {name: pv1_voltage, address: 32016, scale: 0.1, type: I16}]\n  - table: second\n    unit_id: 2\n    cron: '0 * * * * * *'\n    values: [{name: soc, address: 37004, scale: 0.1, type: U16}]";
+    let mut cfg: Config = serde_yaml::from_str(yaml).unwrap();
+    cfg.expand_presets();
+    cfg.validate().unwrap();
+    assert_eq!(cfg.queries[0].values.len(), 48);
+
+    let capabilities = Capabilities::from_model("SUN2000-20KTL-M0", 20000, 6, 2);
+    let dropped = cfg.retain_supported(&capabilities, 1);
+    let names = cfg.queries[0]
+        .values
+        .iter()
+        .map(|v| v.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names.len(), 12);
+    assert_eq!(names[..2], ["pv1_voltage", "pv1_current"]);
+    assert_eq!(names[11], "pv6_current");
+    let current = cfg.queries[0].values[11].register();
+    assert_eq!(current.address, 32027);
+    assert_eq!(current.gain, Gain::div(100));
+    // configured columns are checked as well
+    assert_eq!(cfg.queries[1].values.len(), 1);
+    assert_eq!(dropped.len(), 37);
+    assert_eq!(dropped[0], "pv.pv7_voltage");
+    assert_eq!(dropped[36], "other.soc");
+
+    let capabilities = Capabilities::from_model("SUN2000-20KTL-M0", 20000, 0, 0);
+    cfg.retain_supported(&capabilities, 1);
+    // the table of the other device is left to its own capabilities
+    assert_eq!(cfg.queries.len(), 1);
+    assert_eq!(cfg.queries[0].table, "second");
+    assert_eq!(cfg.queries[0].values.len(), 1);
+}
+
+#[test]
+fn parse_missed_slots() {
+    let parse = |s: &str| -> Option<MissedSlots> {
+        serde_yaml::with::singleton_map::deserialize(serde_yaml::Deserializer::from_str(s)).ok()
+    };
+    assert_eq!(parse("skip"), Some(MissedSlots::Skip));
+    assert_eq!(parse("catch_up: 5"), Some(MissedSlots::CatchUp(5)));
+    assert_eq!(parse("catch_up"), None);
+}
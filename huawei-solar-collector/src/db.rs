@@ -0,0 +1,553 @@
+//! Database schema of the configured tables and the statements storing
+//! samples in them.
+//!
+//! Tables are created on start and columns of registers added to the
+//! configuration since are added to them. Columns of registers removed from
+//! the configuration are kept with their data. The registers behind the
+//! columns are described in [METADATA_TABLE].
+//!
+//! Samples are stored through [Database], which reconnects once the
+//! connection is lost and spools the samples taken in the meantime.
+
+use std::{
+    collections::HashMap,
+    io,
+    time::{Duration, Instant},
+};
+
+use huawei_solar::registers::RegValue;
+use postgres::{types::ToSql, Client, Statement};
+
+use crate::{
+    config::{Decode, RegisterConfig, TableConfig},
+    spool::{Sample, Spool},
+};
+
+/// Table describing the register each column was read from
+pub const METADATA_TABLE: &str = "register_metadata";
+
+/// Time between attempts to reconnect to the database
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Value of a column, bound as parameter of the insert statement
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    Real(f32),
+    Text(String),
+    IntArray(Vec<i32>),
+}
+
+impl SqlValue {
+    /// Convert a value read for the column `col`
+    pub fn new(col: &RegisterConfig, val: &RegValue) -> Result<SqlValue, String> {
+        let context = |err| format!("{}: {}", col.name, err);
+        Ok(match col.decode {
+            None => SqlValue::Real(val.to_float().map_err(context)? as f32),
+            Some(Decode::DeviceStatus) => {
+                SqlValue::Text(val.to_device_status().map_err(context)?.name().into_owned())
+            }
+            Some(Decode::Alarms) => SqlValue::IntArray(
+                val.to_alarms()
+                    .map_err(context)?
+                    .active()
+                    .map(|alarm| alarm.id.into())
+                    .collect(),
+            ),
+        })
+    }
+
+    fn as_sql(&self) -> &(dyn ToSql + Sync) {
+        match self {
+            SqlValue::Real(val) => val,
+            SqlValue::Text(val) => val,
+            SqlValue::IntArray(val) => val,
+        }
+    }
+}
+
+/// `udt_name` of a column type in `information_schema.columns`
+fn udt_name(sql_type: &str) -> &str {
+    match sql_type {
+        "real" => "float4",
+        "integer[]" => "_int4",
+        other => other,
+    }
+}
+
+fn create_table_sql(table: &TableConfig) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (time timestamptz NOT NULL)",
+        table.table
+    )
+}
+
+/// Statements adding the columns of `table` missing from `existing`, the
+/// column names and `udt_name`s of the table in the database.
+///
+/// Fails for columns whose type in the database differs from the configured
+/// one, they need to be migrated by hand.
+fn plan_migration(
+    table: &TableConfig,
+    existing: &HashMap<String, String>,
+) -> Result<Vec<String>, String> {
+    let mut statements = Vec::new();
+    for col in &table.values {
+        // unquoted identifiers are folded to lower case
+        match existing.get(&col.name.to_lowercase()) {
+            Some(udt) if udt != udt_name(col.sql_type()) => {
+                return Err(format!(
+                    "column {}.{} is of type {} in the database but configured as {}",
+                    table.table,
+                    col.name,
+                    udt,
+                    col.sql_type()
+                ))
+            }
+            Some(_) => {}
+            None => statements.push(format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table.table,
+                col.name,
+                col.sql_type()
+            )),
+        }
+    }
+    Ok(statements)
+}
+
+fn insert_sql(table: &str, columns: &[&RegisterConfig]) -> String {
+    format!(
+        "INSERT INTO {} (time{}) VALUES ($1{})",
+        table,
+        columns
+            .iter()
+            .map(|col| format!(", {}", col.name))
+            .collect::<String>(),
+        (2..columns.len() + 2)
+            .map(|i| format!(", ${}", i))
+            .collect::<String>()
+    )
+}
+
+fn decode_name(decode: Option<Decode>) -> Option<&'static str> {
+    decode.map(|decode| match decode {
+        Decode::DeviceStatus => "device_status",
+        Decode::Alarms => "alarms",
+    })
+}
+
+/// Create the tables of `tables` and add missing columns, returning the
+/// changes made as `table.column`
+pub fn migrate(
+    client: &mut Client,
+    tables: &[TableConfig],
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut transaction = client.transaction()?;
+    transaction.batch_execute(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (
+            table_name text NOT NULL,
+            column_name text NOT NULL,
+            address integer NOT NULL,
+            quantity smallint NOT NULL,
+            type text NOT NULL,
+            scale double precision NOT NULL,
+            unit text,
+            decode text,
+            updated timestamptz NOT NULL DEFAULT now(),
+            PRIMARY KEY (table_name, column_name)
+        )",
+        METADATA_TABLE
+    ))?;
+    let columns = transaction.prepare(
+        "SELECT column_name::text, udt_name::text FROM information_schema.columns
+         WHERE table_schema = current_schema() AND table_name = $1",
+    )?;
+    let upsert = transaction.prepare(&format!(
+        "INSERT INTO {0} (table_name, column_name, address, quantity, type, scale, unit, decode)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+         ON CONFLICT (table_name, column_name) DO UPDATE SET
+            address = EXCLUDED.address, quantity = EXCLUDED.quantity, type = EXCLUDED.type,
+            scale = EXCLUDED.scale, unit = EXCLUDED.unit, decode = EXCLUDED.decode,
+            updated = now()
+         WHERE ({0}.address, {0}.quantity, {0}.type, {0}.scale, {0}.unit, {0}.decode)
+            IS DISTINCT FROM (EXCLUDED.address, EXCLUDED.quantity, EXCLUDED.type,
+                              EXCLUDED.scale, EXCLUDED.unit, EXCLUDED.decode)",
+        METADATA_TABLE
+    ))?;
+
+    let mut changes = Vec::new();
+    for table in tables {
+        transaction.batch_execute(&create_table_sql(table))?;
+        let existing = transaction
+            .query(&columns, &[&table.table])?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect::<HashMap<String, String>>();
+        for statement in plan_migration(table, &existing)? {
+            transaction.batch_execute(&statement)?;
+        }
+        for col in &table.values {
+            let gain = col.scale;
+            let updated = transaction.execute(
+                &upsert,
+                &[
+                    &table.table,
+                    &col.name,
+                    &i32::from(col.address),
+                    &i16::from(col.quantity()),
+                    &format!("{:?}", col.typ),
+                    &(gain.multiplier as f64 / gain.divisor as f64),
+                    &col.unit,
+                    &decode_name(col.decode),
+                ],
+            )?;
+            if !existing.contains_key(&col.name.to_lowercase()) {
+                changes.push(format!("added {}.{}", table.table, col.name));
+            } else if updated > 0 {
+                changes.push(format!("updated {}.{}", table.table, col.name));
+            }
+        }
+    }
+    transaction.commit()?;
+    Ok(changes)
+}
+
+/// Insert statements, one per table, prepared on each connection
+pub struct Inserts {
+    /// Columns and insert statement of each table
+    tables: HashMap<String, (Vec<String>, String)>,
+    statements: HashMap<String, Statement>,
+}
+
+impl Inserts {
+    /// Insert statements for `tables`, `(name, columns)`
+    pub fn new<'a, I>(tables: I) -> Inserts
+    where
+        I: IntoIterator<Item = (&'a str, Vec<&'a RegisterConfig>)>,
+    {
+        Inserts {
+            tables: tables
+                .into_iter()
+                .map(|(name, columns)| {
+                    let sql = insert_sql(name, &columns);
+                    let columns = columns.iter().map(|col| col.name.clone()).collect();
+                    (name.to_string(), (columns, sql))
+                })
+                .collect(),
+            statements: HashMap::new(),
+        }
+    }
+
+    /// Prepare the statements on `client`
+    pub fn prepare(&mut self, client: &mut Client) -> Result<(), postgres::Error> {
+        self.statements.clear();
+        for (name, (_, sql)) in &self.tables {
+            self.statements.insert(name.clone(), client.prepare(sql)?);
+        }
+        Ok(())
+    }
+
+    /// Check that `sample` has the configured columns of its table, in
+    /// order. Samples spooled before the configuration changed may not.
+    pub fn check(&self, sample: &Sample) -> Result<(), String> {
+        let Some((columns, _)) = self.tables.get(&sample.table) else {
+            return Err(String::from("table not configured"));
+        };
+        let matches = columns.len() == sample.values.len()
+            && columns
+                .iter()
+                .zip(&sample.values)
+                .all(|(col, (name, _))| col.eq_ignore_ascii_case(name));
+        if !matches {
+            return Err(format!(
+                "columns {} differ from the configured {}",
+                sample
+                    .values
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                columns.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// Insert `sample`, which has to pass [Inserts::check], the statements
+    /// have to be [prepared](Inserts::prepare) on `client`
+    pub fn insert(&self, client: &mut Client, sample: &Sample) -> Result<u64, postgres::Error> {
+        let params = std::iter::once(&sample.time as &(dyn ToSql + Sync))
+            .chain(sample.values.iter().map(|(_, val)| val.as_sql()))
+            .collect::<Vec<_>>();
+        client.execute(&self.statements[&sample.table], &params)
+    }
+}
+
+/// Whether `err` is caused by the connection rather than the row inserted,
+/// so the row can be stored later
+fn is_transient(err: &postgres::Error) -> bool {
+    match err.as_db_error() {
+        // connection exception, insufficient resources, operator intervention
+        Some(db) => matches!(&db.code().code()[..2], "08" | "53" | "57"),
+        // rather than e.g. a parameter the statement doesn't take
+        None => {
+            err.is_closed()
+                || std::error::Error::source(err).is_some_and(|source| source.is::<io::Error>())
+        }
+    }
+}
+
+/// Connection storing samples, reconnected when lost. Samples taken in the
+/// meantime are kept in the [Spool] and stored first once reconnected.
+pub struct Database {
+    connect: Box<dyn FnMut() -> Result<Client, Box<dyn std::error::Error>>>,
+    client: Option<Client>,
+    /// Earliest time to reconnect
+    reconnect_at: Instant,
+    inserts: Inserts,
+    spool: Option<Spool>,
+}
+
+impl Database {
+    /// Store samples over `client`, replaced by one from `connect` once lost
+    pub fn new<F>(
+        mut client: Client,
+        connect: F,
+        mut inserts: Inserts,
+        spool: Option<Spool>,
+    ) -> Result<Database, postgres::Error>
+    where
+        F: FnMut() -> Result<Client, Box<dyn std::error::Error>> + 'static,
+    {
+        inserts.prepare(&mut client)?;
+        Ok(Database {
+            connect: Box::new(connect),
+            client: Some(client),
+            reconnect_at: Instant::now(),
+            inserts,
+            spool,
+        })
+    }
+
+    /// Store `sample` after the spooled ones, spooling it if the database
+    /// is unreachable. Fails if the database rejected it or the spool could
+    /// not take it.
+    pub fn store(&mut self, sample: Sample) -> Result<(), String> {
+        self.inserts.check(&sample)?;
+        self.replay();
+        if self.spool.as_ref().is_none_or(Spool::is_empty) && self.connected() {
+            let client = self.client.as_mut().unwrap();
+            match self.inserts.insert(client, &sample) {
+                Ok(_) => return Ok(()),
+                Err(err) if !is_transient(&err) => return Err(err.to_string()),
+                Err(err) => self.disconnected(&err),
+            }
+        }
+        let Some(spool) = &mut self.spool else {
+            return Err(String::from("database unreachable"));
+        };
+        match spool.push(&sample) {
+            Ok(true) => eprintln!("Spool full, dropped the oldest sample"),
+            Ok(false) => {}
+            Err(err) => return Err(format!("failed to spool: {}", err)),
+        }
+        if spool.len() == 1 {
+            println!("Spooling samples to {}", spool.path().display());
+        }
+        Ok(())
+    }
+
+    /// Store the spooled samples, oldest first, until the spool is empty or
+    /// the database is unreachable again
+    pub fn replay(&mut self) {
+        if self.spool.as_ref().is_none_or(Spool::is_empty) || !self.connected() {
+            return;
+        }
+        let (Some(spool), Some(client)) = (&mut self.spool, &mut self.client) else {
+            return;
+        };
+        println!("Storing {} spooled samples", spool.len());
+        let mut stored = 0;
+        let lost = loop {
+            let sample = match spool.front() {
+                Ok(Some(sample)) => sample,
+                Ok(None) => break None,
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                    eprintln!("Dropping spooled sample: {}", err);
+                    if let Err(err) = spool.pop() {
+                        eprintln!("Failed to read spool: {}", err);
+                        break None;
+                    }
+                    continue;
+                }
+                Err(err) => {
+                    eprintln!("Failed to read spool: {}", err);
+                    break None;
+                }
+            };
+            // the configuration may have changed since it was spooled
+            if let Err(err) = self.inserts.check(&sample) {
+                eprintln!("Dropping spooled {} sample: {}", sample.table, err);
+            } else {
+                match self.inserts.insert(client, &sample) {
+                    Ok(_) => stored += 1,
+                    Err(err) if !is_transient(&err) => {
+                        eprintln!("Failed to store spooled {}: {}", sample.table, err);
+                    }
+                    Err(err) => break Some(err),
+                }
+            }
+            if let Err(err) = spool.pop() {
+                eprintln!("Failed to read spool: {}", err);
+                break None;
+            }
+        };
+        println!("Stored {} spooled samples, {} left", stored, spool.len());
+        if let Some(err) = lost {
+            self.disconnected(&err);
+        }
+    }
+
+    /// Whether there is a connection, reconnecting if it was lost
+    fn connected(&mut self) -> bool {
+        if self
+            .client
+            .as_ref()
+            .is_some_and(|client| !client.is_closed())
+        {
+            return true;
+        }
+        if self.client.take().is_some() {
+            println!("Database connection lost");
+        }
+        if Instant::now() < self.reconnect_at {
+            return false;
+        }
+        let connected = (self.connect)().and_then(|mut client| {
+            self.inserts.prepare(&mut client)?;
+            Ok(client)
+        });
+        match connected {
+            Ok(client) => {
+                println!("Reconnected to database");
+                self.client = Some(client);
+                true
+            }
+            Err(err) => {
+                eprintln!("Failed to reconnect to database: {}", err);
+                self.reconnect_at = Instant::now() + RECONNECT_INTERVAL;
+                false
+            }
+        }
+    }
+
+    fn disconnected(&mut self, err: &postgres::Error) {
+        eprintln!("Database connection lost: {}", err);
+        self.client = None;
+        self.reconnect_at = Instant::now() + RECONNECT_INTERVAL;
+    }
+}
+
+#[cfg(test)]
+fn table(yaml: &str) -> TableConfig {
+    serde_yaml::from_str(yaml).unwrap()
+}
+
+#[test]
+fn insert_statement() {
+    let table = table(
+        "{table: pv, cron: '0 * * * * * *', values: [{name: pv1_voltage, address: 32016, scale: 0.1, type: I16}, {name: state, address: 32089, scale: 1, type: U16, decode: device_status}]}",
+    );
+    assert_eq!(
+        insert_sql("pv", &table.values.iter().collect::<Vec<_>>()),
+        "INSERT INTO pv (time, pv1_voltage, state) VALUES ($1, $2, $3)"
+    );
+    assert_eq!(insert_sql("pv", &[]), "INSERT INTO pv (time) VALUES ($1)");
+}
+
+#[test]
+fn migration_plan() {
+    let table = table(
+        "{table: pv, cron: '0 * * * * * *', values: [{name: PV1_voltage, address: 32016, scale: 0.1, type: I16}, {name: state, address: 32089, scale: 1, type: U16, decode: device_status}, {name: alarms, address: 32008, scale: 1, type: BF, quantity: 3, decode: alarms}]}",
+    );
+    let mut existing = HashMap::from([(String::from("time"), String::from("timestamptz"))]);
+    assert_eq!(
+        plan_migration(&table, &existing).unwrap(),
+        [
+            "ALTER TABLE pv ADD COLUMN PV1_voltage real",
+            "ALTER TABLE pv ADD COLUMN state text",
+            "ALTER TABLE pv ADD COLUMN alarms integer[]",
+        ]
+    );
+    existing.insert(String::from("pv1_voltage"), String::from("float4"));
+    existing.insert(String::from("alarms"), String::from("_int4"));
+    assert_eq!(
+        plan_migration(&table, &existing).unwrap(),
+        ["ALTER TABLE pv ADD COLUMN state text"]
+    );
+    existing.insert(String::from("state"), String::from("float4"));
+    assert_eq!(
+        plan_migration(&table, &existing).unwrap_err(),
+        "column pv.state is of type float4 in the database but configured as text"
+    );
+}
+
+#[test]
+fn sql_values() {
+    use huawei_solar::registers::{Value, ALARMS, DEVICE_STATUS, PV1_VOLTAGE};
+    let table = table(
+        "{table: pv, cron: '0 * * * * * *', values: [{name: pv1_voltage, address: 32016, scale: 0.1, type: I16}, {name: state, address: 32089, scale: 1, type: U16, decode: device_status}, {name: alarms, address: 32008, scale: 1, type: BF, quantity: 3, decode: alarms}]}",
+    );
+    let value = |col: usize, reg, words: &[u16]| {
+        let val = RegValue {
+            reg,
+            val: reg.typ.convert(words).unwrap(),
+        };
+        SqlValue::new(&table.values[col], &val)
+    };
+    assert_eq!(value(0, &PV1_VOLTAGE, &[3995]), Ok(SqlValue::Real(399.5)));
+    assert_eq!(
+        value(1, &DEVICE_STATUS, &[0x0200]),
+        Ok(SqlValue::Text(String::from("on_grid")))
+    );
+    assert_eq!(
+        value(2, &ALARMS, &[0x0001, 0x0000, 0x0000]),
+        Ok(SqlValue::IntArray(vec![2001]))
+    );
+    let text = RegValue {
+        reg: &PV1_VOLTAGE,
+        val: Value::STR(String::from("x")),
+    };
+    assert!(SqlValue::new(&table.values[0], &text).is_err());
+}
+
+#[test]
+fn check_samples() {
+    let table = table(
+        "{table: pv, cron: '0 * * * * * *', values: [{name: PV1_voltage, address: 32016, scale: 0.1, type: I16}, {name: state, address: 32089, scale: 1, type: U16, decode: device_status}]}",
+    );
+    let inserts = Inserts::new([("pv", table.values.iter().collect())]);
+    let sample = |table: &str, columns: &[&str]| Sample {
+        table: table.to_string(),
+        time: chrono::DateTime::parse_from_rfc3339("2024-05-01T12:00:00+02:00").unwrap(),
+        values: columns
+            .iter()
+            .map(|col| (col.to_string(), SqlValue::Real(1.0)))
+            .collect(),
+    };
+    assert_eq!(
+        inserts.check(&sample("pv", &["pv1_voltage", "state"])),
+        Ok(())
+    );
+    assert_eq!(
+        inserts.check(&sample("pv", &["state", "pv1_voltage"])),
+        Err(String::from(
+            "columns state, pv1_voltage differ from the configured PV1_voltage, state"
+        ))
+    );
+    assert!(inserts.check(&sample("pv", &["pv1_voltage"])).is_err());
+    assert_eq!(
+        inserts.check(&sample("general", &[])),
+        Err(String::from("table not configured"))
+    );
+}
@@ -1,302 +1,200 @@
-// use std::{env, fs};
-// use std::collections::HashMap;
-// use std::str::FromStr;
-// use std::string::String;
-// use std::thread::sleep;
-// use std::time::Duration;
-
-// use chrono::{DateTime, Local, Utc};
-// use cron::Schedule;
-// use modbus::{Client, tcp};
-// use postgres::NoTls;
-// // use postgres::types::ToSql;
-// use serde::de::{self, Deserializer};
-// use serde::Deserialize;
-
-// #[derive(Deserialize, Debug)]
-// struct Config {
-//     #[serde(deserialize_with = "duration")]
-//     db_timeout: Duration,
-//     modbus: ModbusConfig,
-//     queries: Vec<Table>,
-// }
-
-// struct InverterData {
-
-// }
-
-// #[derive(Deserialize, Debug)]
-// struct ModbusConfig {
-//     #[serde(deserialize_with = "duration")]
-//     read_timeout: Duration,
-//     #[serde(deserialize_with = "duration")]
-//     connect_timeout: Duration,
-//     #[serde(deserialize_with = "duration")]
-//     write_timeout: Duration,
-//     host: String,
-//     port: u16,
-// }
-
-// #[derive(Deserialize, Debug)]
-// struct Register {
-//     name: String,
-//     address: u16,
-//     scale: f32,
-//     // unit: String,
-//     #[serde(rename = "type")]
-//     data_type: DataType,
-// }
-
-// #[derive(Deserialize, Debug)]
-// enum DataType {
-//     I16,
-//     I32,
-//     U16,
-//     U32,
-// }
-
-// impl DataType {
-//     fn size(&self) -> u16 {
-//         match self {
-//             DataType::I16 | DataType::U16 => 1,
-//             DataType::I32 | DataType::U32 => 2,
-//         }
-//     }
-
-//     /// This function converts the output of the modbus client
-//     /// to the correct value in f32 format
-//     fn convert(&self, data: Vec<u16>, scale: f32) -> f32 {
-//         scale * match self {
-//             DataType::I16 => data[0] as i16 as f32,
-//             DataType::I32 => ((data[0] as i32) << 0x10 | data[1] as i32) as f32,
-//             DataType::U16 => data[0] as f32,
-//             DataType::U32 => ((data[0] as u32) << 0x10 | data[1] as u32) as f32,
-//         }
-//     }
-// }
-
-// #[test]
-// fn data_type_convert_test() {
-//     assert_eq!(DataType::U16.convert(vec![u16::MAX], 1.0), u16::MAX as f32);
-//     assert_eq!(DataType::I16.convert(vec![u16::MAX], 1.0), -1.0);
-//     assert_eq!(DataType::U32.convert(vec![0u16,8000u16], 1.0), 8000.0);
-//     assert_eq!(DataType::U32.convert(vec![0u16,8000u16], 0.001), 8.0);
-//     assert_eq!(DataType::U32.convert(vec![32, 11], 10.0), (((32 << 16) + 11)*10) as f32);
-//     assert_eq!(DataType::I32.convert(vec![u16::MAX, u16::MAX - 53], 1.0), -54.0);
-//     assert_eq!(DataType::I32.convert(vec![u16::MAX, u16::MAX], 1.0), -1.0);
-// }
-
-// /// [Deserialize] a [Schedule] from a [String]
-// fn schedule<'de, D>(deserializer: D) -> Result<Schedule, D::Error>
-//     where D: Deserializer<'de>
-// {
-//     let s = String::deserialize(deserializer)?;
-//     Schedule::from_str(&s).map_err(de::Error::custom)
-// }
-
-// /// [Deserialize] a [Duration] from a [String] using the [parse_duration] crate
-// fn duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
-//     where D: Deserializer<'de>
-// {
-//     let s = String::deserialize(deserializer)?;
-//     parse_duration::parse(&s).map_err(de::Error::custom)
-// }
-
-// fn main() -> Result<(), Box<dyn std::error::Error>> {
-//     let cfg: Config = serde_yaml::from_str(&fs::read_to_string("./resources/config.yaml")?)?;
-
-//     let mut mb_client = tcp::Transport::new_with_cfg(&cfg.modbus.host, modbus::Config {
-//         modbus_uid: 0,
-//         tcp_port: cfg.modbus.port,
-//         tcp_read_timeout: Some(cfg.modbus.read_timeout),
-//         tcp_write_timeout: Some(cfg.modbus.write_timeout),
-//         tcp_connect_timeout: Some(cfg.modbus.connect_timeout),
-//     })?;
-
-//     let mut db_client = connect_database(5, cfg.db_timeout)?;
-
-//     let mut create_queries = Vec::new();
-//     for table in &cfg.queries {
-//         create_queries.push(format!("CREATE TABLE IF NOT EXISTS {} ({})",
-//             &table.table,
-//             &table.values.iter().map(|r| format!("{} real", r.name)).fold(String::from("time timestamptz NOT NULL"), |accu, elem| accu + "," + &elem)
-//         ));
-//     }
-//     db_client.batch_execute(&create_queries.join(";"))?;
-
-//     let mut last_read = DateTime::<Utc>::MIN_UTC;
-//     'out: loop {
-//         // find next query job and time of it
-//         let mut next_job = DateTime::<Utc>::MAX_UTC;
-//         let mut tables = vec![];
-//         for table in &cfg.queries {
-//             let table_time = *table.cron.upcoming(Utc).next().get_or_insert(DateTime::<Utc>::MAX_UTC);
-//             if next_job > table_time {
-//                 next_job = table_time;
-//                 tables.clear();
-//             }
-//             if table_time == next_job {
-//                 tables.push(table);
-//             }
-//         }
-//         // sleep until next invoke
-//         sleep((next_job - Utc::now()).to_std().unwrap_or(Duration::ZERO));
-
-//         // query modbus slave
-//         let mut regs = vec![];
-//         tables.iter_mut().for_each(|q| regs.extend(&q.values));
-//         regs.sort_by_key(|a| a.address);
-
-//         let mut values = HashMap::new();
-//         // group registers to single request
-//         while !regs.is_empty() {
-//             let reg = regs.remove(0);
-//             let start_address = reg.address;
-//             let mut end_address = start_address + reg.data_type.size();
-
-//             while !regs.is_empty() {
-//                 let reg = regs[0];
-//                 if reg.address == end_address {
-//                     end_address += reg.data_type.size();
-//                     regs.remove(0);
-//                 } else {
-//                     break;
-//                 }
-//             }
-
-//             // fill with debug values
-//             // for i in start_address..end_address {
-//             //     values.insert(i, rand::random::<u16>());
-//             // }
-//             // request whole group
-//             println!("since last read: {}, sleeping: {:?}", Utc::now() - last_read, (chrono::Duration::from_std(Duration::from_millis(500))? - (Utc::now() - last_read)).to_std().unwrap_or(Duration::ZERO));
-//             sleep((chrono::Duration::from_std(Duration::from_millis(500))? - (Utc::now() - last_read)).to_std().unwrap_or(Duration::ZERO));
-//             last_read = Utc::now();
-//             match mb_client.read_holding_registers(start_address, end_address - start_address) {
-//                 Ok(res) =>{
-//                     println!("Read regs({}..{}): {:?}", start_address, end_address-start_address, res);
-//                     for i in 0..res.len() { values.insert(start_address + i as u16, res[i]); }
-//                 }
-//                 Err(err) => {
-//                     eprintln!("Modbus error: {}", err.to_string());
-//                     continue 'out;
-//                 }
-//             }
-//         }
-
-//         // convert and write to db
-//         /* type.convert((r.address..r.address + r.data_type.size()).map(|addr| *values.get(&addr).unwrap()).collect(), r.scale)*/
-//         for table in tables {
-//             // params.extend(table.values.iter().map(|r| r.data_type.convert((r.address..r.address+r.data_type.size()).map(|addr| *values.get(&addr).unwrap()), r.scale) /*as (dyn ToSql + Sync)*/));
-//             match db_client.execute(&format!("INSERT INTO {} ({}) VALUES ({})",
-//                 &table.table,
-//                 table.values.iter().map(|elem| &elem.name).fold(String::from("time"), |accu, ele| accu + "," + ele),
-//                 table.values.iter().map(|r| r.data_type.convert((r.address..r.address+r.data_type.size()).map(|addr| *values.get(&addr).unwrap()).collect(), r.scale))
-//                     .fold(format!("'{}'", next_job.with_timezone(&Local).to_string()), |accu, ele| accu + "," + &ele.to_string())
-//                 // (2..table.values.len() + 2).fold(String::from("$1"), |accu, ele| accu + ", $" + &ele.to_string())
-//             ),
-//                 // &vec![&5f32 as &(dyn ToSql + Sync)][..]
-//                 // &table.values.iter().map(|r| &(values.get(&r.address).unwrap_or(&0u16) as &f32) as &(dyn ToSql + Sync)).collect::<Vec<&(dyn ToSql + Sync)>>()[..],
-//                 &[]
-//             ) {
-//                 Ok(_) => {}
-//                 Err(err) => eprintln!("Database error: {}", err.to_string())
-//             }
-//         }
-//     }
-// }
-
-use std::{env, thread::sleep, time::Duration};
+mod config;
+mod db;
+mod schedule;
+mod spool;
+mod timescale;
+
+use std::{collections::HashMap, env, fs::File, thread::sleep, time::Duration};
 
 use chrono::{DateTime, Local, TimeZone};
-use huawei_solar::{registers::*, Inverter};
+use config::{Config, Decode, ModbusConfig, RegisterConfig};
+use cron::Schedule;
+use db::{Database, SqlValue};
+use huawei_solar::{device::DeviceInfo, registers::*, Inverter};
 use postgres::NoTls;
+use schedule::{merge_jobs, next_slot};
+use spool::{Sample, Spool};
+
+const DEFAULT_CONFIG: &str = "./resources/config.yaml";
 
 #[derive(Debug)]
 struct DbTable<'a> {
-    name: String,
-    values: Vec<(&'a str, Register<'a>)>,
-    alignment: Duration,
-    next_read: DateTime<Local>,
+    name: &'a str,
+    /// Modbus unit ID of the device the table is read from
+    unit_id: u8,
+    values: Vec<(&'a RegisterConfig, Register<'a>)>,
+    schedule: &'a Schedule,
+    /// `None` once the schedule has no further slots
+    next_read: Option<DateTime<Local>>,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // config path is taken from the first argument or CONFIG_FILE
+    let cfg_path = env::args()
+        .nth(1)
+        .or_else(|| env::var("CONFIG_FILE").ok())
+        .unwrap_or_else(|| String::from(DEFAULT_CONFIG));
+    println!("Loading config from {}", cfg_path);
+    let mut cfg = Config::load(&cfg_path)?;
+
     println!("Connecting to Inverter over TCP");
-    let mut inverter = connect_inverter()?;
+    let mut inverter = connect_inverter(&cfg.modbus)?;
     println!("Connected!");
 
-    let status = get_status(&mut inverter)?;
+    let info = inverter.identify()?;
+    get_status(&mut inverter, &info)?;
+    // registers the model lacks would fail the whole batch they are read in,
+    // only the inverter is identified
+    for column in cfg.retain_supported(&info.capabilities, inverter.unit_id()) {
+        println!("Skipping {}, not implemented by {}", column, info.model);
+    }
 
     println!();
     println!("Connecting to Timescale database");
-    let mut db_client = connect_database(12, Duration::from_secs(5))?;
+    let mut db_client = connect_database(12, cfg.db_timeout, cfg.db_retry_interval)?;
     println!("Connected!");
 
-    let mut tables = create_tables(&status);
+    let mut tables = create_tables(&cfg, inverter.unit_id());
+    // one handle per device, all sharing the inverter's connection
+    let planner = cfg.modbus.planner();
+    let mut devices = HashMap::new();
+    for table in &tables {
+        devices
+            .entry(table.unit_id)
+            .or_insert_with(|| inverter.connection().device(table.unit_id))
+            .set_planner(planner.clone());
+    }
 
-    println!("Creating DB tables");
-    let create_queries = tables
-        .iter()
-        .map(|table| {
-            format!(
-                "CREATE TABLE IF NOT EXISTS {} ({})",
-                &table.name,
-                &table
-                    .values
-                    .iter()
-                    .map(|r| format!("{} real", r.0))
-                    .fold(String::from("time timestamptz NOT NULL"), |accu, elem| accu
-                        + ","
-                        + &elem)
-            )
-        })
-        .collect::<Vec<String>>();
-    db_client.batch_execute(&create_queries.join(";"))?;
-    println!("Creation done");
+    println!("Migrating DB tables");
+    for change in db::migrate(&mut db_client, &cfg.queries)? {
+        println!("\t{}", change);
+    }
+    if let Some(timescale) = &cfg.timescale {
+        for change in timescale::setup(&mut db_client, timescale, &cfg.queries)? {
+            println!("\t{}", change);
+        }
+    }
+    let inserts = db::Inserts::new(
+        tables
+            .iter()
+            .map(|table| (table.name, table.values.iter().map(|v| v.0).collect())),
+    );
+    println!("Migration done");
+
+    let spool = match &cfg.spool {
+        Some(spool) => {
+            let spool = Spool::open(&spool.path, spool.max_samples)?;
+            println!("{} samples spooled", spool.len());
+            Some(spool)
+        }
+        None => None,
+    };
+    let (db_timeout, db_retry_interval) = (cfg.db_timeout, cfg.db_retry_interval);
+    let mut database = Database::new(
+        db_client,
+        move || connect_database(0, db_timeout, db_retry_interval),
+        inserts,
+        spool,
+    )?;
+    database.replay();
+
+    // last alarm state per alarm column, to log transitions
+    let mut alarm_state: HashMap<(&str, &str), Alarms> = HashMap::new();
 
     println!("Collecting Data");
-    loop {
+    while let Some(next_job) = tables.iter().filter_map(|t| t.next_read).min() {
+        if let Ok(dur) = (next_job - Local::now()).to_std() {
+            println!("sleep time: {:?}", dur);
+            sleep(dur);
+        }
+
         let now = Local::now();
-        tables
-            .iter_mut()
-            .filter(|t| t.next_read < now)
-            .for_each(|t| {
-                // read table
-                let regs = t
-                    .values
-                    .iter()
-                    .map(|v| &v.1)
-                    .collect::<Vec<&Register<'static>>>();
-
-                if let Ok(values) = inverter.read_batch_retry(&regs, 10) {
-                    db_client.execute(
-                        &format!(
-                            "INSERT INTO {} ({}) VALUES ({})",
-                            t.name,
-                            t.values
-                                .iter()
-                                .fold(String::from("time"), |accu, ele| accu + "," + ele.0),
-                            values
-                                .iter()
-                                .fold(format!("'{}'", Local::now().to_string()), |accu, ele| accu
-                                    + ","
-                                    + &ele.to_float().unwrap().to_string())
-                        ),
-                        &[],
-                    ).ok();
+        let due = (0..tables.len())
+            .filter(|&i| tables[i].next_read.is_some_and(|next| next <= now))
+            .collect::<Vec<usize>>();
+        // tables of the same device due at the same time share Modbus
+        // requests where possible
+        let mut jobs = Vec::new();
+        for &unit_id in devices.keys() {
+            let due = due
+                .iter()
+                .copied()
+                .filter(|&i| tables[i].unit_id == unit_id)
+                .collect::<Vec<usize>>();
+            let regs = due
+                .iter()
+                .map(|&i| tables[i].values.iter().map(|v| &v.1).collect())
+                .collect::<Vec<Vec<&Register>>>();
+            for group in merge_jobs(&planner, &regs) {
+                jobs.push((
+                    unit_id,
+                    group.into_iter().map(|j| due[j]).collect::<Vec<_>>(),
+                ));
+            }
+        }
+        for (unit_id, group) in jobs {
+            let device = devices.get_mut(&unit_id).unwrap();
+            let group = group
+                .into_iter()
+                .map(|i| &tables[i])
+                .collect::<Vec<&DbTable>>();
+            let regs = group
+                .iter()
+                .flat_map(|t| t.values.iter().map(|v| &v.1))
+                .collect::<Vec<&Register>>();
+
+            let values = match device.read_batch_retry(&regs, 10) {
+                Ok(values) => values,
+                Err(err) => {
+                    eprintln!("Modbus error: {}", err);
+                    continue;
                 }
-                t.next_read = next_aligned_timepoint(t.alignment);
-                println!("next read: {}", t.next_read);
-            });
-
-        let next_req = tables.iter().map(|t| t.next_read).min();
-        if let None = next_req {
-            break;
+            };
+            // rather than the slot, which lies in the past when catching up
+            let read_at = Local::now().fixed_offset();
+            let mut offset = 0;
+            for table in group {
+                let table_values = &values[offset..offset + table.values.len()];
+                offset += table.values.len();
+                for ((col, _), val) in table.values.iter().zip(table_values) {
+                    if col.decode == Some(Decode::Alarms) {
+                        let alarms = match val.to_alarms() {
+                            Ok(alarms) => alarms,
+                            // the row fails to decode as well, reported below
+                            Err(_) => continue,
+                        };
+                        let previous = alarm_state
+                            .insert((table.name, &col.name), alarms)
+                            .unwrap_or_default();
+                        for alarm in alarms.raised_since(&previous) {
+                            println!("{}.{}: alarm raised: {}", table.name, col.name, alarm);
+                        }
+                        for alarm in alarms.cleared_since(&previous) {
+                            println!("{}.{}: alarm cleared: {}", table.name, col.name, alarm);
+                        }
+                    }
+                }
+                let result = decode_row(table, table_values).and_then(|values| {
+                    database.store(Sample {
+                        table: table.name.to_string(),
+                        time: read_at,
+                        values,
+                    })
+                });
+                if let Err(err) = result {
+                    eprintln!("Failed to store {}: {}", table.name, err);
+                }
+            }
         }
-        let sleep_time = (next_req.unwrap() - Local::now()).to_std();
-        if let Ok(dur) = sleep_time {
-            println!("sleep time: {:?}", dur);
-            sleep(dur);
+
+        let now = Local::now();
+        for i in due {
+            let table = &mut tables[i];
+            let slot = table.next_read.unwrap();
+            table.next_read = next_slot(table.schedule, &slot, &now, cfg.missed_slots);
+            if let Some(next_read) = table.next_read {
+                println!("next read of {}: {}", table.name, next_read);
+            }
         }
     }
 
@@ -307,9 +205,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 fn connect_database(
     retries: u8,
-    delay: Duration,
+    db_timeout: Duration,
+    retry_interval: Duration,
 ) -> Result<postgres::Client, Box<dyn std::error::Error>> {
-    let db_timeout = 10;
     match postgres::Client::connect(
         &format!(
             "host={} user={} password={} dbname={} connect_timeout={}",
@@ -317,15 +215,15 @@ fn connect_database(
             env::var("DB_USER")?,
             env::var("DB_PASS")?,
             env::var("DB_NAME")?,
-            db_timeout
+            db_timeout.as_secs().max(1)
         ),
         NoTls,
     ) {
         Ok(client) => Ok(client),
         Err(e) => {
             if retries > 0 {
-                sleep(delay);
-                connect_database(retries - 1, delay)
+                sleep(retry_interval);
+                connect_database(retries - 1, db_timeout, retry_interval)
             } else {
                 Err(Box::new(e))
             }
@@ -333,31 +231,44 @@ fn connect_database(
     }
 }
 
-fn connect_inverter() -> Result<Inverter, Box<dyn std::error::Error>> {
-    let ip = env::var("INV_ADDR")?;
-    let port = env::var("INV_PORT")?.parse::<u16>()?;
-    let mb_id = env::var("INV_MBID")?.parse::<u8>()?;
+/// Connect to the inverter configured in the `modbus` section,
+/// `INV_ADDR`, `INV_PORT` and `INV_MBID` override the configured values
+fn connect_inverter(cfg: &ModbusConfig) -> Result<Inverter, Box<dyn std::error::Error>> {
+    let ip = env::var("INV_ADDR").unwrap_or_else(|_| cfg.host.clone());
+    let port = match env::var("INV_PORT") {
+        Ok(port) => port.parse::<u16>()?,
+        Err(_) => cfg.port,
+    };
+    let mb_id = match env::var("INV_MBID") {
+        Ok(mb_id) => mb_id.parse::<u8>()?,
+        Err(_) => cfg.unit_id,
+    };
 
     println!("\tIP: {}", ip);
     println!("\tport: {}", port);
     println!("\tmodbus id: {}", mb_id);
 
-    Ok(huawei_solar::Inverter::connect_tcp(
+    let mut inverter = huawei_solar::Inverter::connect_tcp(
         Some(&ip),
         Some(port),
         Some(mb_id),
-        Some(Duration::from_millis(500)),
-        Some(Duration::from_millis(500)),
-        Some(Duration::from_millis(500)),
-    )?)
-}
-
-struct Status {
-    strings: u16,
+        Some(cfg.read_timeout),
+        Some(cfg.write_timeout),
+        Some(cfg.connect_timeout),
+    )?;
+    inverter.set_planner(cfg.planner());
+    if let Some(path) = &cfg.record {
+        println!("\trecording to: {}", path);
+        inverter.connection().record(File::create(path)?)?;
+    }
+    Ok(inverter)
 }
 
-fn get_status(inverter: &mut Inverter) -> Result<Status, Box<dyn std::error::Error>> {
-    let info_regs = vec![
+fn get_status(
+    inverter: &mut Inverter,
+    info: &DeviceInfo,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let info_regs: [&Register; 11] = [
         &MODEL,
         &SN,
         &PN,
@@ -371,9 +282,8 @@ fn get_status(inverter: &mut Inverter) -> Result<Status, Box<dyn std::error::Err
         &MAXIMUM_APPARENT_POWER_FROM_GRID,
     ];
     let info_vals = inverter.read_batch_retry(&info_regs, 10)?;
-    let strings = info_vals[4].to_u16()?;
 
-    let info_regs = vec![
+    let info_regs: [&Register; 5] = [
         &EFFICIENCY,
         &INTERNAL_TEMPERATURE,
         &DEVICE_STATUS,
@@ -385,12 +295,10 @@ fn get_status(inverter: &mut Inverter) -> Result<Status, Box<dyn std::error::Err
     println!("\nInverter");
     println!("\tModel: {} (ID: {})", &info_vals[0], &info_vals[3]);
     println!("\tSN/PN: {}/{}", &info_vals[1], &info_vals[2]);
-    if let Value::U16(val) = info_vals2[2].val {
-        println!(
-            "\tStatus: {}",
-            device_status_to_string(val).unwrap_or("invalid")
-        );
+    if let Ok(status) = info_vals2[2].to_device_status() {
+        println!("\tStatus: {}", status);
     }
+    println!("\tState: {}", inverter.read(&STATE_1)?);
     if let Value::U32(val) = info_vals2[3].val {
         println!(
             "\tStartup: {:?}",
@@ -411,6 +319,7 @@ fn get_status(inverter: &mut Inverter) -> Result<Status, Box<dyn std::error::Err
     }
     println!("\tStrings: {}", &info_vals[4],);
     println!("\tTrackers: {}", &info_vals[5],);
+    println!("\tCapabilities: {}", info.capabilities);
     println!("\tMaximum:");
     println!("\t\tactive power  : {}", &info_vals[7]);
     println!("\t\tapparent power: {}", &info_vals[8]);
@@ -418,7 +327,10 @@ fn get_status(inverter: &mut Inverter) -> Result<Status, Box<dyn std::error::Err
     println!("\t\tapparent power <- grid: {}", &info_vals[10]);
     println!();
 
-    let storage_info_regs = vec![
+    if !info.capabilities.battery {
+        return Ok(());
+    }
+    let storage_info_regs: [&Register; 4] = [
         &storage::RUNNING_STATUS,
         &storage::CHARGE_DISCHARGE_POWER,
         &storage::CHARGE_CAPACITY_DAY,
@@ -437,86 +349,123 @@ fn get_status(inverter: &mut Inverter) -> Result<Status, Box<dyn std::error::Err
     println!("\t\tcharge capacity    (today): {}", &storage_info_vals[2]);
     println!("\t\tdischarge capacity (today): {}", &storage_info_vals[3]);
 
-    Ok(Status { strings })
+    Ok(())
 }
 
-fn create_tables(status: &Status) -> Vec<DbTable<'static>> {
-    let mut tables = vec![
-        DbTable {
-            name: String::from("general"),
-            alignment: Duration::from_secs(30),
-            values: [
-                INPUT_POWER,
-                LINE_VOLTAGE_A_B,
-                LINE_VOLTAGE_B_C,
-                LINE_VOLTAGE_C_A,
-                PHASE_CURRENT_A,
-                PHASE_CURRENT_B,
-                PHASE_CURRENT_C,
-                PHASE_VOLTAGE_A,
-                PHASE_VOLTAGE_B,
-                PHASE_VOLTAGE_C,
-                ACTIVE_POWER,
-                REACTIVE_POWER,
-                // RATED_POWER,
-                ACC_ENERGY_YIELD,
-                ENERGY_YIELD_DAY,
-            ]
-            .into_iter()
-            .map(|reg| (reg.name, reg))
-            .collect(),
-            next_read: Local::now(),
-        },
-        DbTable {
-            name: String::from("monitoring"),
-            alignment: Duration::from_secs(30),
-            values: [EFFICIENCY, INTERNAL_TEMPERATURE]
-                .into_iter()
-                .map(|reg| (reg.name, reg))
-                .collect(),
-            next_read: Local::now(),
-        },
-        DbTable {
-            name: String::from("storage"),
-            alignment: Duration::from_secs(30),
-            values: [
-                storage::CHARGE_DISCHARGE_POWER,
-                storage::CHARGE_CAPACITY_DAY,
-                storage::DISCHARGE_CAPACITY_DAY,
-            ]
-            .into_iter()
-            .map(|reg| (reg.name, reg))
-            .collect(),
-            next_read: Local::now(),
-        },
-    ];
-
-    let plant_regs = [
-        (PV1_VOLTAGE, PV1_CURRENT),
-        (PV2_VOLTAGE, PV2_CURRENT),
-        (PV3_VOLTAGE, PV3_CURRENT),
-        (PV4_VOLTAGE, PV4_CURRENT),
-    ];
-
-    plant_regs
-        .into_iter()
-        .zip(0..status.strings)
-        .for_each(|((volt, curr), i)| {
-            tables.push(DbTable {
-                alignment: Duration::from_secs(5),
-                name: format!("plant_{}", i + 1),
-                values: vec![("voltage", volt), ("current", curr)],
-                next_read: Local::now(),
-            })
-        });
-
-    tables
+/// Columns of a row of `table` from the values read for its registers
+fn decode_row(table: &DbTable, values: &[RegValue]) -> Result<Vec<(String, SqlValue)>, String> {
+    table
+        .values
+        .iter()
+        .zip(values)
+        .map(|((col, _), val)| Ok((col.name.clone(), SqlValue::new(col, val)?)))
+        .collect()
 }
 
-fn next_aligned_timepoint(alignment: Duration) -> DateTime<Local> {
-    let now = Local::now();
-    let nanos = now.timestamp_nanos_opt().unwrap();
-    let align = alignment.as_nanos() as i64;
+fn create_tables(cfg: &Config, default_unit_id: u8) -> Vec<DbTable<'_>> {
+    cfg.queries
+        .iter()
+        .map(|table| DbTable {
+            name: &table.table,
+            unit_id: table.unit_id.unwrap_or(default_unit_id),
+            values: table
+                .values
+                .iter()
+                .map(|reg| (reg, reg.register()))
+                .collect(),
+            schedule: &table.cron,
+            next_read: table.cron.upcoming(Local).next(),
+        })
+        .collect()
+}
 
-    DateTime::from_timestamp_nanos(nanos - (nanos % align) + align).with_timezone(&Local)
+#[test]
+fn collect_from_simulator() {
+    use std::sync::{Arc, Mutex};
+
+    use huawei_solar_simulator::{
+        model::{Battery, Scenario, Simulation},
+        server::{Memory, Server},
+    };
+
+    let mut cfg = Config::load(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/resources/config.yaml"
+    ))
+    .unwrap();
+    // noon, 5 kW of the 7.2 kW DC charging the battery
+    let simulation = Simulation::new(Scenario {
+        start: 13.0 * 3600.0,
+        battery: Some(Battery {
+            capacity: 10.0,
+            soc: 50.0,
+            power: 5.0,
+        }),
+        ..Scenario::default()
+    });
+    let mut memory = Memory::default();
+    simulation.init(&mut memory).unwrap();
+    simulation.write(&mut memory).unwrap();
+    let server = Server::bind(
+        "127.0.0.1:0",
+        cfg.modbus.unit_id,
+        Arc::new(Mutex::new(memory)),
+    )
+    .unwrap();
+    cfg.modbus.host = String::from("127.0.0.1");
+    cfg.modbus.port = server.local_addr().unwrap().port();
+    server.spawn();
+
+    let mut inverter = connect_inverter(&cfg.modbus).unwrap();
+    let info = inverter.identify().unwrap();
+    get_status(&mut inverter, &info).unwrap();
+    let dropped = cfg.retain_supported(&info.capabilities, inverter.unit_id());
+    // the simulated inverter has 2 of the 24 PV strings
+    assert_eq!(dropped.len(), 44);
+    assert!(dropped.iter().all(|col| col.starts_with("pv_strings.")));
+
+    let tables = create_tables(&cfg, inverter.unit_id());
+    let inserts = db::Inserts::new(
+        tables
+            .iter()
+            .map(|table| (table.name, table.values.iter().map(|v| v.0).collect())),
+    );
+    let mut rows = HashMap::new();
+    for table in &tables {
+        let regs = table.values.iter().map(|v| &v.1).collect::<Vec<_>>();
+        let values = inverter.read_batch_retry(&regs, 0).unwrap();
+        let values = decode_row(table, &values).unwrap();
+        let sample = Sample {
+            table: table.name.to_string(),
+            time: Local::now().fixed_offset(),
+            values,
+        };
+        inserts.check(&sample).unwrap();
+        rows.insert(table.name, sample.values);
+    }
+    let value = |table: &str, col: &str| {
+        rows[table]
+            .iter()
+            .find(|(name, _)| name == col)
+            .unwrap_or_else(|| panic!("{}.{} not read", table, col))
+            .1
+            .clone()
+    };
+
+    assert_eq!(rows["pv_strings"].len(), 4);
+    assert_eq!(value("general_data", "input_power"), SqlValue::Real(7.2));
+    assert_eq!(value("ratings", "rated_power"), SqlValue::Real(10.0));
+    assert_eq!(
+        value("energy_storage", "charge_discharge_power"),
+        SqlValue::Real(5000.0)
+    );
+    assert_eq!(
+        value("monitoring", "device_status"),
+        SqlValue::Text(String::from("on_grid"))
+    );
+    assert_eq!(
+        value("monitoring", "alarms"),
+        SqlValue::IntArray(Vec::new())
+    );
+    assert!(!rows["meter"].is_empty());
 }
@@ -0,0 +1,189 @@
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, TimeZone};
+use cron::Schedule;
+use huawei_solar::{batch::Planner, registers::Register};
+use serde::Deserialize;
+
+/// What to do with slots of a schedule which passed while the collector
+/// was busy, e.g. because the inverter stopped responding for a while
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedSlots {
+    /// Drop all missed slots and continue with the next upcoming one
+    #[default]
+    Skip,
+    /// Read once for each of the given number of most recent missed slots,
+    /// older slots are dropped. The rows are stamped with the time they were
+    /// read, not the missed slot.
+    CatchUp(usize),
+}
+
+/// Find the slot following `last`, the slot that was just read.
+///
+/// Returns `None` if the schedule has no further slots.
+pub fn next_slot<Z: TimeZone>(
+    schedule: &Schedule,
+    last: &DateTime<Z>,
+    now: &DateTime<Z>,
+    missed: MissedSlots,
+) -> Option<DateTime<Z>> {
+    match missed {
+        MissedSlots::Skip => schedule.after(now.max(last)).next(),
+        MissedSlots::CatchUp(max) => {
+            let mut upcoming = schedule.after(last);
+            let mut pending = VecDeque::with_capacity(max);
+            loop {
+                let slot = upcoming.next();
+                match slot {
+                    Some(slot) if slot <= *now => {
+                        if max == 0 {
+                            continue;
+                        }
+                        if pending.len() == max {
+                            pending.pop_front();
+                        }
+                        pending.push_back(slot);
+                    }
+                    future => return pending.pop_front().or(future),
+                }
+            }
+        }
+    }
+}
+
+/// Group the jobs due at the same instant whose registers `regs` share a
+/// request planned by `planner`, so each group can be read in a batch of
+/// its own. Returns the indices of the jobs for each group.
+pub fn merge_jobs(planner: &Planner, regs: &[Vec<&Register>]) -> Vec<Vec<usize>> {
+    let flat = regs.iter().flatten().copied().collect::<Vec<&Register>>();
+    // job of each register in `flat`
+    let jobs = regs
+        .iter()
+        .enumerate()
+        .flat_map(|(job, regs)| regs.iter().map(move |_| job))
+        .collect::<Vec<usize>>();
+
+    // jobs sharing a request point to the same root
+    let mut parent = (0..regs.len()).collect::<Vec<usize>>();
+    let root = |parent: &[usize], mut job: usize| {
+        while parent[job] != job {
+            job = parent[job];
+        }
+        job
+    };
+    for request in planner.plan(&flat) {
+        let first = root(&parent, jobs[request.parts[0].0]);
+        for &(i, _) in &request.parts[1..] {
+            let other = root(&parent, jobs[i]);
+            parent[other] = first;
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of_root = HashMap::new();
+    for job in 0..regs.len() {
+        let group = *group_of_root.entry(root(&parent, job)).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(job);
+    }
+    groups
+}
+
+#[cfg(test)]
+fn at(secs: u32) -> DateTime<chrono::Utc> {
+    chrono::Utc.with_ymd_and_hms(2024, 3, 25, 12, 0, 0).unwrap()
+        + chrono::Duration::seconds(secs.into())
+}
+
+#[test]
+fn next_slot_on_time() {
+    let schedule = "*/10 * * * * * *".parse::<Schedule>().unwrap();
+    for missed in [MissedSlots::Skip, MissedSlots::CatchUp(3)] {
+        assert_eq!(next_slot(&schedule, &at(0), &at(1), missed), Some(at(10)));
+        assert_eq!(next_slot(&schedule, &at(10), &at(10), missed), Some(at(20)));
+    }
+}
+
+#[test]
+fn next_slot_skip() {
+    let schedule = "*/10 * * * * * *".parse::<Schedule>().unwrap();
+    assert_eq!(
+        next_slot(&schedule, &at(0), &at(45), MissedSlots::Skip),
+        Some(at(50))
+    );
+}
+
+#[test]
+fn next_slot_catch_up() {
+    let schedule = "*/10 * * * * * *".parse::<Schedule>().unwrap();
+    // slots 10..40 passed, only the two most recent are caught up
+    assert_eq!(
+        next_slot(&schedule, &at(0), &at(45), MissedSlots::CatchUp(2)),
+        Some(at(30))
+    );
+    assert_eq!(
+        next_slot(&schedule, &at(30), &at(46), MissedSlots::CatchUp(2)),
+        Some(at(40))
+    );
+    assert_eq!(
+        next_slot(&schedule, &at(40), &at(47), MissedSlots::CatchUp(2)),
+        Some(at(50))
+    );
+    assert_eq!(
+        next_slot(&schedule, &at(0), &at(45), MissedSlots::CatchUp(0)),
+        Some(at(50))
+    );
+}
+
+#[test]
+fn merge_jobs_test() {
+    use huawei_solar::registers::{storage, EFFICIENCY, INPUT_POWER, PV1_CURRENT, PV1_VOLTAGE};
+
+    let planner = Planner::default();
+    // adjacent registers share a request
+    assert_eq!(
+        merge_jobs(
+            &planner,
+            &[vec![&INPUT_POWER], vec![&PV1_VOLTAGE], vec![&EFFICIENCY]]
+        ),
+        vec![vec![0, 1, 2]]
+    );
+    // registers too far apart for a single request
+    assert_eq!(
+        merge_jobs(
+            &planner,
+            &[
+                vec![&storage::CHARGE_DISCHARGE_POWER],
+                vec![&PV1_VOLTAGE],
+                vec![&EFFICIENCY]
+            ]
+        ),
+        vec![vec![0], vec![1, 2]]
+    );
+    // a job with registers in both requests joins them
+    assert_eq!(
+        merge_jobs(
+            &planner,
+            &[
+                vec![&PV1_VOLTAGE],
+                vec![&storage::CHARGE_DISCHARGE_POWER],
+                vec![&PV1_CURRENT, &storage::BUS_VOLTAGE]
+            ]
+        ),
+        vec![vec![0, 1, 2]]
+    );
+    // gaps and holes are the planner's
+    let jobs: [Vec<&Register>; 3] = [vec![&PV1_VOLTAGE], vec![&PV1_CURRENT], vec![&EFFICIENCY]];
+    assert_eq!(
+        merge_jobs(&planner.clone().with_max_gap(0), &jobs),
+        vec![vec![0, 1], vec![2]]
+    );
+    assert_eq!(
+        merge_jobs(&planner.with_hole(32050..32060), &jobs),
+        vec![vec![0, 1], vec![2]]
+    );
+    assert!(merge_jobs(&Planner::default(), &[]).is_empty());
+}
@@ -0,0 +1,466 @@
+//! Durable queue of the samples taken while the database is unreachable.
+//!
+//! Samples are appended to a text file, one per line, which is synced
+//! before [Spool::push] returns. The position of the oldest sample not yet
+//! stored is kept in `<path>.offset`, which is replaced rather than
+//! rewritten. The file starts with a header naming its generation, bumped
+//! whenever the stored samples are cut off the file, so an offset left over
+//! from a crash in between is recognised as stale. After a crash the samples
+//! stored since the offset was last written are stored again, spooled
+//! samples are never lost.
+//!
+//! ```text
+//! # spool 3
+//! 2024-05-01T12:00:00+02:00 General active_power=r1.984 state=ton_grid alarms=a2001,2063
+//! ```
+//!
+//! Values are named by their column, so samples spooled before the
+//! configuration of their table changed are recognised. They are prefixed
+//! with their kind, `r` for [SqlValue::Real], `t` for [SqlValue::Text] with
+//! spaces, newlines and backslashes escaped and `a` for
+//! [SqlValue::IntArray].
+
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::db::SqlValue;
+
+const HEADER: &str = "# spool";
+
+/// Bytes of stored samples kept at the start of the file before they are
+/// cut off, unless they outweigh the pending ones
+const COMPACT_BYTES: u64 = 1 << 20;
+
+/// Row of `table` taken at `time`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub table: String,
+    pub time: DateTime<FixedOffset>,
+    /// Values with the name of their column
+    pub values: Vec<(String, SqlValue)>,
+}
+
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.time.to_rfc3339(), self.table)?;
+        for (column, value) in &self.values {
+            match value {
+                SqlValue::Real(val) => write!(f, " {}=r{}", column, val)?,
+                SqlValue::Text(val) => write!(f, " {}=t{}", column, escape(val))?,
+                SqlValue::IntArray(val) => write!(
+                    f,
+                    " {}=a{}",
+                    column,
+                    val.iter().map(i32::to_string).collect::<Vec<_>>().join(",")
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Sample {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split(' ');
+        let time = fields.next().unwrap_or_default();
+        let time = DateTime::parse_from_rfc3339(time)
+            .map_err(|err| format!("invalid time '{}': {}", time, err))?;
+        let table = match fields.next() {
+            Some(table) if !table.is_empty() => table.to_string(),
+            _ => return Err(String::from("missing table")),
+        };
+        let values = fields
+            .map(|field| -> Result<_, String> {
+                let invalid = || format!("invalid value '{}'", field);
+                let (column, value) = field.split_once('=').ok_or_else(invalid)?;
+                let mut chars = value.chars();
+                let value = match (chars.next(), chars.as_str()) {
+                    (Some('r'), val) => val.parse().map(SqlValue::Real).map_err(|_| invalid()),
+                    (Some('t'), val) => unescape(val).map(SqlValue::Text).ok_or_else(invalid),
+                    (Some('a'), "") => Ok(SqlValue::IntArray(Vec::new())),
+                    (Some('a'), val) => val
+                        .split(',')
+                        .map(str::parse)
+                        .collect::<Result<_, _>>()
+                        .map(SqlValue::IntArray)
+                        .map_err(|_| invalid()),
+                    _ => Err(invalid()),
+                }?;
+                Ok((column.to_string(), value))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Sample {
+            table,
+            time,
+            values,
+        })
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(' ', "\\s")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(match c {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                's' => ' ',
+                'n' => '\n',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(unescaped)
+}
+
+/// Queue of at most `max_samples` samples kept in a file, the oldest are
+/// dropped beyond
+#[derive(Debug)]
+pub struct Spool {
+    path: PathBuf,
+    file: File,
+    generation: u64,
+    /// Length of the header line
+    start: u64,
+    /// Position of the oldest sample
+    offset: u64,
+    /// Number of samples from `offset` on
+    count: usize,
+    max_samples: usize,
+}
+
+impl Spool {
+    /// Open the spool at `path`, creating it if missing and dropping a
+    /// sample only partially written before a crash
+    pub fn open<P: AsRef<Path>>(path: P, max_samples: usize) -> io::Result<Spool> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+
+        let mut reader = BufReader::new(&file);
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let generation = header
+            .strip_suffix('\n')
+            .and_then(|header| header.strip_prefix(HEADER))
+            .and_then(|generation| generation.trim().parse::<u64>().ok());
+        let Some(generation) = generation else {
+            // new or cut off while writing the header
+            file.set_len(0)?;
+            let mut spool = Spool {
+                path,
+                file,
+                generation: 0,
+                start: 0,
+                offset: 0,
+                count: 0,
+                max_samples,
+            };
+            spool.start = spool.write_header()?;
+            spool.offset = spool.start;
+            spool.save_offset()?;
+            return Ok(spool);
+        };
+        let start = header.len() as u64;
+
+        // start of each complete line
+        let mut lines = Vec::new();
+        let mut end = start;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            lines.push(end);
+            end += read as u64;
+        }
+        drop(reader);
+        if file.metadata()?.len() != end {
+            file.set_len(end)?;
+        }
+
+        let offset = fs::read_to_string(sibling(&path, "offset"))
+            .ok()
+            .and_then(|saved| {
+                let (saved_generation, offset) = saved.trim().split_once(' ')?;
+                Some((saved_generation.parse::<u64>().ok()?, offset.parse().ok()?))
+            })
+            .filter(|&(saved_generation, offset)| {
+                saved_generation == generation && (offset == end || lines.contains(&offset))
+            })
+            .map_or(start, |(_, offset)| offset);
+        let mut spool = Spool {
+            path,
+            file,
+            generation,
+            start,
+            offset,
+            count: lines.iter().filter(|&&line| line >= offset).count(),
+            max_samples,
+        };
+        while spool.count > spool.max_samples {
+            spool.pop()?;
+        }
+        Ok(spool)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of samples queued
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Append `sample`, returning whether the oldest sample was dropped to
+    /// make room for it
+    pub fn push(&mut self, sample: &Sample) -> io::Result<bool> {
+        let len = self.file.metadata()?.len();
+        let written = self
+            .file
+            .write_all(format!("{}\n", sample).as_bytes())
+            .and_then(|_| self.file.sync_data());
+        if let Err(err) = written {
+            // don't leave a partial line the next sample would be appended to
+            self.file.set_len(len)?;
+            return Err(err);
+        }
+        self.count += 1;
+        if self.count > self.max_samples {
+            self.pop()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Oldest sample, fails with [io::ErrorKind::InvalidData] if it can't be
+    /// parsed
+    pub fn front(&mut self) -> io::Result<Option<Sample>> {
+        match self.front_line()? {
+            Some(line) => line
+                .trim_end_matches('\n')
+                .parse()
+                .map(Some)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove the oldest sample
+    pub fn pop(&mut self) -> io::Result<()> {
+        let Some(line) = self.front_line()? else {
+            return Ok(());
+        };
+        self.offset += line.len() as u64;
+        self.count -= 1;
+        let stored = self.offset - self.start;
+        let pending = self.file.metadata()?.len() - self.offset;
+        if self.count == 0 || (stored > COMPACT_BYTES && stored > pending) {
+            self.compact()
+        } else {
+            self.save_offset()
+        }
+    }
+
+    fn front_line(&mut self) -> io::Result<Option<String>> {
+        if self.count == 0 {
+            return Ok(None);
+        }
+        self.file.seek(SeekFrom::Start(self.offset))?;
+        let mut line = String::new();
+        BufReader::new(&self.file).read_line(&mut line)?;
+        Ok(Some(line))
+    }
+
+    /// Replace the file by one holding the pending samples only
+    fn compact(&mut self) -> io::Result<()> {
+        let tmp = sibling(&self.path, "tmp");
+        let mut file = File::create(&tmp)?;
+        writeln!(file, "{} {}", HEADER, self.generation + 1)?;
+        self.file.seek(SeekFrom::Start(self.offset))?;
+        io::copy(&mut self.file, &mut file)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        if let Some(dir) = self.path.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            File::open(dir)?.sync_all()?;
+        }
+
+        self.file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        self.generation += 1;
+        self.start = format!("{} {}\n", HEADER, self.generation).len() as u64;
+        self.offset = self.start;
+        self.save_offset()
+    }
+
+    /// Write the header line, returning its length
+    fn write_header(&mut self) -> io::Result<u64> {
+        let header = format!("{} {}\n", HEADER, self.generation);
+        self.file.write_all(header.as_bytes())?;
+        self.file.sync_data()?;
+        Ok(header.len() as u64)
+    }
+
+    fn save_offset(&self) -> io::Result<()> {
+        let tmp = sibling(&self.path, "offset.tmp");
+        fs::write(&tmp, format!("{} {}\n", self.generation, self.offset))?;
+        fs::rename(&tmp, sibling(&self.path, "offset"))
+    }
+}
+
+/// `path` with `.extension` appended
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    path.into()
+}
+
+#[cfg(test)]
+fn sample(table: &str, minute: u32) -> Sample {
+    Sample {
+        table: table.to_string(),
+        time: DateTime::parse_from_rfc3339(&format!("2024-05-01T12:{:02}:00+02:00", minute))
+            .unwrap(),
+        values: vec![(String::from("a"), SqlValue::Real(minute as f32 / 10.0))],
+    }
+}
+
+#[test]
+fn sample_lines() {
+    let sample = Sample {
+        table: String::from("General"),
+        time: DateTime::parse_from_rfc3339("2024-05-01T12:00:00.5+02:00").unwrap(),
+        values: vec![
+            (String::from("active_power"), SqlValue::Real(1.984)),
+            (
+                String::from("state"),
+                SqlValue::Text(String::from("on grid\\\n")),
+            ),
+            (String::from("alarms"), SqlValue::IntArray(vec![2001, 2063])),
+            (String::from("alarms_2"), SqlValue::IntArray(Vec::new())),
+        ],
+    };
+    let line = sample.to_string();
+    assert_eq!(
+        line,
+        "2024-05-01T12:00:00.500+02:00 General active_power=r1.984 state=ton\\sgrid\\\\\\n alarms=a2001,2063 alarms_2=a"
+    );
+    assert_eq!(line.parse(), Ok(sample));
+    let tiny = Sample {
+        values: vec![
+            (String::from("a"), SqlValue::Real(f32::MIN_POSITIVE)),
+            (String::from("b"), SqlValue::Real(-0.1)),
+        ],
+        ..self::sample("pv", 0)
+    };
+    assert_eq!(tiny.to_string().parse(), Ok(tiny));
+
+    assert!("2024-05-01T12:00:00+02:00".parse::<Sample>().is_err());
+    assert!("2024-05-01 General a=r1".parse::<Sample>().is_err());
+    assert!("2024-05-01T12:00:00+02:00 General r1"
+        .parse::<Sample>()
+        .is_err());
+    assert!("2024-05-01T12:00:00+02:00 General a=x1"
+        .parse::<Sample>()
+        .is_err());
+    assert!("2024-05-01T12:00:00+02:00 General a=t\\x"
+        .parse::<Sample>()
+        .is_err());
+    assert!("2024-05-01T12:00:00+02:00 General a=a1,"
+        .parse::<Sample>()
+        .is_err());
+}
+
+#[test]
+fn spool_queue() {
+    let dir = std::env::temp_dir().join(format!("spool-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("spool.txt");
+
+    let mut spool = Spool::open(&path, 3).unwrap();
+    assert!(spool.is_empty());
+    assert_eq!(spool.front().unwrap(), None);
+    for minute in 0..4 {
+        assert_eq!(spool.push(&sample("pv", minute)).unwrap(), minute == 3);
+    }
+    assert_eq!(spool.len(), 3);
+    assert_eq!(spool.front().unwrap(), Some(sample("pv", 1)));
+    spool.pop().unwrap();
+
+    // the offset and pending samples survive a restart, a partial line
+    // written by a crash is dropped
+    drop(spool);
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"2024-05-01T12:04:00+02:00 pv a=r").unwrap();
+    let mut spool = Spool::open(&path, 3).unwrap();
+    assert_eq!(spool.len(), 2);
+    assert_eq!(spool.front().unwrap(), Some(sample("pv", 2)));
+    spool.push(&sample("pv", 5)).unwrap();
+    for minute in [2, 3, 5] {
+        assert_eq!(spool.front().unwrap(), Some(sample("pv", minute)));
+        spool.pop().unwrap();
+    }
+    assert!(spool.is_empty());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "# spool 1\n");
+
+    // an offset of the file before compaction is stale, all samples of the
+    // new one are pending
+    spool.push(&sample("pv", 6)).unwrap();
+    drop(spool);
+    fs::write(sibling(&path, "offset"), "0 40\n").unwrap();
+    let mut spool = Spool::open(&path, 3).unwrap();
+    assert_eq!(spool.len(), 1);
+    assert_eq!(spool.front().unwrap(), Some(sample("pv", 6)));
+
+    // an unreadable sample is reported and can be skipped
+    OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(b"garbage\n")
+        .unwrap();
+    let mut spool = Spool::open(&path, 3).unwrap();
+    spool.pop().unwrap();
+    assert_eq!(
+        spool.front().unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    spool.pop().unwrap();
+    assert!(spool.is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
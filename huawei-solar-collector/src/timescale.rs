@@ -0,0 +1,278 @@
+//! TimescaleDB hypertables, their compression and retention policies and
+//! continuous aggregates for dashboards spanning years of samples.
+//!
+//! Policies are replaced on every start so they follow the configuration.
+//! Continuous aggregates are only created once, a view lacking columns
+//! added to its table later is reported and has to be dropped to be
+//! rebuilt with them, as does one of buckets in a former time zone.
+//!
+//! Energy counters are aggregated as the energy of each bucket. Buckets
+//! start at midnight of the configured time zone, so counters reset at
+//! midnight only count up within them, and the last value of the day is the
+//! energy of a daily bucket.
+
+use std::time::Duration;
+
+use postgres::Client;
+
+use crate::config::{Bucket, TableConfig, TimescaleConfig};
+
+/// SQL literal of `duration`
+fn interval(duration: Duration) -> String {
+    format!("INTERVAL '{} seconds'", duration.as_secs())
+}
+
+/// Columns of the continuous aggregate of `table` with their definition,
+/// empty if the table has no numeric columns
+fn aggregate_columns(table: &TableConfig, bucket: Bucket) -> Vec<(String, String)> {
+    table
+        .values
+        .iter()
+        .filter(|col| col.decode.is_none())
+        .flat_map(|col| {
+            let name = col.name.to_lowercase();
+            if col.resets_daily && bucket == Bucket::Daily {
+                vec![(name.clone(), format!("max({})", name))]
+            } else if col.is_energy() {
+                // counters only grow within a bucket
+                vec![(name.clone(), format!("max({0}) - min({0})", name))]
+            } else {
+                ["avg", "min", "max"]
+                    .into_iter()
+                    .map(|f| (format!("{}_{}", name, f), format!("{}({})", f, name)))
+                    .collect()
+            }
+        })
+        .collect()
+}
+
+fn view_name(table: &TableConfig, bucket: Bucket) -> String {
+    format!("{}_{}", table.table, bucket.name()).to_lowercase()
+}
+
+fn aggregate_sql(table: &TableConfig, bucket: Bucket, timezone: Option<&str>) -> Option<String> {
+    let columns = aggregate_columns(table, bucket);
+    if columns.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "CREATE MATERIALIZED VIEW IF NOT EXISTS {} WITH (timescaledb.continuous) AS \
+         SELECT time_bucket({}, time{}) AS bucket{} FROM {} GROUP BY bucket WITH NO DATA",
+        view_name(table, bucket),
+        interval(bucket.interval()),
+        timezone
+            .map(|timezone| format!(", '{}'", timezone))
+            .unwrap_or_default(),
+        columns
+            .iter()
+            .map(|(name, definition)| format!(", {} AS {}", definition, name))
+            .collect::<String>(),
+        table.table
+    ))
+}
+
+/// Statements replacing the compression and retention policies of the
+/// hypertable `table`
+fn policy_sql(table: &str, cfg: &TimescaleConfig) -> Vec<String> {
+    let mut statements = vec![format!(
+        "SELECT remove_compression_policy('{}', if_exists => TRUE)",
+        table
+    )];
+    if let Some(compress_after) = cfg.compress_after {
+        statements.push(format!(
+            "SELECT add_compression_policy('{}', {})",
+            table,
+            interval(compress_after)
+        ));
+    }
+    statements.extend(retention_sql(table, cfg.retention));
+    statements
+}
+
+fn retention_sql(relation: &str, retention: Option<Duration>) -> Vec<String> {
+    let mut statements = vec![format!(
+        "SELECT remove_retention_policy('{}', if_exists => TRUE)",
+        relation
+    )];
+    if let Some(retention) = retention {
+        statements.push(format!(
+            "SELECT add_retention_policy('{}', {})",
+            relation,
+            interval(retention)
+        ));
+    }
+    statements
+}
+
+fn refresh_policy_sql(view: &str, bucket: Bucket) -> String {
+    format!(
+        "SELECT add_continuous_aggregate_policy('{}', start_offset => {}, end_offset => {}, \
+         schedule_interval => {}, if_not_exists => TRUE)",
+        view,
+        interval(bucket.refresh_window()),
+        interval(bucket.interval()),
+        interval(bucket.interval())
+    )
+}
+
+/// Turn the tables of `tables` into hypertables and set up their policies
+/// and aggregates, returning the changes made and problems found
+pub fn setup(
+    client: &mut Client,
+    cfg: &TimescaleConfig,
+    tables: &[TableConfig],
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if client
+        .query_opt(
+            "SELECT 1 FROM pg_extension WHERE extname = 'timescaledb'",
+            &[],
+        )?
+        .is_none()
+    {
+        return Err("the TimescaleDB extension is not installed in the database".into());
+    }
+    let hypertable = client.prepare(
+        "SELECT compression_enabled FROM timescaledb_information.hypertables
+         WHERE hypertable_schema = current_schema() AND hypertable_name = $1",
+    )?;
+    let view_columns = client.prepare(
+        "SELECT column_name::text FROM information_schema.columns
+         WHERE table_schema = current_schema() AND table_name = $1",
+    )?;
+
+    let mut changes = Vec::new();
+    for table in tables {
+        let name = table.table.to_lowercase();
+        let compression_enabled = match client.query_opt(&hypertable, &[&name])? {
+            Some(row) => row.get::<_, bool>(0),
+            None => {
+                // samples stored before are moved into chunks
+                client.batch_execute(&format!(
+                    "SELECT create_hypertable('{}', 'time', migrate_data => TRUE)",
+                    name
+                ))?;
+                changes.push(format!("created hypertable {}", name));
+                false
+            }
+        };
+        if let Some(chunk_interval) = cfg.chunk_interval {
+            // applies to chunks created from now on
+            client.batch_execute(&format!(
+                "SELECT set_chunk_time_interval('{}', {})",
+                name,
+                interval(chunk_interval)
+            ))?;
+        }
+        if cfg.compress_after.is_some() && !compression_enabled {
+            client.batch_execute(&format!(
+                "ALTER TABLE {} SET (timescaledb.compress, timescaledb.compress_orderby = 'time DESC')",
+                name
+            ))?;
+            changes.push(format!("enabled compression of {}", name));
+        }
+        client.batch_execute(&policy_sql(&name, cfg).join(";"))?;
+
+        for aggregate in &cfg.aggregates {
+            let Some(sql) = aggregate_sql(table, aggregate.bucket, cfg.timezone.as_deref()) else {
+                continue;
+            };
+            let view = view_name(table, aggregate.bucket);
+            let existing = client
+                .query(&view_columns, &[&view])?
+                .into_iter()
+                .map(|row| row.get::<_, String>(0))
+                .collect::<Vec<_>>();
+            if existing.is_empty() {
+                client.batch_execute(&sql)?;
+                changes.push(format!("created continuous aggregate {}", view));
+            } else {
+                let missing = aggregate_columns(table, aggregate.bucket)
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .filter(|name| !existing.contains(name))
+                    .collect::<Vec<_>>();
+                if !missing.is_empty() {
+                    changes.push(format!(
+                        "continuous aggregate {} lacks {}, drop it to rebuild",
+                        view,
+                        missing.join(", ")
+                    ));
+                }
+            }
+            client.batch_execute(&refresh_policy_sql(&view, aggregate.bucket))?;
+            client.batch_execute(&retention_sql(&view, aggregate.retention).join(";"))?;
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+fn table() -> TableConfig {
+    serde_yaml::from_str(
+        "{table: General, cron: '0 * * * * * *', values: [{name: active_power, address: 32080, scale: 0.001, unit: kW, type: I32}, {name: Accu_energy_yield, address: 32106, scale: 0.01, unit: kWh, type: U32}, {name: daily_energy_yield, address: 32114, scale: 0.01, unit: kWh, type: U32, resets_daily: true}, {name: state, address: 32089, scale: 1, type: U16, decode: device_status}]}",
+    )
+    .unwrap()
+}
+
+#[test]
+fn continuous_aggregates() {
+    let table = table();
+    assert_eq!(
+        aggregate_sql(&table, Bucket::Hourly, None).unwrap(),
+        "CREATE MATERIALIZED VIEW IF NOT EXISTS general_hourly WITH (timescaledb.continuous) AS \
+         SELECT time_bucket(INTERVAL '3600 seconds', time) AS bucket, \
+         avg(active_power) AS active_power_avg, min(active_power) AS active_power_min, \
+         max(active_power) AS active_power_max, \
+         max(accu_energy_yield) - min(accu_energy_yield) AS accu_energy_yield, \
+         max(daily_energy_yield) - min(daily_energy_yield) AS daily_energy_yield \
+         FROM General GROUP BY bucket WITH NO DATA"
+    );
+    // the daily counter holds the energy of the day at its end
+    assert_eq!(
+        aggregate_sql(&table, Bucket::Daily, Some("Europe/Berlin")).unwrap(),
+        "CREATE MATERIALIZED VIEW IF NOT EXISTS general_daily WITH (timescaledb.continuous) AS \
+         SELECT time_bucket(INTERVAL '86400 seconds', time, 'Europe/Berlin') AS bucket, \
+         avg(active_power) AS active_power_avg, min(active_power) AS active_power_min, \
+         max(active_power) AS active_power_max, \
+         max(accu_energy_yield) - min(accu_energy_yield) AS accu_energy_yield, \
+         max(daily_energy_yield) AS daily_energy_yield \
+         FROM General GROUP BY bucket WITH NO DATA"
+    );
+    assert_eq!(
+        refresh_policy_sql("general_daily", Bucket::Daily),
+        "SELECT add_continuous_aggregate_policy('general_daily', \
+         start_offset => INTERVAL '259200 seconds', end_offset => INTERVAL '86400 seconds', \
+         schedule_interval => INTERVAL '86400 seconds', if_not_exists => TRUE)"
+    );
+
+    let states: TableConfig = serde_yaml::from_str(
+        "{table: states, cron: '0 * * * * * *', values: [{name: state, address: 32089, scale: 1, type: U16, decode: device_status}]}",
+    )
+    .unwrap();
+    assert_eq!(aggregate_sql(&states, Bucket::Daily, None), None);
+}
+
+#[test]
+fn policies() {
+    let cfg = TimescaleConfig {
+        compress_after: Some(Duration::from_secs(7 * 86400)),
+        retention: Some(Duration::from_secs(365 * 86400)),
+        ..TimescaleConfig::default()
+    };
+    assert_eq!(
+        policy_sql("general", &cfg),
+        [
+            "SELECT remove_compression_policy('general', if_exists => TRUE)",
+            "SELECT add_compression_policy('general', INTERVAL '604800 seconds')",
+            "SELECT remove_retention_policy('general', if_exists => TRUE)",
+            "SELECT add_retention_policy('general', INTERVAL '31536000 seconds')",
+        ]
+    );
+    assert_eq!(
+        policy_sql("general", &TimescaleConfig::default()),
+        [
+            "SELECT remove_compression_policy('general', if_exists => TRUE)",
+            "SELECT remove_retention_policy('general', if_exists => TRUE)",
+        ]
+    );
+}
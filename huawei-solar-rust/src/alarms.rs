@@ -0,0 +1,288 @@
+//! Decoding of the alarm registers `ALARM_1` to `ALARM_3`.
+
+use std::fmt::Display;
+
+use crate::registers::{RegisterType, Type};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Severity {
+    Warning,
+    Minor,
+    Major,
+}
+
+/// Entry of the Huawei alarm catalogue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Alarm {
+    /// Alarm ID as shown in the app and the inverter manual
+    pub id: u16,
+    pub name: &'static str,
+    pub severity: Severity,
+    /// Alarm register the alarm is reported in, `0` for `ALARM_1`
+    pub register: u8,
+    /// Bit in the alarm register, LSB first
+    pub bit: u8,
+}
+
+impl Display for Alarm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}, {:?})", self.name, self.id, self.severity)
+    }
+}
+
+macro_rules! catalogue {
+    ($($register:literal, $bit:literal => $id:literal, $severity:ident, $name:literal;)*) => {
+        /// All documented alarms of `ALARM_1` to `ALARM_3`
+        pub const CATALOGUE: &[Alarm] = &[
+            $(Alarm { id: $id, name: $name, severity: Severity::$severity, register: $register, bit: $bit },)*
+        ];
+    };
+}
+
+#[rustfmt::skip]
+catalogue! {
+    0,  0 => 2001, Major,   "High String Input Voltage";
+    0,  1 => 2002, Major,   "DC Arc Fault";
+    0,  2 => 2011, Major,   "String Reverse Connection";
+    0,  3 => 2012, Warning, "String Current Backfeed";
+    0,  4 => 2013, Warning, "Abnormal String Power";
+    0,  5 => 2021, Major,   "AFCI Self-Check Fail";
+    0,  6 => 2031, Major,   "Phase Wire Short-Circuited to PE";
+    0,  7 => 2032, Major,   "Grid Loss";
+    0,  8 => 2033, Major,   "Grid Undervoltage";
+    0,  9 => 2034, Major,   "Grid Overvoltage";
+    0, 10 => 2035, Major,   "Grid Voltage Imbalance";
+    0, 11 => 2036, Major,   "Grid Overfrequency";
+    0, 12 => 2037, Major,   "Grid Underfrequency";
+    0, 13 => 2038, Major,   "Unstable Grid Frequency";
+    0, 14 => 2039, Major,   "Output Overcurrent";
+    0, 15 => 2040, Major,   "Output DC Component Overhigh";
+    1,  0 => 2051, Major,   "Abnormal Residual Current";
+    1,  1 => 2061, Major,   "Abnormal Grounding";
+    1,  2 => 2062, Major,   "Low Insulation Resistance";
+    1,  3 => 2063, Minor,   "Overtemperature";
+    1,  4 => 2064, Major,   "Device Fault";
+    1,  5 => 2065, Minor,   "Upgrade Failed or Version Mismatch";
+    1,  6 => 2066, Warning, "License Expired";
+    1,  7 => 61440, Minor,  "Faulty Monitoring Unit";
+    1,  8 => 2067, Major,   "Faulty Power Collector";
+    1,  9 => 2068, Minor,   "Battery Abnormal";
+    1, 10 => 2070, Major,   "Active Islanding";
+    1, 11 => 2071, Major,   "Passive Islanding";
+    1, 12 => 2072, Major,   "Transient AC Overvoltage";
+    1, 13 => 2075, Warning, "Peripheral Port Short Circuit";
+    1, 14 => 2077, Major,   "Churn Output Overload";
+    1, 15 => 2080, Major,   "Abnormal PV Module Configuration";
+    2,  0 => 2081, Warning, "Optimizer Fault";
+    2,  1 => 2085, Minor,   "Built-in PID Operation Abnormal";
+    2,  2 => 2014, Major,   "High Input String Voltage to Ground";
+    2,  3 => 2086, Major,   "External Fan Abnormal";
+    2,  4 => 2069, Major,   "Battery Reverse Connection";
+    2,  5 => 2082, Major,   "On-grid/Off-grid Controller Abnormal";
+    2,  6 => 2015, Warning, "PV String Loss";
+    2,  7 => 2087, Major,   "Internal Fan Abnormal";
+    2,  8 => 2088, Major,   "DC Protection Unit Abnormal";
+}
+
+impl Alarm {
+    /// Look up an alarm by its ID
+    pub fn by_id(id: u16) -> Option<&'static Alarm> {
+        CATALOGUE.iter().find(|alarm| alarm.id == id)
+    }
+}
+
+/// Content of the [ALARMS](crate::registers::ALARMS) registers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Alarms {
+    /// `ALARM_1` to `ALARM_3` as read
+    pub words: [u16; 3],
+}
+
+impl Alarms {
+    pub const fn is_set(&self, alarm: &Alarm) -> bool {
+        self.words[alarm.register as usize] >> alarm.bit & 1 == 1
+    }
+
+    /// Documented alarms which are currently raised
+    pub fn active(&self) -> impl Iterator<Item = &'static Alarm> + '_ {
+        CATALOGUE.iter().filter(|alarm| self.is_set(alarm))
+    }
+
+    /// Raised bits without an entry in the catalogue as `(register, bit)`
+    pub fn undocumented(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        (0..3u8)
+            .flat_map(|register| (0..16u8).map(move |bit| (register, bit)))
+            .filter(|&(register, bit)| self.words[register as usize] >> bit & 1 == 1)
+            .filter(|&(register, bit)| {
+                !CATALOGUE
+                    .iter()
+                    .any(|alarm| alarm.register == register && alarm.bit == bit)
+            })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&word| word == 0)
+    }
+
+    /// Highest severity of the active alarms
+    pub fn severity(&self) -> Option<Severity> {
+        self.active().map(|alarm| alarm.severity).max()
+    }
+
+    /// Alarms raised since `previous`
+    pub fn raised_since<'a>(
+        &'a self,
+        previous: &'a Alarms,
+    ) -> impl Iterator<Item = &'static Alarm> + 'a {
+        self.active().filter(|alarm| !previous.is_set(alarm))
+    }
+
+    /// Alarms cleared since `previous`
+    pub fn cleared_since<'a>(
+        &'a self,
+        previous: &'a Alarms,
+    ) -> impl Iterator<Item = &'static Alarm> + 'a {
+        previous.raised_since(self)
+    }
+}
+
+impl Display for Alarms {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no alarms");
+        }
+        let mut first = true;
+        for alarm in self.active() {
+            if !first {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", alarm)?;
+            first = false;
+        }
+        for (register, bit) in self.undocumented() {
+            if !first {
+                write!(f, ", ")?;
+            }
+            write!(f, "undocumented alarm {}.{}", register + 1, bit)?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+impl RegisterType for Alarms {
+    const TYPE: Type = Type::BF;
+
+    fn convert(vec: &[u16]) -> Option<Self> {
+        Some(Alarms {
+            words: vec.try_into().ok()?,
+        })
+    }
+
+    fn encode(&self, quantity: usize) -> Option<Vec<u16>> {
+        (quantity == 3).then(|| self.words.to_vec())
+    }
+}
+
+#[test]
+fn catalogue_is_consistent() {
+    for (i, alarm) in CATALOGUE.iter().enumerate() {
+        assert!(alarm.register < 3 && alarm.bit < 16, "{}", alarm);
+        for other in &CATALOGUE[i + 1..] {
+            assert_ne!(alarm.id, other.id);
+            assert!((alarm.register, alarm.bit) != (other.register, other.bit));
+        }
+    }
+    assert_eq!(
+        CATALOGUE.iter().filter(|alarm| alarm.register == 0).count(),
+        16
+    );
+    assert_eq!(
+        CATALOGUE.iter().filter(|alarm| alarm.register == 1).count(),
+        16
+    );
+}
+
+#[test]
+fn alarms_lsb_first() {
+    let alarms = Alarms::convert(&[0x0001, 0x0000, 0x0000]).unwrap();
+    assert_eq!(
+        alarms.active().map(|a| a.id).collect::<Vec<_>>(),
+        vec![2001]
+    );
+
+    let alarms = Alarms::convert(&[0x8080, 0x0004, 0x0040]).unwrap();
+    assert_eq!(
+        alarms.active().map(|a| a.id).collect::<Vec<_>>(),
+        vec![2032, 2040, 2062, 2015]
+    );
+    assert_eq!(alarms.severity(), Some(Severity::Major));
+    assert_eq!(alarms.undocumented().count(), 0);
+
+    assert!(Alarms::convert(&[0, 0]).is_none());
+    assert_eq!(alarms.encode(3), Some(vec![0x8080, 0x0004, 0x0040]));
+}
+
+#[test]
+fn alarms_undocumented_bits() {
+    let alarms = Alarms::convert(&[0x0000, 0x0000, 0x8200]).unwrap();
+    assert_eq!(alarms.active().count(), 0);
+    assert_eq!(
+        alarms.undocumented().collect::<Vec<_>>(),
+        vec![(2, 9), (2, 15)]
+    );
+    assert!(!alarms.is_empty());
+    assert_eq!(alarms.severity(), None);
+    assert_eq!(
+        alarms.to_string(),
+        "undocumented alarm 3.9, undocumented alarm 3.15"
+    );
+}
+
+#[test]
+fn alarms_transitions() {
+    let before = Alarms::convert(&[0x0080, 0x0008, 0x0000]).unwrap();
+    let after = Alarms::convert(&[0x0080, 0x0000, 0x0001]).unwrap();
+    assert_eq!(
+        after
+            .raised_since(&before)
+            .map(|a| a.id)
+            .collect::<Vec<_>>(),
+        vec![2081]
+    );
+    assert_eq!(
+        after
+            .cleared_since(&before)
+            .map(|a| a.id)
+            .collect::<Vec<_>>(),
+        vec![2063]
+    );
+    assert_eq!(Alarm::by_id(2032).unwrap().name, "Grid Loss");
+    assert_eq!(
+        Alarm::by_id(2063).unwrap().to_string(),
+        "Overtemperature (2063, Minor)"
+    );
+    assert_eq!(Alarms::default().to_string(), "no alarms");
+}
+
+#[test]
+fn alarms_from_reg_value() {
+    use crate::registers::{RegValue, ALARMS, ALARM_1};
+
+    let raw = [0x0000, 0x0400, 0x0001];
+    let val = RegValue {
+        reg: &ALARMS,
+        val: ALARMS.typ.convert(&raw).unwrap(),
+    };
+    assert_eq!(val.to_alarms().unwrap(), Alarms::convert(&raw).unwrap());
+    let val = RegValue {
+        reg: &ALARM_1,
+        val: ALARM_1.typ.convert(&[0x0001]).unwrap(),
+    };
+    assert!(val.to_alarms().is_err());
+}
@@ -0,0 +1,461 @@
+//! Modbus TCP client for tokio, see [AsyncInverter].
+
+use std::{io, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{sleep, timeout},
+};
+
+use crate::{
+    batch, pdu,
+    registers::{self, Access},
+    Error,
+};
+
+/// Asynchronous counterpart of [Inverter](crate::Inverter) connected over
+/// Modbus TCP.
+///
+/// All futures are cancellation safe: a request interrupted by dropping its
+/// future leaves the connection in an unknown state, so the next request
+/// reconnects first. Late responses to earlier requests are recognised by
+/// their transaction ID and skipped.
+pub struct AsyncInverter {
+    addr: String,
+    uid: u8,
+    timeout: Duration,
+    stream: Option<TcpStream>,
+    /// Set while a request is in flight
+    dirty: bool,
+    transaction_id: u16,
+    planner: batch::Planner,
+}
+
+/// Size of the MBAP header including the unit ID
+const HEADER_LEN: usize = 7;
+
+impl AsyncInverter {
+    /// Connect to the inverter directly over TCP
+    ///
+    /// Defaults are the same as for [Inverter::connect_tcp](crate::Inverter::connect_tcp),
+    /// `timeout` limits connecting as well as each request.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use huawei_solar::{registers::*, AsyncInverter};
+    /// # async fn run() -> Result<(), huawei_solar::Error> {
+    /// let mut inverter = AsyncInverter::connect_tcp(None, None, None, None).await?;
+    /// let rated_power: u32 = inverter.read(&RATED_POWER).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn connect_tcp(
+        addr: Option<&str>,
+        port: Option<u16>,
+        modbus_uid: Option<u8>,
+        timeout: Option<Duration>,
+    ) -> Result<Self, Error> {
+        let mut inverter = AsyncInverter {
+            addr: format!(
+                "{}:{}",
+                addr.unwrap_or("192.168.200.1"),
+                port.unwrap_or(6607)
+            ),
+            uid: modbus_uid.unwrap_or(0),
+            timeout: timeout.unwrap_or(Duration::from_secs(5)),
+            stream: None,
+            dirty: false,
+            transaction_id: 0,
+            planner: batch::Planner::default(),
+        };
+        inverter.reconnect().await?;
+        Ok(inverter)
+    }
+
+    async fn reconnect(&mut self) -> Result<(), Error> {
+        self.stream = None;
+        let stream = timeout(self.timeout, TcpStream::connect(&self.addr))
+            .await
+            .map_err(|_| timed_out())??;
+        stream.set_nodelay(true)?;
+        self.stream = Some(stream);
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Set how batch reads are split into requests
+    pub fn set_planner(&mut self, planner: batch::Planner) {
+        self.planner = planner;
+    }
+
+    /// Read a single register and convert it to its value type
+    pub async fn read<T: registers::RegisterType>(
+        &mut self,
+        reg: &registers::TypedRegister<'_, T>,
+    ) -> Result<T, Error> {
+        let value = self.read_raw(reg).await?;
+        T::convert(&value).ok_or(Error::Conversion)
+    }
+
+    pub async fn read_raw(&mut self, reg: &registers::Register<'_>) -> Result<Vec<u16>, Error> {
+        if reg.access == Access::WO {
+            return Err(Error::WriteOnly(reg.name.to_string()));
+        }
+        self.read_holding_registers(reg.address, reg.quantity.into())
+            .await
+    }
+
+    /// Read the raw words of `regs`, in input order, see
+    /// [Inverter::read_batch_raw](crate::Inverter::read_batch_raw)
+    pub async fn read_batch_raw(
+        &mut self,
+        regs: &[&registers::Register<'_>],
+    ) -> Result<Vec<Vec<u16>>, Error> {
+        let mut chunked = vec![Vec::new(); regs.len()];
+        for request in self.planner.plan(regs) {
+            let values = self
+                .read_holding_registers(request.address, request.quantity)
+                .await?;
+            for (i, offset) in request.parts {
+                chunked[i] = values[offset..offset + regs[i].quantity as usize].to_vec();
+            }
+        }
+        Ok(chunked)
+    }
+
+    pub async fn read_batch<'a, 'b>(
+        &mut self,
+        regs: &'a [&registers::Register<'b>],
+    ) -> Result<Vec<registers::RegValue<'a, 'b>>, Error> {
+        let values = self.read_batch_raw(regs).await?;
+
+        values
+            .into_iter()
+            .zip(regs)
+            .map(|(val, reg)| {
+                Ok(registers::RegValue {
+                    reg,
+                    val: reg.typ.convert(&val).ok_or(Error::Conversion)?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn read_batch_retry<'a, 'b>(
+        &mut self,
+        regs: &'a [&registers::Register<'b>],
+        retries: u8,
+    ) -> Result<Vec<registers::RegValue<'a, 'b>>, Error> {
+        let mut retries = retries;
+        loop {
+            match self.read_batch(regs).await {
+                Err(_) if retries > 0 => {
+                    retries -= 1;
+                    sleep(Duration::from_millis(200)).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Write `value` to a [Access::RW] or [Access::WO] register, see
+    /// [Inverter::write](crate::Inverter::write)
+    pub async fn write(
+        &mut self,
+        reg: &registers::Register<'_>,
+        value: registers::Value,
+    ) -> Result<(), Error> {
+        if reg.access == Access::RO {
+            return Err(Error::ReadOnly(reg.name.to_string()));
+        }
+        let words = reg.encode(&value)?;
+        self.write_words(reg.address, &words).await
+    }
+
+    /// Like [AsyncInverter::write] and read the register back to confirm
+    /// the inverter accepted the value
+    pub async fn write_verified(
+        &mut self,
+        reg: &registers::Register<'_>,
+        value: registers::Value,
+    ) -> Result<(), Error> {
+        if reg.access == Access::WO {
+            return Err(Error::WriteOnly(reg.name.to_string()));
+        }
+        if reg.access == Access::RO {
+            return Err(Error::ReadOnly(reg.name.to_string()));
+        }
+        let words = reg.encode(&value)?;
+        self.write_words(reg.address, &words).await?;
+
+        let read_back = self
+            .read_holding_registers(reg.address, reg.quantity.into())
+            .await?;
+        if read_back != words {
+            return Err(Error::Verification(reg.name.to_string()));
+        }
+        Ok(())
+    }
+
+    pub async fn disconnect(&mut self) -> Result<(), Error> {
+        if let Some(mut stream) = self.stream.take() {
+            stream.shutdown().await?;
+        }
+        Ok(())
+    }
+
+    async fn read_holding_registers(
+        &mut self,
+        address: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>, Error> {
+        let mut pdu = vec![0x03];
+        pdu.extend_from_slice(&address.to_be_bytes());
+        pdu.extend_from_slice(&quantity.to_be_bytes());
+        let response = self.transact(&pdu).await?;
+        if response.len() < 2
+            || response[1] as usize != quantity as usize * 2
+            || response.len() != 2 + response[1] as usize
+        {
+            return Err(invalid_response());
+        }
+        Ok(words(&response[2..]))
+    }
+
+    async fn write_words(&mut self, address: u16, values: &[u16]) -> Result<(), Error> {
+        let mut pdu = Vec::with_capacity(6 + values.len() * 2);
+        match values {
+            [value] => {
+                pdu.push(0x06);
+                pdu.extend_from_slice(&address.to_be_bytes());
+                pdu.extend_from_slice(&value.to_be_bytes());
+                if self.transact(&pdu).await? != pdu {
+                    return Err(invalid_response());
+                }
+            }
+            _ => {
+                pdu.push(0x10);
+                pdu.extend_from_slice(&address.to_be_bytes());
+                pdu.extend_from_slice(&(values.len() as u16).to_be_bytes());
+                pdu.push((values.len() * 2) as u8);
+                pdu.extend(values.iter().flat_map(|v| v.to_be_bytes()));
+                if self.transact(&pdu).await? != pdu[..5] {
+                    return Err(invalid_response());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Send `pdu` and return the PDU of the matching response
+    async fn transact(&mut self, pdu: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.dirty || self.stream.is_none() {
+            self.reconnect().await?;
+        }
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        let transaction_id = self.transaction_id;
+
+        let mut frame = Vec::with_capacity(HEADER_LEN + pdu.len());
+        frame.extend_from_slice(&transaction_id.to_be_bytes());
+        frame.extend_from_slice(&0u16.to_be_bytes());
+        frame.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+        frame.push(self.uid);
+        frame.extend_from_slice(pdu);
+
+        self.dirty = true;
+        let stream = self.stream.as_mut().unwrap();
+        let response = timeout(self.timeout, async {
+            stream.write_all(&frame).await?;
+            loop {
+                let mut header = [0u8; HEADER_LEN];
+                stream.read_exact(&mut header).await?;
+                let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+                if len < 2 {
+                    return Err(invalid_response());
+                }
+                let mut response = vec![0u8; len - 1];
+                stream.read_exact(&mut response).await?;
+                // response to a request which timed out earlier
+                if header[0..2] != transaction_id.to_be_bytes() {
+                    continue;
+                }
+                if header[2..4] != [0, 0] || header[6] != frame[6] {
+                    return Err(invalid_response());
+                }
+                return Ok(response);
+            }
+        })
+        .await
+        .map_err(|_| timed_out())??;
+        self.dirty = false;
+
+        pdu::check_response(pdu, &response)?;
+        Ok(response)
+    }
+}
+
+fn words(bytes: &[u8]) -> Vec<u16> {
+    bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect()
+}
+
+fn timed_out() -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::TimedOut, "request timed out"))
+}
+
+fn invalid_response() -> Error {
+    Error::Modbus(modbus::Error::InvalidResponse)
+}
+
+/// Serve Modbus TCP on localhost, `reply` maps the connection number and
+/// request PDU to the response frames as `(transaction ID offset, PDU)`
+#[cfg(test)]
+async fn serve<F>(reply: F) -> AsyncInverter
+where
+    F: Fn(usize, &[u8]) -> Vec<(u16, Vec<u8>)> + Send + Sync + 'static,
+{
+    use std::sync::Arc;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let reply = Arc::new(reply);
+    tokio::spawn(async move {
+        for connection in 0.. {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let reply = reply.clone();
+            tokio::spawn(async move {
+                let mut header = [0u8; HEADER_LEN];
+                while stream.read_exact(&mut header).await.is_ok() {
+                    let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+                    let mut pdu = vec![0u8; len - 1];
+                    stream.read_exact(&mut pdu).await.unwrap();
+                    let tid = u16::from_be_bytes([header[0], header[1]]);
+                    for (offset, response) in reply(connection, &pdu) {
+                        let mut frame = tid.wrapping_sub(offset).to_be_bytes().to_vec();
+                        frame.extend_from_slice(&[0, 0]);
+                        frame.extend_from_slice(&(response.len() as u16 + 1).to_be_bytes());
+                        frame.push(header[6]);
+                        frame.extend_from_slice(&response);
+                        stream.write_all(&frame).await.unwrap();
+                    }
+                }
+            });
+        }
+    });
+    AsyncInverter::connect_tcp(
+        Some("127.0.0.1"),
+        Some(port),
+        Some(1),
+        Some(Duration::from_millis(200)),
+    )
+    .await
+    .unwrap()
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn async_read() {
+    use registers::*;
+
+    let mut inverter = serve(|_, pdu| match pdu {
+        [0x03, 0x7D, 0x10, 0x00, 0x02] => vec![(0, vec![0x03, 0x04, 0x0F, 0xA0, 0x03, 0x39])],
+        [0x03, 0x75, 0x76, 0x00, 0x01] => vec![(0, vec![0x03, 0x02, 0x01, 0x9A])],
+        [0x03, 0x75, 0x79, 0x00, 0x02] => vec![(0, vec![0x03, 0x04, 0x00, 0x00, 0x27, 0x10])],
+        _ => vec![(0, vec![pdu[0] | 0x80, 0x02])],
+    })
+    .await;
+
+    let rated_power: u32 = inverter.read(&RATED_POWER).await.unwrap();
+    assert_eq!(rated_power, 10000);
+    let regs: [&Register; 3] = [&PV1_VOLTAGE, &MODEL_ID, &PV1_CURRENT];
+    let values = inverter.read_batch(&regs).await.unwrap();
+    assert_eq!(values[0].to_float().unwrap(), 400.0);
+    assert_eq!(values[1].to_u16().unwrap(), 410);
+    assert_eq!(values[2].to_float().unwrap(), 8.25);
+    assert!(matches!(
+        inverter.read_raw(&MODEL).await,
+        Err(Error::Modbus(modbus::Error::Exception(
+            modbus::ExceptionCode::IllegalDataAddress
+        )))
+    ));
+    assert!(matches!(
+        inverter.read(&STARTUP).await,
+        Err(Error::WriteOnly(_))
+    ));
+    inverter.disconnect().await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn async_write() {
+    use registers::*;
+
+    let mut inverter = serve(|_, pdu| match pdu {
+        [0x06, ..] => vec![(0, pdu.to_vec())],
+        [0x10, ..] => vec![(0, pdu[..5].to_vec())],
+        [0x03, 0xA7, 0xFE, 0x00, 0x01] => vec![(0, vec![0x03, 0x02, 0x00, 0x00])],
+        _ => vec![(0, vec![pdu[0] | 0x80, 0x01])],
+    })
+    .await;
+
+    inverter.write(&STARTUP, Value::U16(0)).await.unwrap();
+    inverter
+        .write(&ACTIVE_POWER, Value::I32(-1))
+        .await
+        .unwrap_err();
+    let mut reg = *ACTIVE_POWER;
+    reg.access = Access::RW;
+    inverter.write(&reg, Value::I32(-1)).await.unwrap();
+    assert!(matches!(
+        inverter.write_verified(&TIME_ZONE, Value::I16(60)).await,
+        Err(Error::Verification(_))
+    ));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn async_stale_response() {
+    use registers::*;
+
+    // answers every request with a late response to the previous one first
+    let mut inverter = serve(|_, _| {
+        vec![
+            (1, vec![0x03, 0x02, 0xFF, 0xFF]),
+            (0, vec![0x03, 0x02, 0x00, 0x2A]),
+        ]
+    })
+    .await;
+    assert_eq!(inverter.read(&MODEL_ID).await.unwrap(), 42);
+    assert_eq!(inverter.read(&MODEL_ID).await.unwrap(), 42);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn async_timeout_and_cancellation() {
+    use registers::*;
+
+    // the first connection never answers
+    let mut inverter = serve(|connection, _| match connection {
+        0 => vec![],
+        _ => vec![(0, vec![0x03, 0x02, 0x00, 0x2A])],
+    })
+    .await;
+    assert!(matches!(
+        inverter.read(&MODEL_ID).await,
+        Err(Error::Io(err)) if err.kind() == io::ErrorKind::TimedOut
+    ));
+    assert_eq!(inverter.read(&MODEL_ID).await.unwrap(), 42);
+
+    // a dropped request forces a new connection as well
+    let mut inverter = serve(|connection, _| match connection {
+        0 => vec![],
+        _ => vec![(0, vec![0x03, 0x02, 0x00, 0x2A])],
+    })
+    .await;
+    let cancelled = timeout(Duration::from_millis(20), inverter.read(&MODEL_ID)).await;
+    assert!(cancelled.is_err());
+    assert_eq!(inverter.read(&MODEL_ID).await.unwrap(), 42);
+}
@@ -0,0 +1,207 @@
+//! Planning of Modbus requests for reading arbitrary sets of registers.
+
+use std::ops::Range;
+
+use crate::registers::Register;
+
+/// Maximum number of registers in a single Modbus read request
+pub const MAX_REQUEST_LEN: u16 = 125;
+
+/// Splits a set of registers into as few read requests as possible.
+///
+/// Registers end up in the same request if the request stays within
+/// `max_len` registers, the gap to the previous register is at most
+/// `max_gap` registers and the gap does not touch any of the known holes,
+/// i.e. address ranges the inverter refuses to read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Planner {
+    max_len: u16,
+    max_gap: u16,
+    holes: Vec<Range<u16>>,
+}
+
+/// Single read request of a plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub address: u16,
+    pub quantity: u16,
+    /// Index of each register in the planned set and its offset in the
+    /// response
+    pub parts: Vec<(usize, usize)>,
+}
+
+impl Default for Planner {
+    /// Requests of up to [MAX_REQUEST_LEN] registers with unlimited gaps
+    fn default() -> Self {
+        Planner {
+            max_len: MAX_REQUEST_LEN,
+            max_gap: u16::MAX,
+            holes: Vec::new(),
+        }
+    }
+}
+
+impl Planner {
+    /// Limit the number of registers per request, at most [MAX_REQUEST_LEN]
+    pub fn with_max_len(self, max_len: u16) -> Self {
+        assert!(
+            (1..=MAX_REQUEST_LEN).contains(&max_len),
+            "request length must be between 1 and {}",
+            MAX_REQUEST_LEN
+        );
+        Planner { max_len, ..self }
+    }
+
+    /// Maximum number of unused registers read to merge two requests
+    pub fn with_max_gap(self, max_gap: u16) -> Self {
+        Planner { max_gap, ..self }
+    }
+
+    /// Never read the addresses in `hole` unless a register asks for them
+    pub fn with_hole(mut self, hole: Range<u16>) -> Self {
+        self.holes.push(hole);
+        self
+    }
+
+    /// Plan the requests for `regs`, ordered by address.
+    ///
+    /// A register longer than the maximum request length gets a request
+    /// of its own.
+    pub fn plan(&self, regs: &[&Register]) -> Vec<Request> {
+        let mut order = (0..regs.len()).collect::<Vec<usize>>();
+        order.sort_by_key(|&i| (regs[i].address, regs[i].quantity));
+
+        let mut requests: Vec<Request> = Vec::new();
+        // end of the current request, may exceed u16 for the last register
+        let mut end = 0u32;
+        for i in order {
+            let start = regs[i].address as u32;
+            let reg_end = start + regs[i].quantity as u32;
+            match requests.last_mut() {
+                Some(request) if self.can_merge(request.address as u32, end, start, reg_end) => {
+                    end = end.max(reg_end);
+                    request.quantity = (end - request.address as u32) as u16;
+                    request
+                        .parts
+                        .push((i, (start - request.address as u32) as usize));
+                }
+                _ => {
+                    end = reg_end;
+                    requests.push(Request {
+                        address: regs[i].address,
+                        quantity: regs[i].quantity.into(),
+                        parts: vec![(i, 0)],
+                    });
+                }
+            }
+        }
+        requests
+    }
+
+    fn can_merge(&self, request_start: u32, request_end: u32, start: u32, end: u32) -> bool {
+        // unused registers read when merging
+        let gap = request_end..start;
+        end.max(request_end) - request_start <= self.max_len.into()
+            && start.saturating_sub(request_end) <= self.max_gap.into()
+            && (gap.is_empty()
+                || !self
+                    .holes
+                    .iter()
+                    .any(|hole| gap.start < hole.end as u32 && (hole.start as u32) < gap.end))
+    }
+}
+
+#[cfg(test)]
+fn reg(address: u16, quantity: u8) -> Register<'static> {
+    use crate::registers::{Access, Gain, Type};
+    Register {
+        address,
+        quantity,
+        gain: Gain::NONE,
+        unit: None,
+        access: Access::RO,
+        typ: Type::STR,
+        name: "TEST",
+    }
+}
+
+#[cfg(test)]
+fn spans(requests: &[Request]) -> Vec<(u16, u16)> {
+    requests.iter().map(|r| (r.address, r.quantity)).collect()
+}
+
+#[test]
+fn plan_contiguous() {
+    let regs = [reg(32016, 1), reg(32017, 1), reg(32064, 2)];
+    let regs = regs.iter().collect::<Vec<_>>();
+    let plan = Planner::default().plan(&regs);
+    assert_eq!(spans(&plan), vec![(32016, 50)]);
+    assert_eq!(plan[0].parts, vec![(0, 0), (1, 1), (2, 48)]);
+    assert!(Planner::default().plan(&[]).is_empty());
+}
+
+#[test]
+fn plan_max_len() {
+    // 30000 and 32000 range can never share a request
+    let regs = [reg(32080, 2), reg(30000, 15), reg(30070, 1), reg(32016, 1)];
+    let regs = regs.iter().collect::<Vec<_>>();
+    let plan = Planner::default().plan(&regs);
+    assert_eq!(spans(&plan), vec![(30000, 71), (32016, 66)]);
+    assert_eq!(plan[0].parts, vec![(1, 0), (2, 70)]);
+    assert_eq!(plan[1].parts, vec![(3, 0), (0, 64)]);
+
+    let regs = [reg(0, 100), reg(100, 26)];
+    let regs = regs.iter().collect::<Vec<_>>();
+    assert_eq!(
+        spans(&Planner::default().plan(&regs)),
+        vec![(0, 100), (100, 26)]
+    );
+    assert_eq!(
+        spans(&Planner::default().with_max_len(50).plan(&regs)),
+        vec![(0, 100), (100, 26)]
+    );
+}
+
+#[test]
+fn plan_max_gap() {
+    let regs = [reg(10, 2), reg(20, 1), reg(21, 1), reg(40, 2)];
+    let regs = regs.iter().collect::<Vec<_>>();
+    let planner = Planner::default().with_max_gap(8);
+    assert_eq!(spans(&planner.plan(&regs)), vec![(10, 12), (40, 2)]);
+    let planner = Planner::default().with_max_gap(0);
+    assert_eq!(spans(&planner.plan(&regs)), vec![(10, 2), (20, 2), (40, 2)]);
+}
+
+#[test]
+fn plan_holes() {
+    let regs = [reg(32000, 1), reg(32002, 1), reg(32008, 3)];
+    let regs = regs.iter().collect::<Vec<_>>();
+    let planner = Planner::default().with_hole(32005..32007);
+    assert_eq!(spans(&planner.plan(&regs)), vec![(32000, 3), (32008, 3)]);
+    let planner = Planner::default().with_hole(32003..32004);
+    assert_eq!(spans(&planner.plan(&regs)), vec![(32000, 3), (32008, 3)]);
+    // holes outside of gaps do not split requests
+    let planner = Planner::default().with_hole(32011..32020);
+    assert_eq!(spans(&planner.plan(&regs)), vec![(32000, 11)]);
+    let planner = Planner::default().with_hole(32001..32002);
+    assert_eq!(spans(&planner.plan(&regs)), vec![(32000, 1), (32002, 9)]);
+    let planner = Planner::default().with_hole(31000..32000);
+    assert_eq!(spans(&planner.plan(&regs)), vec![(32000, 11)]);
+}
+
+#[test]
+fn plan_overlapping() {
+    let regs = [reg(32008, 3), reg(32009, 1), reg(32008, 1), reg(32008, 3)];
+    let regs = regs.iter().collect::<Vec<_>>();
+    let plan = Planner::default().plan(&regs);
+    assert_eq!(spans(&plan), vec![(32008, 3)]);
+    assert_eq!(plan[0].parts, vec![(2, 0), (0, 0), (3, 0), (1, 1)]);
+}
+
+#[test]
+fn plan_end_of_address_space() {
+    let regs = [reg(65530, 6), reg(65500, 2)];
+    let regs = regs.iter().collect::<Vec<_>>();
+    let plan = Planner::default().plan(&regs);
+    assert_eq!(spans(&plan), vec![(65500, 36)]);
+}
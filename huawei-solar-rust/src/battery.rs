@@ -0,0 +1,437 @@
+//! Control of LUNA2000 batteries: working mode, forced charge/discharge and
+//! time-of-use periods.
+
+use std::{fmt::Display, time::Duration};
+
+use crate::{
+    registers::{storage, RegisterError, RegisterType, Type},
+    Error, Inverter,
+};
+
+/// Content of the [WORKING_MODE_SETTINGS](storage::WORKING_MODE_SETTINGS)
+/// register
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum WorkingMode {
+    Adaptive,
+    FixedChargeDischarge,
+    MaximiseSelfConsumption,
+    TimeOfUseLg,
+    FullyFedToGrid,
+    /// Charge and discharge in the [TouPeriods]
+    TimeOfUseLuna2000,
+    /// Code not documented in the register table, kept as read and never
+    /// written
+    Other(u16),
+}
+
+impl WorkingMode {
+    pub const fn code(&self) -> u16 {
+        match self {
+            WorkingMode::Adaptive => 0,
+            WorkingMode::FixedChargeDischarge => 1,
+            WorkingMode::MaximiseSelfConsumption => 2,
+            WorkingMode::TimeOfUseLg => 3,
+            WorkingMode::FullyFedToGrid => 4,
+            WorkingMode::TimeOfUseLuna2000 => 5,
+            WorkingMode::Other(code) => *code,
+        }
+    }
+
+    pub const fn from_code(code: u16) -> WorkingMode {
+        match code {
+            0 => WorkingMode::Adaptive,
+            1 => WorkingMode::FixedChargeDischarge,
+            2 => WorkingMode::MaximiseSelfConsumption,
+            3 => WorkingMode::TimeOfUseLg,
+            4 => WorkingMode::FullyFedToGrid,
+            5 => WorkingMode::TimeOfUseLuna2000,
+            code => WorkingMode::Other(code),
+        }
+    }
+}
+
+impl Display for WorkingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorkingMode::Adaptive => write!(f, "adaptive"),
+            WorkingMode::FixedChargeDischarge => write!(f, "fixed charge/discharge"),
+            WorkingMode::MaximiseSelfConsumption => write!(f, "maximise self consumption"),
+            WorkingMode::TimeOfUseLg => write!(f, "time of use (LG)"),
+            WorkingMode::FullyFedToGrid => write!(f, "fully fed to grid"),
+            WorkingMode::TimeOfUseLuna2000 => write!(f, "time of use (LUNA2000)"),
+            WorkingMode::Other(code) => write!(f, "undocumented mode {}", code),
+        }
+    }
+}
+
+impl RegisterType for WorkingMode {
+    const TYPE: Type = Type::U16;
+
+    fn convert(vec: &[u16]) -> Option<Self> {
+        u16::convert(vec).map(WorkingMode::from_code)
+    }
+
+    fn encode(&self, quantity: usize) -> Option<Vec<u16>> {
+        match self {
+            WorkingMode::Other(_) => None,
+            mode => mode.code().encode(quantity),
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// Days a [TouPeriod] applies to
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Days: u8 {
+        const SUNDAY = 1 << 0;
+        const MONDAY = 1 << 1;
+        const TUESDAY = 1 << 2;
+        const WEDNESDAY = 1 << 3;
+        const THURSDAY = 1 << 4;
+        const FRIDAY = 1 << 5;
+        const SATURDAY = 1 << 6;
+        const WEEKDAYS = Self::MONDAY.bits()
+            | Self::TUESDAY.bits()
+            | Self::WEDNESDAY.bits()
+            | Self::THURSDAY.bits()
+            | Self::FRIDAY.bits();
+        const WEEKEND = Self::SATURDAY.bits() | Self::SUNDAY.bits();
+    }
+}
+
+/// Whether the battery charges or discharges in a [TouPeriod]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ChargeFlag {
+    Charge,
+    Discharge,
+}
+
+/// Single time-of-use period, times in minutes after midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TouPeriod {
+    pub start: u16,
+    /// End of the period, at most 1440
+    pub end: u16,
+    pub flag: ChargeFlag,
+    pub days: Days,
+}
+
+/// Content of the [TIME_OF_USE_PERIODS](storage::TIME_OF_USE_PERIODS)
+/// registers, at most [TouPeriods::MAX] periods.
+///
+/// The registers hold the number of periods followed by three words per
+/// period: start, end and `flag << 8 | days`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TouPeriods(Vec<TouPeriod>);
+
+impl TouPeriods {
+    pub const MAX: usize = 14;
+
+    /// Check `periods` as the inverter would: each period must end after it
+    /// starts and apply to at least one day, periods sharing a day must not
+    /// overlap
+    pub fn new(periods: Vec<TouPeriod>) -> Result<TouPeriods, RegisterError> {
+        if periods.len() > TouPeriods::MAX {
+            return Err(RegisterError::ValueConversion(format!(
+                "{} time-of-use periods, at most {} are supported",
+                periods.len(),
+                TouPeriods::MAX
+            )));
+        }
+        for (i, period) in periods.iter().enumerate() {
+            if period.start >= period.end || period.end > 1440 {
+                return Err(RegisterError::ValueConversion(format!(
+                    "time-of-use period {} from {} to {} min is invalid",
+                    i, period.start, period.end
+                )));
+            }
+            if period.days.is_empty() || !Days::all().contains(period.days) {
+                return Err(RegisterError::ValueConversion(format!(
+                    "time-of-use period {} has no valid days",
+                    i
+                )));
+            }
+            let overlapping = periods[..i].iter().position(|other| {
+                other.days.intersects(period.days)
+                    && other.start < period.end
+                    && period.start < other.end
+            });
+            if let Some(other) = overlapping {
+                return Err(RegisterError::ValueConversion(format!(
+                    "time-of-use periods {} and {} overlap",
+                    other, i
+                )));
+            }
+        }
+        Ok(TouPeriods(periods))
+    }
+
+    pub fn periods(&self) -> &[TouPeriod] {
+        &self.0
+    }
+}
+
+impl RegisterType for TouPeriods {
+    const TYPE: Type = Type::BF;
+
+    fn convert(vec: &[u16]) -> Option<Self> {
+        let (&count, periods) = vec.split_first()?;
+        let periods = periods.chunks_exact(3).take(count.into());
+        if periods.len() != count as usize {
+            return None;
+        }
+        Some(TouPeriods(
+            periods
+                .map(|words| TouPeriod {
+                    start: words[0],
+                    end: words[1],
+                    flag: match words[2] >> 8 {
+                        0 => ChargeFlag::Charge,
+                        _ => ChargeFlag::Discharge,
+                    },
+                    days: Days::from_bits_retain(words[2] as u8),
+                })
+                .collect(),
+        ))
+    }
+
+    fn encode(&self, quantity: usize) -> Option<Vec<u16>> {
+        let mut words = vec![self.0.len() as u16];
+        for period in &self.0 {
+            let flag = match period.flag {
+                ChargeFlag::Charge => 0,
+                ChargeFlag::Discharge => 1,
+            };
+            words.extend([
+                period.start,
+                period.end,
+                flag << 8 | period.days.bits() as u16,
+            ]);
+        }
+        if words.len() > quantity {
+            return None;
+        }
+        words.resize(quantity, 0);
+        Some(words)
+    }
+}
+
+/// Direction of a forced charge or discharge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Force {
+    Charge,
+    Discharge,
+}
+
+impl Inverter {
+    /// Set the working mode of the batteries
+    pub fn set_working_mode(&mut self, mode: WorkingMode) -> Result<(), Error> {
+        self.write_typed(&storage::WORKING_MODE_SETTINGS, &mode)
+    }
+
+    /// Charge or discharge the batteries with `power` in W for `duration`,
+    /// regardless of the working mode.
+    ///
+    /// `power` is checked against the maximum charge or discharge power
+    /// of the batteries and `duration` must be between 1 minute and 24
+    /// hours, in whole minutes.
+    pub fn force(&mut self, force: Force, power: u32, duration: Duration) -> Result<(), Error> {
+        let minutes = duration.as_secs() / 60;
+        if !duration.as_secs().is_multiple_of(60) || !(1..=1440).contains(&minutes) {
+            return Err(RegisterError::ValueConversion(format!(
+                "forced charge/discharge duration {:?} is invalid",
+                duration
+            ))
+            .into());
+        }
+        let (maximum, power_reg, command) = match force {
+            Force::Charge => (
+                self.read(&storage::MAXIMUM_CHARGE_POWER)?,
+                &storage::FORCIBLE_CHARGE_POWER,
+                1,
+            ),
+            Force::Discharge => (
+                self.read(&storage::MAXIMUM_DISCHARGE_POWER)?,
+                &storage::FORCIBLE_DISCHARGE_POWER,
+                2,
+            ),
+        };
+        if power == 0 || power > maximum {
+            return Err(RegisterError::ValueConversion(format!(
+                "forced charge/discharge power {} W is not between 1 and {} W",
+                power, maximum
+            ))
+            .into());
+        }
+
+        // mode 0: stop after the period rather than at a target SOC
+        self.write_typed(&storage::FORCIBLE_CHARGE_DISCHARGE_MODE, &0)?;
+        self.write_typed(&storage::FORCED_CHARGE_DISCHARGE_PERIOD, &(minutes as u16))?;
+        self.write_typed(power_reg, &power)?;
+        self.write_typed(&storage::FORCIBLE_CHARGE_DISCHARGE, &command)
+    }
+
+    /// End a forced charge or discharge started with [Inverter::force]
+    pub fn stop_force(&mut self) -> Result<(), Error> {
+        self.write_typed(&storage::FORCIBLE_CHARGE_DISCHARGE, &0)
+    }
+
+    /// Allow or forbid charging the batteries from the grid
+    pub fn set_charge_from_grid(&mut self, enabled: bool) -> Result<(), Error> {
+        self.write_typed(&storage::CHARGE_FROM_GRID, &u16::from(enabled))
+    }
+
+    /// Stop charging from the grid at `soc` percent, between 20 and 100
+    pub fn set_grid_charge_cutoff_soc(&mut self, soc: f64) -> Result<(), Error> {
+        if !(20.0..=100.0).contains(&soc) {
+            return Err(RegisterError::ValueConversion(format!(
+                "grid charge cut-off SOC {}% is not between 20% and 100%",
+                soc
+            ))
+            .into());
+        }
+        let value = storage::GRID_CHARGE_CUTOFF_SOC.value_from_float(soc)?;
+        self.write(&storage::GRID_CHARGE_CUTOFF_SOC, value)
+    }
+
+    /// Replace the time-of-use periods, used in
+    /// [WorkingMode::TimeOfUseLuna2000]
+    pub fn set_tou_periods(&mut self, periods: &TouPeriods) -> Result<(), Error> {
+        self.write_typed(&storage::TIME_OF_USE_PERIODS, periods)
+    }
+}
+
+#[cfg(test)]
+fn period(start: u16, end: u16, flag: ChargeFlag, days: Days) -> TouPeriod {
+    TouPeriod {
+        start,
+        end,
+        flag,
+        days,
+    }
+}
+
+#[test]
+fn working_mode_codes() {
+    for code in 0..8 {
+        let mode = WorkingMode::from_code(code);
+        assert_eq!(mode.code(), code);
+        assert_eq!(WorkingMode::convert(&[code]), Some(mode));
+    }
+    assert_eq!(WorkingMode::TimeOfUseLuna2000.encode(1), Some(vec![0x0005]));
+    assert_eq!(WorkingMode::Other(7).encode(1), None);
+    assert_eq!(
+        WorkingMode::MaximiseSelfConsumption.to_string(),
+        "maximise self consumption"
+    );
+}
+
+#[test]
+fn tou_periods_roundtrip() {
+    let periods = TouPeriods::new(vec![
+        period(0, 360, ChargeFlag::Charge, Days::all()),
+        period(1020, 1440, ChargeFlag::Discharge, Days::WEEKDAYS),
+    ])
+    .unwrap();
+    let words = periods.encode(43).unwrap();
+    assert_eq!(words.len(), 43);
+    assert_eq!(words[..7], [2, 0, 360, 0x007F, 1020, 1440, 0x013E]);
+    assert!(words[7..].iter().all(|&w| w == 0));
+    assert_eq!(TouPeriods::convert(&words), Some(periods));
+
+    assert_eq!(TouPeriods::default().encode(43).unwrap(), vec![0; 43]);
+    // count beyond the words read
+    assert_eq!(TouPeriods::convert(&[2, 0, 60, 1]), None);
+}
+
+#[test]
+fn tou_periods_validation() {
+    let charge = |start, end, days| period(start, end, ChargeFlag::Charge, days);
+    assert!(TouPeriods::new(vec![charge(60, 60, Days::all())]).is_err());
+    assert!(TouPeriods::new(vec![charge(60, 1441, Days::all())]).is_err());
+    assert!(TouPeriods::new(vec![charge(0, 60, Days::empty())]).is_err());
+    assert!(TouPeriods::new(vec![charge(0, 60, Days::from_bits_retain(0x80))]).is_err());
+    // overlapping only on days the periods share
+    assert!(TouPeriods::new(vec![
+        charge(0, 120, Days::WEEKDAYS),
+        charge(60, 180, Days::MONDAY),
+    ])
+    .is_err());
+    assert!(TouPeriods::new(vec![
+        charge(0, 120, Days::WEEKDAYS),
+        charge(60, 180, Days::WEEKEND),
+        charge(120, 180, Days::WEEKDAYS),
+    ])
+    .is_ok());
+    let many = (0..15).map(|i| charge(i * 60, i * 60 + 30, Days::all()));
+    assert!(TouPeriods::new(many.collect()).is_err());
+}
+
+#[test]
+fn force_charge_sequence() {
+    let (mut inverter, server) = crate::tcp_inverter(|stream| {
+        let exchanges: [(&[u8], &[u8]); 5] = [
+            (
+                &[0x03, 0x90, 0xB6, 0x00, 0x02],
+                &[0x03, 0x04, 0x00, 0x00, 0x13, 0x88],
+            ),
+            (
+                &[0x06, 0xB8, 0x8E, 0x00, 0x00],
+                &[0x06, 0xB8, 0x8E, 0x00, 0x00],
+            ),
+            (
+                &[0x06, 0xB7, 0xEB, 0x00, 0x3C],
+                &[0x06, 0xB7, 0xEB, 0x00, 0x3C],
+            ),
+            (
+                &[0x10, 0xB8, 0x8F, 0x00, 0x02, 0x04, 0x00, 0x00, 0x07, 0xD0],
+                &[0x10, 0xB8, 0x8F, 0x00, 0x02],
+            ),
+            (
+                &[0x06, 0xB7, 0xFC, 0x00, 0x01],
+                &[0x06, 0xB7, 0xFC, 0x00, 0x01],
+            ),
+        ];
+        for (request, response) in exchanges {
+            assert_eq!(crate::tcp::reply(stream, response), request);
+        }
+        // power above the maximum is rejected after reading it
+        crate::tcp::reply(stream, &[0x03, 0x04, 0x00, 0x00, 0x13, 0x88]);
+    });
+    inverter
+        .force(Force::Charge, 2000, Duration::from_secs(3600))
+        .unwrap();
+    assert!(matches!(
+        inverter.force(Force::Charge, 6000, Duration::from_secs(3600)),
+        Err(Error::Register(_))
+    ));
+    // invalid arguments never reach the inverter
+    for duration in [0, 90, 1441 * 60] {
+        assert!(matches!(
+            inverter.force(Force::Discharge, 2000, Duration::from_secs(duration)),
+            Err(Error::Register(_))
+        ));
+    }
+    assert!(matches!(
+        inverter.set_grid_charge_cutoff_soc(10.0),
+        Err(Error::Register(_))
+    ));
+    assert!(matches!(
+        inverter.set_working_mode(WorkingMode::Other(9)),
+        Err(Error::Register(_))
+    ));
+    server.join().unwrap();
+}
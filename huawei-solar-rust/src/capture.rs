@@ -0,0 +1,480 @@
+//! Recording of the Modbus exchanges of a
+//! [Connection](crate::Connection) and their replay, turning odd behaviour
+//! captured on site into deterministic tests.
+//!
+//! A capture is a text file with one exchange per line:
+//!
+//! ```text
+//! # at_ms duration_ms unit_id request outcome
+//! 1520 12 1 037d100002 ok 03040fa00339
+//! 1533 3 1 0675fe003c exception 02
+//! 2040 5000 1 0375300001 io TimedOut
+//! 7041 0 1 0375300001 invalid_response
+//! ```
+//!
+//! PDUs are hex encoded, `at_ms` is the start of the exchange since the
+//! recording started and `duration_ms` the time until the response or
+//! error. Lines starting with `#` are comments, so captures can be
+//! annotated before they are attached to a bug report.
+//!
+//! # Examples
+//! ```no_run
+//! # use std::{fs::File, io::BufReader};
+//! # use huawei_solar::{registers::*, Connection};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let connection = Connection::tcp(Some("192.168.200.1"), None, None, None, None)?;
+//! connection.record(File::create("capture.txt")?)?;
+//! let power: i32 = connection.device(1).read(&ACTIVE_POWER)?;
+//!
+//! // later, without the inverter
+//! let replay = Connection::replay(BufReader::new(File::open("capture.txt")?))?;
+//! assert_eq!(replay.device(1).read(&ACTIVE_POWER)?, power);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fmt::{Display, Write as _},
+    io::{self, BufRead, ErrorKind, Write},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use modbus::Result;
+
+use crate::{
+    pdu::{self, Transact},
+    Client, Error,
+};
+
+/// I/O errors kept apart in captures, anything else is replayed as
+/// [ErrorKind::Other]
+const IO_ERROR_KINDS: [ErrorKind; 9] = [
+    ErrorKind::TimedOut,
+    ErrorKind::WouldBlock,
+    ErrorKind::UnexpectedEof,
+    ErrorKind::ConnectionReset,
+    ErrorKind::ConnectionAborted,
+    ErrorKind::ConnectionRefused,
+    ErrorKind::NotConnected,
+    ErrorKind::BrokenPipe,
+    ErrorKind::Other,
+];
+
+/// How the device answered a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// PDU of the response
+    Response(Vec<u8>),
+    /// Modbus exception code
+    Exception(u8),
+    /// Timeout or loss of the connection
+    Io(ErrorKind),
+    /// Malformed response frame
+    InvalidResponse,
+}
+
+impl Outcome {
+    fn of(result: &Result<Vec<u8>>) -> Outcome {
+        match result {
+            Ok(pdu) => Outcome::Response(pdu.clone()),
+            Err(modbus::Error::Exception(code)) => (0..=u8::MAX)
+                .find(|&byte| pdu::exception(byte).as_ref() == Some(code))
+                .map_or(Outcome::InvalidResponse, Outcome::Exception),
+            Err(modbus::Error::Io(err)) if IO_ERROR_KINDS.contains(&err.kind()) => {
+                Outcome::Io(err.kind())
+            }
+            Err(modbus::Error::Io(_)) => Outcome::Io(ErrorKind::Other),
+            Err(_) => Outcome::InvalidResponse,
+        }
+    }
+
+    fn result(&self) -> Result<Vec<u8>> {
+        match self {
+            Outcome::Response(pdu) => Ok(pdu.clone()),
+            Outcome::Exception(code) => Err(pdu::exception(*code)
+                .map_or(modbus::Error::InvalidResponse, modbus::Error::Exception)),
+            Outcome::Io(kind) => Err(modbus::Error::Io(io::Error::new(
+                *kind,
+                "replayed from capture",
+            ))),
+            Outcome::InvalidResponse => Err(modbus::Error::InvalidResponse),
+        }
+    }
+}
+
+/// A request and its outcome, one line of a capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    /// Start of the exchange since the recording started
+    pub at: Duration,
+    pub duration: Duration,
+    pub unit_id: u8,
+    /// PDU of the request
+    pub request: Vec<u8>,
+    pub outcome: Outcome,
+}
+
+impl Display for Exchange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} ",
+            self.at.as_millis(),
+            self.duration.as_millis(),
+            self.unit_id,
+            hex(&self.request)
+        )?;
+        match &self.outcome {
+            Outcome::Response(pdu) => write!(f, "ok {}", hex(pdu)),
+            Outcome::Exception(code) => write!(f, "exception {:02x}", code),
+            Outcome::Io(kind) => write!(f, "io {:?}", kind),
+            Outcome::InvalidResponse => write!(f, "invalid_response"),
+        }
+    }
+}
+
+impl FromStr for Exchange {
+    type Err = String;
+
+    fn from_str(line: &str) -> std::result::Result<Self, Self::Err> {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let millis = |field: &str| {
+            field
+                .parse()
+                .map(Duration::from_millis)
+                .map_err(|_| format!("invalid time {}", field))
+        };
+        let (at, duration, unit_id, request, outcome) = match fields[..] {
+            [at, duration, unit_id, request, ref outcome @ ..] if !outcome.is_empty() => {
+                (at, duration, unit_id, request, outcome)
+            }
+            _ => return Err(String::from("expected at least 5 fields")),
+        };
+        let outcome = match outcome {
+            ["ok", pdu] => Outcome::Response(unhex(pdu)?),
+            ["exception", code] => Outcome::Exception(
+                u8::from_str_radix(code, 16)
+                    .map_err(|_| format!("invalid exception code {}", code))?,
+            ),
+            ["io", kind] => Outcome::Io(
+                IO_ERROR_KINDS
+                    .into_iter()
+                    .find(|known| format!("{:?}", known) == *kind)
+                    .unwrap_or(ErrorKind::Other),
+            ),
+            ["invalid_response"] => Outcome::InvalidResponse,
+            _ => return Err(format!("invalid outcome {}", outcome.join(" "))),
+        };
+        Ok(Exchange {
+            at: millis(at)?,
+            duration: millis(duration)?,
+            unit_id: unit_id
+                .parse()
+                .map_err(|_| format!("invalid unit ID {}", unit_id))?,
+            request: unhex(request)?,
+            outcome,
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, byte| {
+        let _ = write!(s, "{:02x}", byte);
+        s
+    })
+}
+
+fn unhex(s: &str) -> std::result::Result<Vec<u8>, String> {
+    if !s.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits in {}", s));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            s.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("invalid hex {}", s))
+        })
+        .collect()
+}
+
+/// Read the exchanges of a capture
+pub fn parse<R: BufRead>(capture: R) -> std::result::Result<Vec<Exchange>, Error> {
+    let mut exchanges = Vec::new();
+    for (n, line) in capture.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let exchange = line
+            .parse()
+            .map_err(|reason| Error::Capture(format!("line {}: {}", n + 1, reason)))?;
+        exchanges.push(exchange);
+    }
+    Ok(exchanges)
+}
+
+/// Transport logging each exchange of the wrapped one
+pub(crate) struct Recorder {
+    inner: Box<Client>,
+    /// `None` once writing to the log failed, recording stops then
+    log: Option<Box<dyn Write + Send>>,
+    /// First write error, reported by [Recorder::close]
+    error: Option<io::Error>,
+    start: Instant,
+    uid: u8,
+}
+
+impl Recorder {
+    /// Fails if the header cannot be written, handing `inner` back
+    pub(crate) fn new(
+        inner: Client,
+        mut log: Box<dyn Write + Send>,
+    ) -> std::result::Result<Recorder, (Client, io::Error)> {
+        let header =
+            writeln!(log, "# at_ms duration_ms unit_id request outcome").and_then(|_| log.flush());
+        if let Err(err) = header {
+            return Err((inner, err));
+        }
+        Ok(Recorder {
+            inner: Box::new(inner),
+            log: Some(log),
+            error: None,
+            start: Instant::now(),
+            uid: 0,
+        })
+    }
+
+    /// Also fails if recording stopped early
+    pub(crate) fn close(&mut self) -> Result<()> {
+        let logged = match (self.error.take(), &mut self.log) {
+            (Some(err), _) => Err(err),
+            (None, Some(log)) => log.flush(),
+            (None, None) => Ok(()),
+        };
+        self.inner.close()?;
+        Ok(logged?)
+    }
+}
+
+impl Transact for Recorder {
+    fn transact(&mut self, pdu: &[u8]) -> Result<Vec<u8>> {
+        self.inner.modbus().set_uid(self.uid);
+        let at = self.start.elapsed();
+        let result = self.inner.transact(pdu);
+        let exchange = Exchange {
+            at,
+            duration: self.start.elapsed() - at,
+            unit_id: self.uid,
+            request: pdu.to_vec(),
+            outcome: Outcome::of(&result),
+        };
+        if let Some(log) = &mut self.log {
+            // flushed right away, the process may well crash on what follows
+            if let Err(err) = writeln!(log, "{}", exchange).and_then(|_| log.flush()) {
+                // the exchange succeeded regardless of its record
+                self.log = None;
+                self.error = Some(err);
+            }
+        }
+        result
+    }
+}
+
+pdu::modbus_client!(Recorder);
+
+/// Transport answering from a capture instead of a device.
+///
+/// Requests must come in the recorded order, timing is not reproduced.
+pub(crate) struct Replay {
+    exchanges: VecDeque<Exchange>,
+    uid: u8,
+}
+
+impl Replay {
+    pub(crate) fn new(exchanges: Vec<Exchange>) -> Replay {
+        Replay {
+            exchanges: exchanges.into(),
+            uid: 0,
+        }
+    }
+}
+
+impl Transact for Replay {
+    fn transact(&mut self, pdu: &[u8]) -> Result<Vec<u8>> {
+        let Some(exchange) = self.exchanges.pop_front() else {
+            return Err(modbus::Error::Io(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "end of capture",
+            )));
+        };
+        if exchange.request != pdu || exchange.unit_id != self.uid {
+            return Err(modbus::Error::Io(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "request {} to unit {} does not match capture at {} ms, {} to unit {}",
+                    hex(pdu),
+                    self.uid,
+                    exchange.at.as_millis(),
+                    hex(&exchange.request),
+                    exchange.unit_id
+                ),
+            )));
+        }
+        exchange.outcome.result()
+    }
+}
+
+pdu::modbus_client!(Replay);
+
+#[test]
+fn exchange_lines() {
+    let lines = [
+        "1520 12 1 037d100002 ok 03040fa00339",
+        "1533 3 1 0675fe003c exception 02",
+        "2040 5000 1 0375300001 io TimedOut",
+        "7041 0 1 0375300001 invalid_response",
+    ];
+    for line in lines {
+        assert_eq!(line.parse::<Exchange>().unwrap().to_string(), line);
+    }
+    assert_eq!(
+        lines[0].parse::<Exchange>().unwrap(),
+        Exchange {
+            at: Duration::from_millis(1520),
+            duration: Duration::from_millis(12),
+            unit_id: 1,
+            request: vec![0x03, 0x7D, 0x10, 0x00, 0x02],
+            outcome: Outcome::Response(vec![0x03, 0x04, 0x0F, 0xA0, 0x03, 0x39]),
+        }
+    );
+    assert_eq!(
+        "0 0 1 03 io Interrupted"
+            .parse::<Exchange>()
+            .unwrap()
+            .outcome,
+        Outcome::Io(ErrorKind::Other)
+    );
+    for line in [
+        "0 0 1 037d100002",
+        "0 0 1 037d10000 ok 03",
+        "0 0 256 03 ok 03",
+        "0 0 1 03 exception",
+        "0 0 1 03 done",
+    ] {
+        assert!(line.parse::<Exchange>().is_err(), "{}", line);
+    }
+
+    let capture = format!("# annotated\n\n{}\n{}\n", lines[0], lines[1]);
+    assert_eq!(parse(capture.as_bytes()).unwrap().len(), 2);
+    assert!(matches!(
+        parse(&b"# annotated\nbogus\n"[..]),
+        Err(Error::Capture(reason)) if reason.starts_with("line 2:")
+    ));
+}
+
+#[test]
+fn record_and_replay() {
+    use crate::registers::{ACTIVE_POWER, MODEL_ID, TIME_ZONE};
+
+    let (mut inverter, server) = crate::tcp_inverter(|stream| {
+        crate::tcp::reply(stream, &[0x03, 0x04, 0x00, 0x00, 0x0F, 0xA0]);
+        crate::tcp::reply(stream, &[0x86, 0x02]);
+        // short response
+        crate::tcp::reply(stream, &[0x03, 0x01, 0x00]);
+        // then the connection drops
+    });
+    let path = std::env::temp_dir().join(format!("huawei-solar-capture-{}", std::process::id()));
+    inverter
+        .connection()
+        .record(std::fs::File::create(&path).unwrap())
+        .unwrap();
+    assert_eq!(inverter.read(&ACTIVE_POWER).unwrap(), 4000);
+    assert!(matches!(
+        inverter.write(&TIME_ZONE, crate::registers::Value::I16(60)),
+        Err(Error::Modbus(modbus::Error::Exception(
+            modbus::ExceptionCode::IllegalDataAddress
+        )))
+    ));
+    assert!(matches!(
+        inverter.read(&MODEL_ID),
+        Err(Error::Modbus(modbus::Error::InvalidData(_)))
+    ));
+    server.join().unwrap();
+    let Err(Error::Modbus(modbus::Error::Io(dropped))) = inverter.read(&MODEL_ID) else {
+        panic!("connection should be closed");
+    };
+    inverter.connection().close().ok();
+
+    let capture = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(capture.lines().count(), 5);
+    let mut replay = crate::Connection::replay(capture.as_bytes())
+        .unwrap()
+        .device(1);
+    assert_eq!(replay.read(&ACTIVE_POWER).unwrap(), 4000);
+    assert!(matches!(
+        replay.write(&TIME_ZONE, crate::registers::Value::I16(60)),
+        Err(Error::Modbus(modbus::Error::Exception(
+            modbus::ExceptionCode::IllegalDataAddress
+        )))
+    ));
+    assert!(matches!(
+        replay.read(&MODEL_ID),
+        Err(Error::Modbus(modbus::Error::InvalidData(_)))
+    ));
+    assert!(matches!(
+        replay.read(&MODEL_ID),
+        Err(Error::Modbus(modbus::Error::Io(err))) if err.kind() == dropped.kind()
+    ));
+    // past the end of the capture
+    assert!(matches!(
+        replay.read(&MODEL_ID),
+        Err(Error::Modbus(modbus::Error::Io(err))) if err.kind() == ErrorKind::UnexpectedEof
+    ));
+
+    // requests deviating from the capture
+    let mut replay = crate::Connection::replay(capture.as_bytes())
+        .unwrap()
+        .device(2);
+    assert!(matches!(
+        replay.read(&ACTIVE_POWER),
+        Err(Error::Modbus(modbus::Error::Io(err))) if err.kind() == ErrorKind::InvalidInput
+    ));
+}
+
+#[test]
+fn record_to_full_log() {
+    use crate::registers::ACTIVE_POWER;
+
+    /// Accepts the header only
+    struct Full(usize);
+    impl Write for Full {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.0 > 0 {
+                return Err(io::Error::other("disk full"));
+            }
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let (mut inverter, server) = crate::tcp_inverter(|stream| {
+        crate::tcp::reply(stream, &[0x03, 0x04, 0x00, 0x00, 0x0F, 0xA0]);
+        crate::tcp::reply(stream, &[0x03, 0x04, 0x00, 0x00, 0x1F, 0x40]);
+    });
+    inverter.connection().record(Full(0)).unwrap();
+    assert_eq!(inverter.read(&ACTIVE_POWER).unwrap(), 4000);
+    assert_eq!(inverter.read(&ACTIVE_POWER).unwrap(), 8000);
+    server.join().unwrap();
+    assert!(matches!(
+        inverter.connection().close(),
+        Err(modbus::Error::Io(err)) if err.to_string() == "disk full"
+    ));
+}
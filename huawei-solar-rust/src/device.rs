@@ -0,0 +1,281 @@
+//! Identification of the inverter model and the features it implements.
+//!
+//! The capabilities are derived from the model name, e.g.
+//! `SUN2000-10KTL-M1`: the series suffix after the last `-` tells single
+//! phase (`L…`) from three phase (`M…`) inverters, the residential hybrid
+//! series accept LUNA2000 batteries and a power meter on their RS485 port.
+//! Models the heuristic does not recognise get no optional features.
+//!
+//! `MODEL_ID` is read but not used: Huawei publishes no table of the IDs,
+//! and a guessed table would misreport capabilities where the name does
+//! not.
+
+use std::fmt::Display;
+
+use crate::{
+    registers::{self, Register, RegisterType},
+    Error, Inverter,
+};
+
+/// Series of the residential hybrid inverters which control batteries
+const HYBRID_SERIES: [&str; 5] = ["L1", "LC0", "M1", "MAP0", "MB0"];
+/// Commercial inverters share series names with the residential ones but
+/// start above this rated power
+const MAX_RESIDENTIAL_POWER: u32 = 30_000;
+
+/// Grid connection of the inverter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Phases {
+    Single,
+    Three,
+}
+
+/// Features implemented by an inverter model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities {
+    /// `None` if the series is unknown
+    pub phases: Option<Phases>,
+    pub pv_strings: u16,
+    pub mpp_trackers: u16,
+    /// LUNA2000 batteries, [storage](registers::storage) registers
+    pub battery: bool,
+    /// Smart power meter, [meter](registers::meter) registers
+    pub meter: bool,
+}
+
+impl Capabilities {
+    /// Derive the capabilities from the model name and rated power in W,
+    /// `pv_strings` is limited to the 24 strings of the register table
+    pub fn from_model(model: &str, rated_power: u32, pv_strings: u16, mpp_trackers: u16) -> Self {
+        let series = model
+            .strip_prefix("SUN2000-")
+            .and_then(|rest| rest.rsplit_once('-'))
+            .map(|(_, series)| series);
+        let phases = match series.and_then(|series| series.chars().next()) {
+            Some('L') => Some(Phases::Single),
+            Some('M') => Some(Phases::Three),
+            _ => None,
+        };
+        let residential = series.is_some() && rated_power <= MAX_RESIDENTIAL_POWER;
+        Capabilities {
+            phases,
+            pv_strings: pv_strings.min(registers::PV_STRINGS.len() as u16),
+            mpp_trackers,
+            battery: residential && series.is_some_and(|series| HYBRID_SERIES.contains(&series)),
+            meter: residential,
+        }
+    }
+
+    /// Whether the model implements all of `reg`.
+    ///
+    /// Registers of the battery, meter, PV strings beyond
+    /// [pv_strings](Capabilities::pv_strings) and phases B and C of single
+    /// phase inverters are not implemented, anything else is assumed to be.
+    pub fn supports(&self, reg: &Register) -> bool {
+        let overlaps = |(start, end): (u16, u16)| {
+            reg.address < end && start < reg.address.saturating_add(reg.quantity.into())
+        };
+        if !self.battery && BATTERY_RANGES.into_iter().any(overlaps) {
+            return false;
+        }
+        if !self.meter && overlaps(METER_RANGE) {
+            return false;
+        }
+        // the fields are public, the strings may not be limited yet
+        let pv_strings = self.pv_strings.min(registers::PV_STRINGS.len() as u16);
+        let pv_end = registers::PV1_VOLTAGE.address + 2 * pv_strings;
+        if overlaps((pv_end, registers::INPUT_POWER.address)) {
+            return false;
+        }
+        if self.phases == Some(Phases::Single) {
+            return !PHASE_B_C_REGISTERS
+                .into_iter()
+                .any(|other| overlaps((other.address, other.address + other.quantity as u16)));
+        }
+        true
+    }
+}
+
+/// Address ranges `[start, end)` of the battery registers, the meter
+/// registers in between excluded
+const BATTERY_RANGES: [(u16, u16); 4] = [
+    (37000, 37100),
+    (37700, 37800),
+    (38200, 38500),
+    (47000, 47300),
+];
+const METER_RANGE: (u16, u16) = (37100, 37139);
+const PHASE_B_C_REGISTERS: [&Register<'static>; 6] = [
+    registers::LINE_VOLTAGE_B_C.erased(),
+    registers::LINE_VOLTAGE_C_A.erased(),
+    registers::PHASE_VOLTAGE_B.erased(),
+    registers::PHASE_VOLTAGE_C.erased(),
+    registers::PHASE_CURRENT_B.erased(),
+    registers::PHASE_CURRENT_C.erased(),
+];
+
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.phases {
+            Some(Phases::Single) => write!(f, "single phase")?,
+            Some(Phases::Three) => write!(f, "three phase")?,
+            None => write!(f, "unknown phases")?,
+        }
+        write!(
+            f,
+            ", {} PV strings, {} MPP trackers",
+            self.pv_strings, self.mpp_trackers
+        )?;
+        if self.battery {
+            write!(f, ", battery")?;
+        }
+        if self.meter {
+            write!(f, ", meter")?;
+        }
+        Ok(())
+    }
+}
+
+/// Identity of an inverter as returned by [Inverter::identify]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceInfo {
+    pub model: String,
+    pub serial_number: String,
+    pub part_number: String,
+    /// Reported as is, the capabilities are derived from the name only
+    pub model_id: u16,
+    /// Rated power in kW
+    pub rated_power: f64,
+    pub capabilities: Capabilities,
+}
+
+impl Inverter {
+    /// Read the model of the inverter and derive its [Capabilities]
+    pub fn identify(&mut self) -> Result<DeviceInfo, Error> {
+        let regs: [&Register; 7] = [
+            &registers::MODEL,
+            &registers::SN,
+            &registers::PN,
+            &registers::MODEL_ID,
+            &registers::NUMBER_OF_PV_STRINGS,
+            &registers::NUMBER_OF_MPP_TRACKERS,
+            &registers::RATED_POWER,
+        ];
+        let values = self.read_batch_raw(&regs)?;
+        let model = String::convert(&values[0]).ok_or(Error::Conversion)?;
+        let rated_power = u32::convert(&values[6]).ok_or(Error::Conversion)?;
+        let pv_strings = u16::convert(&values[4]).ok_or(Error::Conversion)?;
+        let mpp_trackers = u16::convert(&values[5]).ok_or(Error::Conversion)?;
+        Ok(DeviceInfo {
+            capabilities: Capabilities::from_model(&model, rated_power, pv_strings, mpp_trackers),
+            model,
+            serial_number: String::convert(&values[1]).ok_or(Error::Conversion)?,
+            part_number: String::convert(&values[2]).ok_or(Error::Conversion)?,
+            model_id: u16::convert(&values[3]).ok_or(Error::Conversion)?,
+            rated_power: registers::RATED_POWER.gain.apply(rated_power.into()),
+        })
+    }
+}
+
+#[test]
+fn capabilities_from_model() {
+    let caps = Capabilities::from_model("SUN2000-10KTL-M1", 10000, 2, 2);
+    assert_eq!(caps.phases, Some(Phases::Three));
+    assert!(caps.battery && caps.meter);
+    let caps = Capabilities::from_model("SUN2000-4.6KTL-L1", 4600, 2, 2);
+    assert_eq!(caps.phases, Some(Phases::Single));
+    assert!(caps.battery);
+    let caps = Capabilities::from_model("SUN2000-20KTL-M0", 20000, 4, 2);
+    assert_eq!(caps.phases, Some(Phases::Three));
+    assert!(!caps.battery && caps.meter);
+    // commercial series
+    let caps = Capabilities::from_model("SUN2000-100KTL-M1", 100000, 20, 10);
+    assert!(!caps.battery && !caps.meter);
+    let caps = Capabilities::from_model("SUN3000", 10000, 2, 2);
+    assert_eq!(caps.phases, None);
+    assert!(!caps.battery && !caps.meter);
+    assert_eq!(
+        Capabilities::from_model("SUN2000-10KTL-M1", 10000, 2, 2).to_string(),
+        "three phase, 2 PV strings, 2 MPP trackers, battery, meter"
+    );
+}
+
+#[test]
+fn supported_registers() {
+    use registers::{meter, storage};
+    let three_phase = Capabilities::from_model("SUN2000-20KTL-M0", 20000, 4, 2);
+    assert!(three_phase.supports(&registers::PV4_CURRENT));
+    assert!(!three_phase.supports(&registers::PV5_VOLTAGE));
+    assert!(three_phase.supports(&registers::INPUT_POWER));
+    assert!(three_phase.supports(&registers::PHASE_CURRENT_C));
+    assert!(three_phase.supports(&meter::ACTIVE_POWER));
+    assert!(!three_phase.supports(&storage::STATE_OF_CAPACITY));
+    assert!(!three_phase.supports(&storage::TIME_OF_USE_PERIODS));
+    assert!(!three_phase.supports(&storage::unit_2_pack_3::MINIMUM_TEMPERATURE));
+    assert!(three_phase.supports(&registers::ACTIVE_POWER_CONTROL_MODE));
+
+    let single_phase = Capabilities::from_model("SUN2000-5KTL-L1", 5000, 2, 2);
+    assert!(single_phase.supports(&storage::STATE_OF_CAPACITY));
+    assert!(single_phase.supports(&registers::PHASE_VOLTAGE_A));
+    assert!(!single_phase.supports(&registers::PHASE_VOLTAGE_B));
+    assert!(!single_phase.supports(&registers::PHASE_CURRENT_C));
+    // registers spanning a supported and an unsupported one
+    let span = Register {
+        quantity: 2,
+        ..*registers::PV2_CURRENT
+    };
+    assert!(!single_phase.supports(&span));
+
+    // a garbage string count covers all strings rather than overflowing
+    let garbage = Capabilities::from_model("SUN2000-20KTL-M0", 20000, u16::MAX, 2);
+    assert_eq!(garbage.pv_strings, 24);
+    assert!(garbage.supports(&registers::PV24_CURRENT));
+    let unlimited = Capabilities {
+        pv_strings: u16::MAX,
+        ..three_phase
+    };
+    assert!(unlimited.supports(&registers::PV24_CURRENT));
+    assert!(unlimited.supports(&registers::INPUT_POWER));
+}
+
+#[test]
+fn identify_inverter() {
+    let (mut inverter, server) = crate::tcp_inverter(|stream| {
+        let mut words = [0u16; 75];
+        let mut put_str = |offset: usize, s: &str| {
+            let mut bytes = s.as_bytes().to_vec();
+            bytes.resize(bytes.len() + bytes.len() % 2, 0);
+            for (i, pair) in bytes.chunks(2).enumerate() {
+                words[offset + i] = u16::from_be_bytes([pair[0], pair[1]]);
+            }
+        };
+        put_str(0, "SUN2000-5KTL-L1");
+        put_str(15, "HV2140012345");
+        put_str(25, "01074356");
+        words[70] = 428;
+        words[71] = 2;
+        words[72] = 2;
+        words[74] = 5000;
+        let mut response = vec![0x03, 150];
+        response.extend(words.iter().flat_map(|w| w.to_be_bytes()));
+        let request = crate::tcp::reply(stream, &response);
+        assert_eq!(request, [0x03, 0x75, 0x30, 0x00, 75]);
+    });
+    let info = inverter.identify().unwrap();
+    assert_eq!(info.model, "SUN2000-5KTL-L1");
+    assert_eq!(info.serial_number, "HV2140012345");
+    assert_eq!(info.part_number, "01074356");
+    assert_eq!(info.model_id, 428);
+    assert_eq!(info.rated_power, 5.0);
+    assert_eq!(info.capabilities.phases, Some(Phases::Single));
+    assert_eq!(info.capabilities.pv_strings, 2);
+    assert!(info.capabilities.battery);
+    server.join().unwrap();
+}
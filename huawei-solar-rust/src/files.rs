@@ -0,0 +1,300 @@
+//! File upload with the Huawei custom function 0x41 and parsers for the
+//! uploaded files.
+//!
+//! A transfer takes three sub-commands, all framed as
+//! `0x41 | sub-command | byte count | data`:
+//!
+//! 1. start upload (0x05) with the file type and its parameters, answered
+//!    with the file length
+//! 2. upload data (0x06) for frames 0, 1, ... until the file is complete
+//! 3. complete upload (0x0C), answered with the CRC of the file
+
+use crate::{rtu::crc16, Error};
+
+/// Huawei custom function code
+const FUNCTION: u8 = 0x41;
+const START_UPLOAD: u8 = 0x05;
+const UPLOAD_DATA: u8 = 0x06;
+const COMPLETE_UPLOAD: u8 = 0x0C;
+
+/// Longest file accepted, the length is announced by the inverter
+const MAX_FILE_LEN: usize = 4 * 1024 * 1024;
+/// Memory reserved for the file before its frames arrive
+const PREALLOCATE_LEN: usize = 64 * 1024;
+
+/// Daily energy yield history, see [parse_yield_history]
+pub const DAILY_YIELD: u8 = 0x49;
+/// Monthly energy yield history, see [parse_yield_history]
+pub const MONTHLY_YIELD: u8 = 0x4A;
+/// Operation and alarm log
+pub const LOG: u8 = 0x4B;
+
+/// Parameters of the yield history files, the period from `start` to
+/// `end` in seconds since the epoch, local time of the inverter
+pub fn yield_params(start: u32, end: u32) -> Vec<u8> {
+    let mut params = start.to_be_bytes().to_vec();
+    params.extend_from_slice(&end.to_be_bytes());
+    params
+}
+
+/// Upload the file `file_type` through `transact`, which exchanges a
+/// single PDU with the inverter
+pub(crate) fn upload<F>(mut transact: F, file_type: u8, params: &[u8]) -> Result<Vec<u8>, Error>
+where
+    F: FnMut(&[u8]) -> Result<Vec<u8>, modbus::Error>,
+{
+    let mut start = vec![file_type];
+    start.extend_from_slice(params);
+    let response = transact(&request(START_UPLOAD, &start)?)?;
+    let file_len = match data(&response, START_UPLOAD)? {
+        [typ, len @ ..] if *typ == file_type && len.len() >= 4 => {
+            u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize
+        }
+        _ => {
+            return Err(Error::File(String::from(
+                "unexpected start upload response",
+            )))
+        }
+    };
+
+    if file_len > MAX_FILE_LEN {
+        return Err(Error::File(format!(
+            "file of {} bytes exceeds the maximum of {} bytes",
+            file_len, MAX_FILE_LEN
+        )));
+    }
+
+    let mut file = Vec::with_capacity(file_len.min(PREALLOCATE_LEN));
+    let mut frame_no = 0u16;
+    while file.len() < file_len {
+        let mut upload = vec![file_type];
+        upload.extend_from_slice(&frame_no.to_be_bytes());
+        let response = transact(&request(UPLOAD_DATA, &upload)?)?;
+        match data(&response, UPLOAD_DATA)? {
+            [typ, rest @ ..] if rest.len() > 2 && *typ == file_type && rest[..2] == upload[1..] => {
+                file.extend_from_slice(&rest[2..]);
+            }
+            _ => {
+                return Err(Error::File(format!(
+                    "unexpected response to frame {}",
+                    frame_no
+                )))
+            }
+        }
+        frame_no = frame_no
+            .checked_add(1)
+            .ok_or_else(|| Error::File(String::from("too many frames")))?;
+    }
+    if file.len() != file_len {
+        return Err(Error::File(format!(
+            "received {} bytes of a {} byte file",
+            file.len(),
+            file_len
+        )));
+    }
+
+    let response = transact(&request(COMPLETE_UPLOAD, &[file_type])?)?;
+    match data(&response, COMPLETE_UPLOAD)? {
+        [typ, crc_hi, crc_lo] if *typ == file_type => {
+            if u16::from_be_bytes([*crc_hi, *crc_lo]) != crc16(&file) {
+                return Err(Error::File(String::from("CRC mismatch")));
+            }
+        }
+        _ => {
+            return Err(Error::File(String::from(
+                "unexpected complete upload response",
+            )))
+        }
+    }
+    Ok(file)
+}
+
+fn request(sub_command: u8, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let count =
+        u8::try_from(data.len()).map_err(|_| Error::File(String::from("parameters too long")))?;
+    let mut pdu = vec![FUNCTION, sub_command, count];
+    pdu.extend_from_slice(data);
+    Ok(pdu)
+}
+
+/// Data of a response to the sub-command `sub_command`
+fn data(response: &[u8], sub_command: u8) -> Result<&[u8], Error> {
+    match response {
+        [FUNCTION, sub, count, data @ ..]
+            if *sub == sub_command && data.len() == *count as usize =>
+        {
+            Ok(data)
+        }
+        _ => Err(Error::Modbus(modbus::Error::InvalidResponse)),
+    }
+}
+
+/// Energy yield of one day or month
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct YieldRecord {
+    /// Start of the period in seconds since the epoch, local time of the
+    /// inverter
+    pub time: u32,
+    /// Energy yield in kWh
+    pub energy: f64,
+}
+
+/// Parse a [DAILY_YIELD] or [MONTHLY_YIELD] file.
+///
+/// The file is a sequence of 8 byte records: the start of the period as
+/// u32 and the yield in 0.01 kWh as u32, both big-endian. Periods without
+/// data have a yield of `0xFFFFFFFF` and are skipped.
+pub fn parse_yield_history(file: &[u8]) -> Result<Vec<YieldRecord>, Error> {
+    if !file.len().is_multiple_of(8) {
+        return Err(Error::File(format!(
+            "yield history of {} bytes is not a multiple of 8",
+            file.len()
+        )));
+    }
+    Ok(file
+        .chunks_exact(8)
+        .map(|record| {
+            (
+                u32::from_be_bytes([record[0], record[1], record[2], record[3]]),
+                u32::from_be_bytes([record[4], record[5], record[6], record[7]]),
+            )
+        })
+        .filter(|&(_, energy)| energy != u32::MAX)
+        .map(|(time, energy)| YieldRecord {
+            time,
+            energy: energy as f64 / 100.0,
+        })
+        .collect())
+}
+
+/// Fake inverter serving `file` in frames of `frame_len` bytes, reporting
+/// `crc` on completion
+#[cfg(test)]
+fn serve(
+    file: Vec<u8>,
+    frame_len: usize,
+    crc: u16,
+) -> impl FnMut(&[u8]) -> Result<Vec<u8>, modbus::Error> {
+    move |pdu| {
+        assert_eq!(pdu[0], FUNCTION);
+        assert_eq!(pdu[2] as usize, pdu.len() - 3);
+        let file_type = pdu[3];
+        let data = match pdu[1] {
+            START_UPLOAD => {
+                let mut data = vec![file_type];
+                data.extend_from_slice(&(file.len() as u32).to_be_bytes());
+                data.push(frame_len as u8);
+                data
+            }
+            UPLOAD_DATA => {
+                let frame_no = u16::from_be_bytes([pdu[4], pdu[5]]) as usize;
+                let start = frame_no * frame_len;
+                let mut data = pdu[3..6].to_vec();
+                data.extend_from_slice(&file[start..file.len().min(start + frame_len)]);
+                data
+            }
+            COMPLETE_UPLOAD => {
+                let mut data = vec![file_type];
+                data.extend_from_slice(&crc.to_be_bytes());
+                data
+            }
+            _ => {
+                return Err(modbus::Error::Exception(
+                    modbus::ExceptionCode::IllegalFunction,
+                ))
+            }
+        };
+        let mut response = vec![FUNCTION, pdu[1], data.len() as u8];
+        response.extend_from_slice(&data);
+        Ok(response)
+    }
+}
+
+#[test]
+fn upload_frames() {
+    let file = (0..=255u8).cycle().take(500).collect::<Vec<_>>();
+    let crc = crc16(&file);
+    let mut requests = Vec::new();
+    let mut server = serve(file.clone(), 200, crc);
+    let uploaded = upload(
+        |pdu| {
+            requests.push(pdu.to_vec());
+            server(pdu)
+        },
+        LOG,
+        &[],
+    )
+    .unwrap();
+    assert_eq!(uploaded, file);
+    assert_eq!(
+        requests,
+        vec![
+            vec![FUNCTION, START_UPLOAD, 1, LOG],
+            vec![FUNCTION, UPLOAD_DATA, 3, LOG, 0, 0],
+            vec![FUNCTION, UPLOAD_DATA, 3, LOG, 0, 1],
+            vec![FUNCTION, UPLOAD_DATA, 3, LOG, 0, 2],
+            vec![FUNCTION, COMPLETE_UPLOAD, 1, LOG],
+        ]
+    );
+}
+
+#[test]
+fn upload_errors() {
+    let file = vec![1, 2, 3, 4];
+    assert!(matches!(
+        upload(serve(file.clone(), 3, 0x1234), LOG, &[]),
+        Err(Error::File(reason)) if reason == "CRC mismatch"
+    ));
+    // empty frames would never complete the file
+    assert!(matches!(
+        upload(serve(file.clone(), 0, crc16(&file)), LOG, &[]),
+        Err(Error::File(_))
+    ));
+    assert!(matches!(
+        upload(serve(file, 3, 0), 0x41, &[0; 255]),
+        Err(Error::File(reason)) if reason == "parameters too long"
+    ));
+    // the announced length is not trusted
+    let start_only = |pdu: &[u8]| match pdu[1] {
+        START_UPLOAD => Ok(vec![FUNCTION, START_UPLOAD, 5, LOG, 0xFF, 0xFF, 0xFF, 0xFF]),
+        _ => panic!("file of 4 GiB requested"),
+    };
+    assert!(matches!(
+        upload(start_only, LOG, &[]),
+        Err(Error::File(reason)) if reason.starts_with("file of 4294967295 bytes")
+    ));
+    assert!(upload(
+        serve(vec![], 3, crc16(&[])),
+        DAILY_YIELD,
+        &yield_params(0, 1)
+    )
+    .unwrap()
+    .is_empty());
+}
+
+#[test]
+fn yield_history() {
+    #[rustfmt::skip]
+    let file = [
+        0x65, 0x92, 0x00, 0x00,  0x00, 0x00, 0x0B, 0xB8,
+        0x65, 0x93, 0x51, 0x80,  0xFF, 0xFF, 0xFF, 0xFF,
+        0x65, 0x94, 0xA3, 0x00,  0x00, 0x00, 0x00, 0x00,
+    ];
+    assert_eq!(
+        parse_yield_history(&file).unwrap(),
+        vec![
+            YieldRecord {
+                time: 0x65920000,
+                energy: 30.0
+            },
+            YieldRecord {
+                time: 0x6594A300,
+                energy: 0.0
+            },
+        ]
+    );
+    assert!(parse_yield_history(&file[..12]).is_err());
+    assert!(parse_yield_history(&[]).unwrap().is_empty());
+    assert_eq!(yield_params(1, 2), vec![0, 0, 0, 1, 0, 0, 0, 2]);
+}
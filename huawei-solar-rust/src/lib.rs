@@ -1,35 +1,95 @@
-use std::{thread::sleep, time::Duration};
+use std::{
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread::sleep,
+    time::Duration,
+};
 
-use modbus::Transport;
+pub mod alarms;
+#[cfg(feature = "tokio")]
+pub mod async_tcp;
+pub mod batch;
+pub mod battery;
+pub mod capture;
+pub mod device;
+pub mod files;
+pub mod login;
+mod pdu;
+pub mod power;
+pub mod rtu;
+pub mod status;
+pub mod tcp;
+
+#[cfg(feature = "tokio")]
+pub use async_tcp::AsyncInverter;
 
 pub mod registers {
-    use std::fmt::Display;
-    use num::pow::Pow;
+    use std::{fmt::Display, marker::PhantomData, ops::Deref, str::FromStr};
 
     use bit_vec::BitVec;
     use thiserror::Error;
 
+    pub use crate::alarms::Alarms;
+    pub use crate::status::{DeviceStatus, State1, State2, State3};
+
     #[derive(Error, Debug)]
     pub enum RegisterError {
         #[error("Failed to convert: {0}")]
         ValueConversion(String),
+        #[error("Unknown register type: {0}")]
+        UnknownType(String),
     }
     pub trait RegisterType {
+        /// Register [Type] holding values of this type
+        const TYPE: Type;
+
         fn convert(vec: &[u16]) -> Option<Self>
         where
             Self: Sized;
+
+        /// Inverse of [RegisterType::convert], `None` if the value does not
+        /// fit into `quantity` registers
+        fn encode(&self, quantity: usize) -> Option<Vec<u16>>;
     }
     impl RegisterType for String {
+        const TYPE: Type = Type::STR;
+
         fn convert(vec: &[u16]) -> Option<Self> {
-            String::from_utf8(vec.into_iter().flat_map(|u| u.to_be_bytes()).collect()).ok()
+            // strings are padded with NUL bytes up to the register quantity
+            let bytes = vec
+                .iter()
+                .flat_map(|u| u.to_be_bytes())
+                .take_while(|&b| b != 0)
+                .collect();
+            String::from_utf8(bytes).ok()
+        }
+
+        fn encode(&self, quantity: usize) -> Option<Vec<u16>> {
+            let mut bytes = self.as_bytes().to_vec();
+            if bytes.len() > quantity * 2 {
+                return None;
+            }
+            bytes.resize(quantity * 2, 0);
+            Some(
+                bytes
+                    .chunks(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect(),
+            )
         }
     }
     impl RegisterType for u16 {
+        const TYPE: Type = Type::U16;
+
         fn convert(vec: &[u16]) -> Option<Self> {
             vec.first().copied()
         }
+
+        fn encode(&self, quantity: usize) -> Option<Vec<u16>> {
+            (quantity == 1).then(|| vec![*self])
+        }
     }
     #[test]
+    #[allow(clippy::useless_vec)]
     fn convert_u16() {
         assert_eq!(u16::convert(&vec![u16::MIN]), Some(u16::MIN));
         assert_eq!(u16::convert(&vec![0u16]), Some(0u16));
@@ -37,11 +97,18 @@ pub mod registers {
         assert_eq!(u16::convert(&vec![u16::MAX]), Some(u16::MAX));
     }
     impl RegisterType for u32 {
+        const TYPE: Type = Type::U32;
+
         fn convert(vec: &[u16]) -> Option<Self> {
             Some((vec[0] as u32) << 16 | vec[1] as u32)
         }
+
+        fn encode(&self, quantity: usize) -> Option<Vec<u16>> {
+            (quantity == 2).then(|| vec![(*self >> 16) as u16, *self as u16])
+        }
     }
     #[test]
+    #[allow(clippy::useless_vec)]
     fn convert_u32() {
         assert_eq!(u32::convert(&vec![u16::MIN, u16::MIN]), Some(u32::MIN));
         assert_eq!(u32::convert(&vec![0u16, 0u16]), Some(0u32));
@@ -53,12 +120,19 @@ pub mod registers {
     }
 
     impl RegisterType for i16 {
+        const TYPE: Type = Type::I16;
+
         fn convert(vec: &[u16]) -> Option<Self> {
             vec.first().map(|v| *v as i16)
         }
+
+        fn encode(&self, quantity: usize) -> Option<Vec<u16>> {
+            (quantity == 1).then(|| vec![*self as u16])
+        }
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn convert_i16() {
         assert_eq!(i16::convert(&vec![0u16]), Some(0i16));
         assert_eq!(i16::convert(&vec![1u16]), Some(1i16));
@@ -66,11 +140,18 @@ pub mod registers {
     }
 
     impl RegisterType for i32 {
+        const TYPE: Type = Type::I32;
+
         fn convert(vec: &[u16]) -> Option<Self> {
             Some((vec[0] as i32) << 16 | vec[1] as i32)
         }
+
+        fn encode(&self, quantity: usize) -> Option<Vec<u16>> {
+            (quantity == 2).then(|| vec![(*self >> 16) as u16, *self as u16])
+        }
     }
     #[test]
+    #[allow(clippy::useless_vec)]
     fn convert_i32() {
         assert_eq!(
             <i32 as RegisterType>::convert(&vec![0u16, 0u16]),
@@ -86,28 +167,47 @@ pub mod registers {
         );
     }
 
-    // TODO
-    // BitVec read bits from MSB to LSB -- probably wrong direction
+    /// Bit fields are numbered LSB first, as in the Huawei register tables.
+    /// Multi-register fields are big-endian like the integer types, so bit 0
+    /// is the least significant bit of the last register.
     impl RegisterType for BitVec {
+        const TYPE: Type = Type::BF;
+
         fn convert(vec: &[u16]) -> Option<Self> {
-            let bytes = vec
-                .iter()
-                .flat_map(|&num| num.to_be_bytes())
-                .collect::<Vec<u8>>();
+            Some(
+                vec.iter()
+                    .rev()
+                    .flat_map(|&word| (0..16).map(move |bit| word >> bit & 1 == 1))
+                    .collect(),
+            )
+        }
 
-            Some(BitVec::from_bytes(&bytes))
+        fn encode(&self, quantity: usize) -> Option<Vec<u16>> {
+            if self.len() > quantity * 16 {
+                return None;
+            }
+            let mut words = vec![0u16; quantity];
+            for bit in (0..self.len()).filter(|&i| self[i]) {
+                words[quantity - 1 - bit / 16] |= 1 << (bit % 16);
+            }
+            Some(words)
         }
     }
 
     #[test]
     #[rustfmt::skip]
     fn convert_bf() {
-        assert!(BitVec::convert(&vec![0u16, 0u16]).unwrap().capacity() >= 32);
-        assert_eq!(BitVec::convert(&vec![0u16, 0u16]).unwrap()[0], false);
-        assert_eq!(BitVec::convert(&vec![8u16, 0u16]).unwrap()[12], true);
+        assert_eq!(BitVec::convert(&[0u16, 0u16]).unwrap().len(), 32);
+        assert!(BitVec::convert(&[0u16, 0u16]).unwrap().none());
+        assert!(BitVec::convert(&[0x0001u16]).unwrap()[0]);
+        assert!(BitVec::convert(&[0x8000u16]).unwrap()[15]);
+        assert!(BitVec::convert(&[0x0000u16, 0x0008u16]).unwrap()[3]);
+        assert!(BitVec::convert(&[0x0008u16, 0x0000u16]).unwrap()[19]);
+        assert!(BitVec::convert(&[0x8000u16, 0x0000u16]).unwrap()[31]);
+        assert_eq!(BitVec::convert(&[0x0004u16]).unwrap().iter().filter(|&b| b).count(), 1);
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub enum Type {
         U16,
         U32,
@@ -118,7 +218,7 @@ pub mod registers {
     }
     impl Type {
         #[rustfmt::skip]
-        pub fn convert(&self, val: &Vec<u16>) -> Option<Value> {
+        pub fn convert(&self, val: &[u16]) -> Option<Value> {
             match self {
                 Type::U16 => Some(Value::U16(   u16::convert(val)?)),
                 Type::U32 => Some(Value::U32(   u32::convert(val)?)),
@@ -128,6 +228,64 @@ pub mod registers {
                 Type::STR => Some(Value::STR(String::convert(val)?)),
             }
         }
+
+        /// Inverse of [Type::convert], encode `val` into `quantity` registers
+        pub fn encode(&self, val: &Value, quantity: u8) -> Result<Vec<u16>, RegisterError> {
+            let quantity = quantity as usize;
+            let words = match (self, val) {
+                (Type::U16, Value::U16(v)) => v.encode(quantity),
+                (Type::U32, Value::U32(v)) => v.encode(quantity),
+                (Type::I16, Value::I16(v)) => v.encode(quantity),
+                (Type::I32, Value::I32(v)) => v.encode(quantity),
+                (Type::BF, Value::BF(v)) => v.encode(quantity),
+                (Type::STR, Value::STR(v)) => v.encode(quantity),
+                _ => {
+                    return Err(RegisterError::ValueConversion(format!(
+                        "Cannot encode {:?} as {:?}",
+                        val, self
+                    )))
+                }
+            };
+            words.ok_or_else(|| {
+                RegisterError::ValueConversion(format!(
+                    "{:?} does not fit into {} registers",
+                    val, quantity
+                ))
+            })
+        }
+
+        /// Number of registers occupied by a value of this type,
+        /// `None` for types with a register specific length
+        pub const fn size(&self) -> Option<u8> {
+            match self {
+                Type::U16 | Type::I16 => Some(1),
+                Type::U32 | Type::I32 => Some(2),
+                Type::BF | Type::STR => None,
+            }
+        }
+    }
+    impl FromStr for Type {
+        type Err = RegisterError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "U16" => Ok(Type::U16),
+                "U32" => Ok(Type::U32),
+                "I16" => Ok(Type::I16),
+                "I32" => Ok(Type::I32),
+                "BF" => Ok(Type::BF),
+                "STR" => Ok(Type::STR),
+                _ => Err(RegisterError::UnknownType(s.to_string())),
+            }
+        }
+    }
+    #[test]
+    fn type_from_str() {
+        assert_eq!("U16".parse::<Type>().unwrap(), Type::U16);
+        assert_eq!("I32".parse::<Type>().unwrap(), Type::I32);
+        assert_eq!("STR".parse::<Type>().unwrap(), Type::STR);
+        assert!("u16".parse::<Type>().is_err());
+        assert!("F32".parse::<Type>().is_err());
     }
 
     #[derive(Debug)]
@@ -147,10 +305,10 @@ pub mod registers {
     impl<'a, 'b> RegValue<'a, 'b> {
         pub fn to_float(&self) -> Result<f64, RegisterError> {
             match self.val {
-                Value::U16(v) => Ok(v as f64 * 10f64.pow(self.reg.gain)),
-                Value::U32(v) => Ok(v as f64 * 10f64.pow(self.reg.gain)),
-                Value::I16(v) => Ok(v as f64 * 10f64.pow(self.reg.gain)),
-                Value::I32(v) => Ok(v as f64 * 10f64.pow(self.reg.gain)),
+                Value::U16(v) => Ok(self.reg.gain.apply(v.into())),
+                Value::U32(v) => Ok(self.reg.gain.apply(v.into())),
+                Value::I16(v) => Ok(self.reg.gain.apply(v.into())),
+                Value::I32(v) => Ok(self.reg.gain.apply(v.into())),
                 Value::BF(_) => Err(RegisterError::ValueConversion(
                     "Cannot convert bit field to float".to_string(),
                 )),
@@ -195,6 +353,34 @@ pub mod registers {
                 ))),
             }
         }
+        pub fn to_device_status(&self) -> Result<DeviceStatus, RegisterError> {
+            match &self.val {
+                Value::U16(v) => Ok(DeviceStatus::convert(&[*v]).unwrap()),
+                default => Err(RegisterError::ValueConversion(format!(
+                    "Cannot convert {:?} to DeviceStatus",
+                    default
+                ))),
+            }
+        }
+        pub fn to_alarms(&self) -> Result<Alarms, RegisterError> {
+            self.decode()
+        }
+        /// Re-interpret the value as another [RegisterType] of the same
+        /// [Type], e.g. the flags of a bit field register
+        pub fn decode<T: RegisterType>(&self) -> Result<T, RegisterError> {
+            if T::TYPE != self.reg.typ {
+                return Err(RegisterError::ValueConversion(format!(
+                    "Cannot decode {:?} register {} as {:?}",
+                    self.reg.typ,
+                    self.reg.name,
+                    T::TYPE
+                )));
+            }
+            let words = self.reg.encode(&self.val)?;
+            T::convert(&words).ok_or_else(|| {
+                RegisterError::ValueConversion(format!("Cannot decode {:?}", self.val))
+            })
+        }
         pub fn to_i16(&self) -> Result<i16, RegisterError> {
             match &self.val {
                 Value::I16(v) => Ok(*v),
@@ -221,130 +407,362 @@ pub mod registers {
         }
     }
 
-    #[derive(Debug)]
+    /// Scaling model of a register.
+    ///
+    /// Huawei specifies the gain of a register as a divisor, e.g. a voltage
+    /// register with gain 10 holds the value in 0.1V steps. The engineering
+    /// value is calculated as `raw * multiplier / divisor + offset`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Gain {
+        pub divisor: u32,
+        pub multiplier: u32,
+        pub offset: i32,
+    }
+    impl Gain {
+        /// Gain of registers which hold the engineering value unscaled
+        pub const NONE: Gain = Gain::div(1);
+
+        /// Gain as listed in the Huawei register tables
+        pub const fn div(divisor: u32) -> Self {
+            Gain {
+                divisor,
+                multiplier: 1,
+                offset: 0,
+            }
+        }
+
+        pub const fn mul(multiplier: u32) -> Self {
+            Gain {
+                divisor: 1,
+                multiplier,
+                offset: 0,
+            }
+        }
+
+        pub const fn with_offset(self, offset: i32) -> Self {
+            Gain { offset, ..self }
+        }
+
+        /// Convert a raw register value to its engineering value
+        pub fn apply(&self, raw: f64) -> f64 {
+            raw * self.multiplier as f64 / self.divisor as f64 + self.offset as f64
+        }
+
+        /// Convert an engineering value back to the raw register value
+        pub fn invert(&self, value: f64) -> f64 {
+            (value - self.offset as f64) * self.divisor as f64 / self.multiplier as f64
+        }
+    }
+
+    #[test]
+    fn gain_apply() {
+        assert_eq!(Gain::NONE.apply(1234.0), 1234.0);
+        assert_eq!(Gain::div(10).apply(4000.0), 400.0);
+        assert_eq!(Gain::div(1000).apply(-1500.0), -1.5);
+        assert_eq!(Gain::mul(10).apply(12.0), 120.0);
+        assert_eq!(Gain::div(10).with_offset(-40).apply(650.0), 25.0);
+    }
+
+    #[test]
+    fn gain_invert() {
+        for gain in [
+            Gain::NONE,
+            Gain::div(10),
+            Gain::div(1000),
+            Gain::mul(10),
+            Gain::div(10).with_offset(-40),
+        ] {
+            assert!((gain.invert(gain.apply(650.0)) - 650.0).abs() < 1e-9);
+        }
+        assert_eq!(Gain::div(100).invert(8.25), 825.0);
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub enum Access {
         RO,
         WO,
         RW,
     }
 
-    #[derive(Debug)]
-    pub struct Register<'a /* , T: RegisterType */> {
+    /// Type-erased description of a register, see [TypedRegister] for
+    /// registers with their value type known at compile time
+    #[derive(Debug, Clone, Copy)]
+    pub struct Register<'a> {
         pub address: u16,
         pub quantity: u8,
-        pub gain: u8,
+        pub gain: Gain,
         pub unit: Option<&'a str>,
         pub access: Access,
         pub typ: Type,
         pub name: &'a str,
-        // typ: std::marker::PhantomData<T>,
+    }
+
+    /// [Register] holding values of type `T`
+    ///
+    /// Dereferences to the type-erased [Register] so typed registers can be
+    /// mixed in batch reads.
+    #[derive(Debug, Clone, Copy)]
+    pub struct TypedRegister<'a, T> {
+        reg: Register<'a>,
+        value: PhantomData<fn() -> T>,
+    }
+    impl<'a, T: RegisterType> TypedRegister<'a, T> {
+        /// Panics (at compile time for constants) if the [Type] of `reg`
+        /// does not match `T`
+        pub const fn new(reg: Register<'a>) -> Self {
+            assert!(
+                reg.typ as u8 == T::TYPE as u8,
+                "register type does not match value type"
+            );
+            TypedRegister {
+                reg,
+                value: PhantomData,
+            }
+        }
+
+        pub const fn erased(&self) -> &Register<'a> {
+            &self.reg
+        }
+    }
+    impl<'a, T> Deref for TypedRegister<'a, T> {
+        type Target = Register<'a>;
+
+        fn deref(&self) -> &Self::Target {
+            &self.reg
+        }
+    }
+
+    impl<'a> Register<'a> {
+        /// Convert an engineering value to the raw [Value] of this register,
+        /// the inverse of [RegValue::to_float]
+        pub fn value_from_float(&self, value: f64) -> Result<Value, RegisterError> {
+            let raw = self.gain.invert(value).round();
+            let out_of_range = || {
+                RegisterError::ValueConversion(format!(
+                    "{} is out of range for {}",
+                    value, self.name
+                ))
+            };
+            let in_range = |min: f64, max: f64| {
+                if raw.is_finite() && raw >= min && raw <= max {
+                    Ok(raw)
+                } else {
+                    Err(out_of_range())
+                }
+            };
+            match self.typ {
+                Type::U16 => Ok(Value::U16(
+                    in_range(u16::MIN.into(), u16::MAX.into())? as u16
+                )),
+                Type::U32 => Ok(Value::U32(
+                    in_range(u32::MIN.into(), u32::MAX.into())? as u32
+                )),
+                Type::I16 => Ok(Value::I16(
+                    in_range(i16::MIN.into(), i16::MAX.into())? as i16
+                )),
+                Type::I32 => Ok(Value::I32(
+                    in_range(i32::MIN.into(), i32::MAX.into())? as i32
+                )),
+                Type::BF | Type::STR => Err(RegisterError::ValueConversion(format!(
+                    "Cannot convert float to {:?}",
+                    self.typ
+                ))),
+            }
+        }
+
+        /// Encode `value` into the words written to this register
+        pub fn encode(&self, value: &Value) -> Result<Vec<u16>, RegisterError> {
+            self.typ.encode(value, self.quantity)
+        }
+    }
+
+    #[test]
+    fn value_from_float() {
+        assert!(matches!(
+            TIME_ZONE.value_from_float(-60.0),
+            Ok(Value::I16(-60))
+        ));
+        assert!(matches!(
+            PV1_CURRENT.value_from_float(8.25),
+            Ok(Value::I16(825))
+        ));
+        assert!(matches!(
+            RATED_POWER.value_from_float(10.0),
+            Ok(Value::U32(10000))
+        ));
+        assert!(matches!(
+            EFFICIENCY.value_from_float(99.996),
+            Ok(Value::U16(10000))
+        ));
+        assert!(GRID_CODE.value_from_float(-1.0).is_err());
+        assert!(GRID_CODE.value_from_float(65536.0).is_err());
+        assert!(GRID_CODE.value_from_float(f64::NAN).is_err());
+        assert!(MODEL.value_from_float(1.0).is_err());
+    }
+
+    #[test]
+    fn encode_roundtrip() {
+        let values: [(&Register, Value); 6] = [
+            (&GRID_CODE, Value::U16(17)),
+            (&TIME_ZONE, Value::I16(-60)),
+            (&RATED_POWER, Value::U32(0x0001_86A0)),
+            (&ACTIVE_POWER, Value::I32(-200)),
+            (
+                &STATE_3,
+                Value::BF(BitVec::convert(&[0x8001, 0x0002]).unwrap()),
+            ),
+            (&MODEL, Value::STR(String::from("SUN2000-10KTL-M1"))),
+        ];
+        for (reg, value) in values {
+            let words = reg.encode(&value).unwrap();
+            assert_eq!(words.len(), reg.quantity as usize);
+            assert_eq!(
+                format!("{:?}", reg.typ.convert(&words).unwrap()),
+                format!("{:?}", value)
+            );
+        }
+        assert_eq!(TIME_ZONE.encode(&Value::I16(-60)).unwrap(), vec![0xFFC4]);
+        assert_eq!(
+            RATED_POWER.encode(&Value::U32(0x0001_86A0)).unwrap(),
+            vec![0x0001, 0x86A0]
+        );
+        let mut bits = BitVec::from_elem(32, false);
+        bits.set(0, true);
+        bits.set(17, true);
+        assert_eq!(
+            STATE_3.encode(&Value::BF(bits)).unwrap(),
+            vec![0x0002, 0x0001]
+        );
+        assert!(TIME_ZONE.encode(&Value::U16(60)).is_err());
+        assert!(SN.encode(&Value::STR("X".repeat(21))).is_err());
     }
 
     pub use nofmt::*;
+
+    /// Voltage and current register of a PV string
+    pub type PvString = (TypedRegister<'static, i16>, TypedRegister<'static, i16>);
+
+    /// Voltage and current register of PV string `n`, counting from 1 like
+    /// the register names. `None` beyond the 24 strings of the register
+    /// table, [Inverter::pv_strings](crate::Inverter::pv_strings) limits
+    /// them to the strings of the inverter.
+    pub const fn pv_string(n: usize) -> Option<PvString> {
+        if n == 0 || n > PV_STRINGS.len() {
+            return None;
+        }
+        Some(PV_STRINGS[n - 1])
+    }
     #[rustfmt::skip]
     mod nofmt {
-        // use bit_vec::BitVec;
-        use super::{Access, Type, Register};
-
-        pub const MODEL:                            Register = Register { address: 30000, quantity: 15, gain: 0, unit: None        , access: Access::RO, typ: Type::STR, name: "MODEL"                            };
-        pub const SN:                               Register = Register { address: 30015, quantity: 10, gain: 0, unit: None        , access: Access::RO, typ: Type::STR, name: "SN"                               };
-        pub const PN:                               Register = Register { address: 30025, quantity: 10, gain: 0, unit: None        , access: Access::RO, typ: Type::STR, name: "PN"                               };
-        pub const MODEL_ID:                         Register = Register { address: 30070, quantity:  1, gain: 0, unit: None        , access: Access::RO, typ: Type::U16, name: "MODEL_ID"                         };
-        pub const NUMBER_OF_PV_STRINGS:             Register = Register { address: 30071, quantity:  1, gain: 0, unit: None        , access: Access::RO, typ: Type::U16, name: "NUMBER_OF_PV_STRINGS"             };
-        pub const NUMBER_OF_MPP_TRACKERS:           Register = Register { address: 30072, quantity:  1, gain: 0, unit: None        , access: Access::RO, typ: Type::U16, name: "NUMBER_OF_MPP_TRACKERS"           };
-        pub const RATED_POWER:                      Register = Register { address: 30073, quantity:  2, gain: 3, unit: Some("kW")  , access: Access::RO, typ: Type::U32, name: "RATED_POWER"                      };
-        pub const MAXIMUM_ACTIVE_POWER:             Register = Register { address: 30075, quantity:  2, gain: 3, unit: Some("kW")  , access: Access::RO, typ: Type::U32, name: "MAXIMUM_ACTIVE_POWER"             };
-        pub const MAXIMUM_APPARENT_POWER:           Register = Register { address: 30077, quantity:  2, gain: 3, unit: Some("kVA") , access: Access::RO, typ: Type::U32, name: "MAXIMUM_APPARENT_POWER"           };
-        pub const MAXIMUM_REACTIVE_POWER_TO_GRID:   Register = Register { address: 30079, quantity:  2, gain: 3, unit: Some("kVar"), access: Access::RO, typ: Type::I32, name: "MAXIMUM_REACTIVE_POWER_TO_GRID"   };
-        pub const MAXIMUM_APPARENT_POWER_FROM_GRID: Register = Register { address: 30081, quantity:  2, gain: 3, unit: Some("kVar"), access: Access::RO, typ: Type::I32, name: "MAXIMUM_APPARENT_POWER_FROM_GRID" };
-
-        pub const STATE_1:                          Register = Register { address: 32000, quantity:  1, gain: 0, unit: None        , access: Access::RO, typ: Type::BF , name: "STATE_1"                          };
-        pub const STATE_2:                          Register = Register { address: 32002, quantity:  1, gain: 0, unit: None        , access: Access::RO, typ: Type::BF , name: "STATE_2"                          };
-        pub const STATE_3:                          Register = Register { address: 32003, quantity:  2, gain: 0, unit: None        , access: Access::RO, typ: Type::BF , name: "STATE_3"                          };
-        pub const ALARM_1:                          Register = Register { address: 32008, quantity:  1, gain: 0, unit: None        , access: Access::RO, typ: Type::BF , name: "ALARM_1"                          };
-        pub const ALARM_2:                          Register = Register { address: 32009, quantity:  1, gain: 0, unit: None        , access: Access::RO, typ: Type::BF , name: "ALARM_2"                          };
-        pub const ALARM_3:                          Register = Register { address: 32010, quantity:  1, gain: 0, unit: None        , access: Access::RO, typ: Type::BF , name: "ALARM_3"                          };
-
-        pub const PV1_VOLTAGE:                      Register = Register { address: 32016, quantity:  1, gain: 1, unit: Some("V")   , access: Access::RO, typ: Type::I16, name: "PV1_VOLTAGE"                      };
-        pub const PV1_CURRENT:                      Register = Register { address: 32017, quantity:  1, gain: 2, unit: Some("A")   , access: Access::RO, typ: Type::I16, name: "PV1_CURRENT"                      };
-        pub const PV2_VOLTAGE:                      Register = Register { address: 32018, quantity:  1, gain: 1, unit: Some("V")   , access: Access::RO, typ: Type::I16, name: "PV2_VOLTAGE"                      };
-        pub const PV2_CURRENT:                      Register = Register { address: 32019, quantity:  1, gain: 2, unit: Some("A")   , access: Access::RO, typ: Type::I16, name: "PV2_CURRENT"                      };
-        pub const PV3_VOLTAGE:                      Register = Register { address: 32020, quantity:  1, gain: 1, unit: Some("V")   , access: Access::RO, typ: Type::I16, name: "PV3_VOLTAGE"                      };
-        pub const PV3_CURRENT:                      Register = Register { address: 32021, quantity:  1, gain: 2, unit: Some("A")   , access: Access::RO, typ: Type::I16, name: "PV3_CURRENT"                      };
-        pub const PV4_VOLTAGE:                      Register = Register { address: 32022, quantity:  1, gain: 1, unit: Some("V")   , access: Access::RO, typ: Type::I16, name: "PV4_VOLTAGE"                      };
-        pub const PV4_CURRENT:                      Register = Register { address: 32023, quantity:  1, gain: 2, unit: Some("A")   , access: Access::RO, typ: Type::I16, name: "PV4_CURRENT"                      };
-
-        pub const INPUT_POWER:                      Register = Register { address: 32064, quantity:  2, gain: 3, unit: Some("kW")  , access: Access::RO, typ: Type::I32, name: "INPUT_POWER"                      };
-
-        pub const LINE_VOLTAGE_A_B:                 Register = Register { address: 32066, quantity:  1, gain: 1, unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "LINE_VOLTAGE_A_B"                 };
-        pub const LINE_VOLTAGE_B_C:                 Register = Register { address: 32067, quantity:  1, gain: 1, unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "LINE_VOLTAGE_B_C"                 };
-        pub const LINE_VOLTAGE_C_A:                 Register = Register { address: 32068, quantity:  1, gain: 1, unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "LINE_VOLTAGE_C_A"                 };
-        pub const PHASE_VOLTAGE_A:                  Register = Register { address: 32069, quantity:  1, gain: 1, unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "PHASE_VOLTAGE_A"                  };
-        pub const PHASE_VOLTAGE_B:                  Register = Register { address: 32070, quantity:  1, gain: 1, unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "PHASE_VOLTAGE_B"                  };
-        pub const PHASE_VOLTAGE_C:                  Register = Register { address: 32071, quantity:  1, gain: 1, unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "PHASE_VOLTAGE_C"                  };
-        pub const PHASE_CURRENT_A:                  Register = Register { address: 32072, quantity:  2, gain: 3, unit: Some("A")   , access: Access::RO, typ: Type::I32, name: "PHASE_CURRENT_A"                  };
-        pub const PHASE_CURRENT_B:                  Register = Register { address: 32074, quantity:  2, gain: 3, unit: Some("A")   , access: Access::RO, typ: Type::I32, name: "PHASE_CURRENT_B"                  };
-        pub const PHASE_CURRENT_C:                  Register = Register { address: 32076, quantity:  2, gain: 3, unit: Some("A")   , access: Access::RO, typ: Type::I32, name: "PHASE_CURRENT_C"                  };
-
-        pub const PEAK_ACTIVE_POWER_DAY:            Register = Register { address: 32078, quantity:  2, gain: 3, unit: Some("kW")  , access: Access::RO, typ: Type::I32, name: "PEAK_ACTIVE_POWER_DAY"            };
-        pub const ACTIVE_POWER:                     Register = Register { address: 32080, quantity:  2, gain: 3, unit: Some("kW")  , access: Access::RO, typ: Type::I32, name: "ACTIVE_POWER"                     };
-        pub const REACTIVE_POWER:                   Register = Register { address: 32082, quantity:  2, gain: 3, unit: Some("kVar"), access: Access::RO, typ: Type::I32, name: "REACTIVE_POWER"                   };
-
-        pub const POWER_FACTOR:                     Register = Register { address: 32084, quantity:  1, gain: 3, unit: None        , access: Access::RO, typ: Type::I16, name: "POWER_FACTOR"                     };
-        pub const GRID_FREQUENCY:                   Register = Register { address: 32085, quantity:  1, gain: 2, unit: Some("Hz")  , access: Access::RO, typ: Type::U16, name: "GRID_FREQUENCY"                   };
-        pub const EFFICIENCY:                       Register = Register { address: 32086, quantity:  1, gain: 2, unit: Some("%")   , access: Access::RO, typ: Type::U16, name: "EFFICIENCY"                       };
-        pub const INTERNAL_TEMPERATURE:             Register = Register { address: 32087, quantity:  1, gain: 1, unit: Some("°C")  , access: Access::RO, typ: Type::I16, name: "INTERNAL_TEMPERATURE"             };
-        pub const INSULATION_RESISTANCE:            Register = Register { address: 32088, quantity:  1, gain: 3, unit: Some("MΩ")  , access: Access::RO, typ: Type::U16, name: "INSULATION_RESISTANCE"            };
-
-        pub const DEVICE_STATUS:                    Register = Register { address: 32089, quantity:  1, gain: 0, unit: None        , access: Access::RO, typ: Type::U16, name: "DEVICE_STATUS"                    };
-        pub const fn device_status_to_string(status: u16) -> Option<&'static str> {
-            match status {
-                0x0000 => Some("Standby: initializing"),
-                0x0001 => Some("Standby: detecting insulation resistance"),
-                0x0002 => Some("Standby: detecting irradiation"),
-                0x0003 => Some("Standby: drid detecting"),
-                0x0100 => Some("Starting"),
-                0x0200 => Some("On-grid (Off-grid mode: running)"),
-                0x0201 => Some("Grid connection: power limited (Off-grid mode: running: power limited)"),
-                0x0202 => Some("Grid connection: self- derating (Off-grid mode: running: self-derating)"),
-                0x0300 => Some("Shutdown: fault"),
-                0x0301 => Some("Shutdown: command"),
-                0x0302 => Some("Shutdown: OVGR"),
-                0x0303 => Some("Shutdown: communication disconnected"),
-                0x0304 => Some("Shutdown: power limited"),
-                0x0305 => Some("Shutdown: manual startup required"),
-                0x0306 => Some("Shutdown: DC switches disconnected"),
-                0x0307 => Some("Shutdown: rapid cutoff"),
-                0x0308 => Some("Shutdown: input underpower"),
-                0x0401 => Some("Grid scheduling: cosφ-P curve"),
-                0x0402 => Some("Grid scheduling: Q-U curve"),
-                0x0403 => Some("Grid scheduling: PF-U curve"),
-                0x0404 => Some("Grid scheduling: dry contact"),
-                0x0405 => Some("Grid scheduling: Q-P curve"),
-                0x0500 => Some("Spot-check ready"),
-                0x0501 => Some("Spot-checking"),
-                0x0600 => Some("Inspecting"),
-                0x0700 => Some("AFCI self check"),
-                0x0800 => Some("I-V scanning"),
-                0x0900 => Some("DC input detection"),
-                0x0A00 => Some("Running: off-grid charging"),
-                0xA000 => Some("Standby: no irradiation"),
-                _ => None
-            }
-        } 
-        
-            
+        use bit_vec::BitVec;
+        use crate::alarms::Alarms;
+        use crate::power::ActivePowerControlMode;
+        use crate::status::{DeviceStatus, State1, State2, State3};
+        use super::{Access, Gain, Type, Register, TypedRegister};
+
+        pub const MODEL:                            TypedRegister<String> = TypedRegister::new(Register { address: 30000, quantity: 15, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::STR, name: "MODEL"                            });
+        pub const SN:                               TypedRegister<String> = TypedRegister::new(Register { address: 30015, quantity: 10, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::STR, name: "SN"                               });
+        pub const PN:                               TypedRegister<String> = TypedRegister::new(Register { address: 30025, quantity: 10, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::STR, name: "PN"                               });
+        pub const MODEL_ID:                         TypedRegister<u16>    = TypedRegister::new(Register { address: 30070, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "MODEL_ID"                         });
+        pub const NUMBER_OF_PV_STRINGS:             TypedRegister<u16>    = TypedRegister::new(Register { address: 30071, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "NUMBER_OF_PV_STRINGS"             });
+        pub const NUMBER_OF_MPP_TRACKERS:           TypedRegister<u16>    = TypedRegister::new(Register { address: 30072, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "NUMBER_OF_MPP_TRACKERS"           });
+        pub const RATED_POWER:                      TypedRegister<u32>    = TypedRegister::new(Register { address: 30073, quantity:  2, gain: Gain::div(1000), unit: Some("kW")  , access: Access::RO, typ: Type::U32, name: "RATED_POWER"                      });
+        pub const MAXIMUM_ACTIVE_POWER:             TypedRegister<u32>    = TypedRegister::new(Register { address: 30075, quantity:  2, gain: Gain::div(1000), unit: Some("kW")  , access: Access::RO, typ: Type::U32, name: "MAXIMUM_ACTIVE_POWER"             });
+        pub const MAXIMUM_APPARENT_POWER:           TypedRegister<u32>    = TypedRegister::new(Register { address: 30077, quantity:  2, gain: Gain::div(1000), unit: Some("kVA") , access: Access::RO, typ: Type::U32, name: "MAXIMUM_APPARENT_POWER"           });
+        pub const MAXIMUM_REACTIVE_POWER_TO_GRID:   TypedRegister<i32>    = TypedRegister::new(Register { address: 30079, quantity:  2, gain: Gain::div(1000), unit: Some("kVar"), access: Access::RO, typ: Type::I32, name: "MAXIMUM_REACTIVE_POWER_TO_GRID"   });
+        pub const MAXIMUM_APPARENT_POWER_FROM_GRID: TypedRegister<i32>    = TypedRegister::new(Register { address: 30081, quantity:  2, gain: Gain::div(1000), unit: Some("kVar"), access: Access::RO, typ: Type::I32, name: "MAXIMUM_APPARENT_POWER_FROM_GRID" });
+
+        pub const STATE_1:                          TypedRegister<State1> = TypedRegister::new(Register { address: 32000, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::BF , name: "STATE_1"                          });
+        pub const STATE_2:                          TypedRegister<State2> = TypedRegister::new(Register { address: 32002, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::BF , name: "STATE_2"                          });
+        pub const STATE_3:                          TypedRegister<State3> = TypedRegister::new(Register { address: 32003, quantity:  2, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::BF , name: "STATE_3"                          });
+        pub const ALARM_1:                          TypedRegister<BitVec> = TypedRegister::new(Register { address: 32008, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::BF , name: "ALARM_1"                          });
+        pub const ALARM_2:                          TypedRegister<BitVec> = TypedRegister::new(Register { address: 32009, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::BF , name: "ALARM_2"                          });
+        pub const ALARM_3:                          TypedRegister<BitVec> = TypedRegister::new(Register { address: 32010, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::BF , name: "ALARM_3"                          });
+        /// `ALARM_1` to `ALARM_3` decoded together
+        pub const ALARMS:                           TypedRegister<Alarms> = TypedRegister::new(Register { address: 32008, quantity:  3, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::BF , name: "ALARMS"                           });
 
-        pub const FAULT_CODE:                       Register = Register { address: 32090, quantity:  1, gain: 0, unit: None        , access: Access::RO, typ: Type::U16, name: "FAULT_CODE"                       };
-        pub const STARTUP_TIME:                     Register = Register { address: 32091, quantity:  2, gain: 0, unit: None        , access: Access::RO, typ: Type::U32, name: "STARTUP_TIME"                     };
-        pub const SHUTDOWN_TIME:                    Register = Register { address: 32093, quantity:  2, gain: 0, unit: None        , access: Access::RO, typ: Type::U32, name: "SHUTDOWN_TIME"                    };
-        pub const ACC_ENERGY_YIELD:                 Register = Register { address: 32106, quantity:  2, gain: 2, unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "ACC_ENERGY_YIELD"                 };
-        pub const ENERGY_YIELD_DAY:                 Register = Register { address: 32114, quantity:  2, gain: 2, unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "ENERGY_YIELD_DAY"                 };
+        /// Voltage and current registers of PV string `n`, following each
+        /// other from PV1 on
+        macro_rules! pv_strings {
+            ($($n:literal => $voltage:ident, $current:ident;)*) => {
+                $(
+                    pub const $voltage: TypedRegister<i16> = TypedRegister::new(Register { address: 32016 + 2 * ($n - 1), quantity: 1, gain: Gain::div( 10), unit: Some("V"), access: Access::RO, typ: Type::I16, name: stringify!($voltage) });
+                    pub const $current: TypedRegister<i16> = TypedRegister::new(Register { address: 32017 + 2 * ($n - 1), quantity: 1, gain: Gain::div(100), unit: Some("A"), access: Access::RO, typ: Type::I16, name: stringify!($current) });
+                )*
+
+                /// Voltage and current of each PV string, `PV_STRINGS[0]` is PV1, see
+                /// [pv_string](super::pv_string)
+                pub const PV_STRINGS: [super::PvString; 24] = [$(($voltage, $current)),*];
+            };
+        }
+        pv_strings! {
+             1 => PV1_VOLTAGE, PV1_CURRENT;
+             2 => PV2_VOLTAGE, PV2_CURRENT;
+             3 => PV3_VOLTAGE, PV3_CURRENT;
+             4 => PV4_VOLTAGE, PV4_CURRENT;
+             5 => PV5_VOLTAGE, PV5_CURRENT;
+             6 => PV6_VOLTAGE, PV6_CURRENT;
+             7 => PV7_VOLTAGE, PV7_CURRENT;
+             8 => PV8_VOLTAGE, PV8_CURRENT;
+             9 => PV9_VOLTAGE, PV9_CURRENT;
+            10 => PV10_VOLTAGE, PV10_CURRENT;
+            11 => PV11_VOLTAGE, PV11_CURRENT;
+            12 => PV12_VOLTAGE, PV12_CURRENT;
+            13 => PV13_VOLTAGE, PV13_CURRENT;
+            14 => PV14_VOLTAGE, PV14_CURRENT;
+            15 => PV15_VOLTAGE, PV15_CURRENT;
+            16 => PV16_VOLTAGE, PV16_CURRENT;
+            17 => PV17_VOLTAGE, PV17_CURRENT;
+            18 => PV18_VOLTAGE, PV18_CURRENT;
+            19 => PV19_VOLTAGE, PV19_CURRENT;
+            20 => PV20_VOLTAGE, PV20_CURRENT;
+            21 => PV21_VOLTAGE, PV21_CURRENT;
+            22 => PV22_VOLTAGE, PV22_CURRENT;
+            23 => PV23_VOLTAGE, PV23_CURRENT;
+            24 => PV24_VOLTAGE, PV24_CURRENT;
+        }
 
+        pub const INPUT_POWER:                      TypedRegister<i32>    = TypedRegister::new(Register { address: 32064, quantity:  2, gain: Gain::div(1000), unit: Some("kW")  , access: Access::RO, typ: Type::I32, name: "INPUT_POWER"                      });
+
+        pub const LINE_VOLTAGE_A_B:                 TypedRegister<u16>    = TypedRegister::new(Register { address: 32066, quantity:  1, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "LINE_VOLTAGE_A_B"                 });
+        pub const LINE_VOLTAGE_B_C:                 TypedRegister<u16>    = TypedRegister::new(Register { address: 32067, quantity:  1, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "LINE_VOLTAGE_B_C"                 });
+        pub const LINE_VOLTAGE_C_A:                 TypedRegister<u16>    = TypedRegister::new(Register { address: 32068, quantity:  1, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "LINE_VOLTAGE_C_A"                 });
+        pub const PHASE_VOLTAGE_A:                  TypedRegister<u16>    = TypedRegister::new(Register { address: 32069, quantity:  1, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "PHASE_VOLTAGE_A"                  });
+        pub const PHASE_VOLTAGE_B:                  TypedRegister<u16>    = TypedRegister::new(Register { address: 32070, quantity:  1, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "PHASE_VOLTAGE_B"                  });
+        pub const PHASE_VOLTAGE_C:                  TypedRegister<u16>    = TypedRegister::new(Register { address: 32071, quantity:  1, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "PHASE_VOLTAGE_C"                  });
+        pub const PHASE_CURRENT_A:                  TypedRegister<i32>    = TypedRegister::new(Register { address: 32072, quantity:  2, gain: Gain::div(1000), unit: Some("A")   , access: Access::RO, typ: Type::I32, name: "PHASE_CURRENT_A"                  });
+        pub const PHASE_CURRENT_B:                  TypedRegister<i32>    = TypedRegister::new(Register { address: 32074, quantity:  2, gain: Gain::div(1000), unit: Some("A")   , access: Access::RO, typ: Type::I32, name: "PHASE_CURRENT_B"                  });
+        pub const PHASE_CURRENT_C:                  TypedRegister<i32>    = TypedRegister::new(Register { address: 32076, quantity:  2, gain: Gain::div(1000), unit: Some("A")   , access: Access::RO, typ: Type::I32, name: "PHASE_CURRENT_C"                  });
+
+        pub const PEAK_ACTIVE_POWER_DAY:            TypedRegister<i32>    = TypedRegister::new(Register { address: 32078, quantity:  2, gain: Gain::div(1000), unit: Some("kW")  , access: Access::RO, typ: Type::I32, name: "PEAK_ACTIVE_POWER_DAY"            });
+        pub const ACTIVE_POWER:                     TypedRegister<i32>    = TypedRegister::new(Register { address: 32080, quantity:  2, gain: Gain::div(1000), unit: Some("kW")  , access: Access::RO, typ: Type::I32, name: "ACTIVE_POWER"                     });
+        pub const REACTIVE_POWER:                   TypedRegister<i32>    = TypedRegister::new(Register { address: 32082, quantity:  2, gain: Gain::div(1000), unit: Some("kVar"), access: Access::RO, typ: Type::I32, name: "REACTIVE_POWER"                   });
+
+        pub const POWER_FACTOR:                     TypedRegister<i16>    = TypedRegister::new(Register { address: 32084, quantity:  1, gain: Gain::div(1000), unit: None        , access: Access::RO, typ: Type::I16, name: "POWER_FACTOR"                     });
+        pub const GRID_FREQUENCY:                   TypedRegister<u16>    = TypedRegister::new(Register { address: 32085, quantity:  1, gain: Gain::div( 100), unit: Some("Hz")  , access: Access::RO, typ: Type::U16, name: "GRID_FREQUENCY"                   });
+        pub const EFFICIENCY:                       TypedRegister<u16>    = TypedRegister::new(Register { address: 32086, quantity:  1, gain: Gain::div( 100), unit: Some("%")   , access: Access::RO, typ: Type::U16, name: "EFFICIENCY"                       });
+        pub const INTERNAL_TEMPERATURE:             TypedRegister<i16>    = TypedRegister::new(Register { address: 32087, quantity:  1, gain: Gain::div(  10), unit: Some("°C")  , access: Access::RO, typ: Type::I16, name: "INTERNAL_TEMPERATURE"             });
+        pub const INSULATION_RESISTANCE:            TypedRegister<u16>    = TypedRegister::new(Register { address: 32088, quantity:  1, gain: Gain::div(1000), unit: Some("MΩ")  , access: Access::RO, typ: Type::U16, name: "INSULATION_RESISTANCE"            });
+
+        pub const DEVICE_STATUS:                    TypedRegister<DeviceStatus> = TypedRegister::new(Register { address: 32089, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "DEVICE_STATUS"                    });
+
+        pub const FAULT_CODE:                       TypedRegister<u16>    = TypedRegister::new(Register { address: 32090, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "FAULT_CODE"                       });
+        pub const STARTUP_TIME:                     TypedRegister<u32>    = TypedRegister::new(Register { address: 32091, quantity:  2, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U32, name: "STARTUP_TIME"                     });
+        pub const SHUTDOWN_TIME:                    TypedRegister<u32>    = TypedRegister::new(Register { address: 32093, quantity:  2, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U32, name: "SHUTDOWN_TIME"                    });
+        pub const ACC_ENERGY_YIELD:                 TypedRegister<u32>    = TypedRegister::new(Register { address: 32106, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "ACC_ENERGY_YIELD"                 });
+        pub const ENERGY_YIELD_DAY:                 TypedRegister<u32>    = TypedRegister::new(Register { address: 32114, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "ENERGY_YIELD_DAY"                 });
+
+        /// LUNA2000 batteries connected to the inverter.
+        ///
+        /// Registers directly in this module belong to battery unit 1, see
+        /// [unit_2](storage::unit_2) and [combined](storage::combined) for the
+        /// second unit and both units together. Positive power charges the
+        /// battery.
         pub mod storage {
-            use crate::registers::{Access, Register, Type};
+            use crate::battery::{TouPeriods, WorkingMode};
+            use crate::registers::{Access, Gain, Register, Type, TypedRegister};
 
-            pub const RUNNING_STATUS:               Register = Register { address: 37000, quantity:  1, gain: 0, unit: None        , access: Access::RO, typ: Type::U16, name: "RUNNING_STATUS"                   };
+            pub const RUNNING_STATUS:                  TypedRegister<u16>    = TypedRegister::new(Register { address: 37000, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "RUNNING_STATUS"                   });
             pub const fn running_status_to_string(status: u16) -> Option<&'static str> {
                 match status {
                     0 => Some("offline"),
@@ -355,22 +773,867 @@ pub mod registers {
                     _ => None
                 }
             }
-            pub const CHARGE_DISCHARGE_POWER:       Register = Register { address: 37001, quantity:  2, gain: 0, unit: Some("W")   , access: Access::RO, typ: Type::I32, name: "CHARGE_DISCHARGE_POWER"           };
-            pub const CHARGE_CAPACITY_DAY:          Register = Register { address: 37015, quantity:  2, gain: 2, unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "CHARGE_CAPACITY_DAY"              };
-            pub const DISCHARGE_CAPACITY_DAY:       Register = Register { address: 37017, quantity:  2, gain: 2, unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "DISCHARGE_CAPACITY_DAY"           };
-            // pub const ACTIVE_POWER:                 Register = Register { address: 37113, quantity:  2, gain: 0, unit: Some("W")   , access: Access::RO, typ: Type::U16, name: "INSULATION_RESISTANCE"            };
+            pub const CHARGE_DISCHARGE_POWER:          TypedRegister<i32>    = TypedRegister::new(Register { address: 37001, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RO, typ: Type::I32, name: "CHARGE_DISCHARGE_POWER"           });
+            pub const BUS_VOLTAGE:                     TypedRegister<u16>    = TypedRegister::new(Register { address: 37003, quantity:  1, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "BUS_VOLTAGE"                      });
+            pub const STATE_OF_CAPACITY:               TypedRegister<u16>    = TypedRegister::new(Register { address: 37004, quantity:  1, gain: Gain::div(  10), unit: Some("%")   , access: Access::RO, typ: Type::U16, name: "STATE_OF_CAPACITY"                });
+            pub const WORKING_MODE:                    TypedRegister<u16>    = TypedRegister::new(Register { address: 37006, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "WORKING_MODE"                     });
+            pub const fn working_mode_to_string(mode: u16) -> Option<&'static str> {
+                match mode {
+                    0 => Some("none"),
+                    1 => Some("forcible charge/discharge"),
+                    2 => Some("time of use (LG)"),
+                    3 => Some("fixed charge/discharge"),
+                    4 => Some("maximise self consumption"),
+                    5 => Some("fully fed to grid"),
+                    6 => Some("time of use (LUNA2000)"),
+                    _ => None
+                }
+            }
+            pub const RATED_CHARGE_POWER:              TypedRegister<u32>    = TypedRegister::new(Register { address: 37007, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RO, typ: Type::U32, name: "RATED_CHARGE_POWER"               });
+            pub const RATED_DISCHARGE_POWER:           TypedRegister<u32>    = TypedRegister::new(Register { address: 37009, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RO, typ: Type::U32, name: "RATED_DISCHARGE_POWER"            });
+            pub const FAULT_ID:                        TypedRegister<u16>    = TypedRegister::new(Register { address: 37014, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "FAULT_ID"                         });
+            pub const CHARGE_CAPACITY_DAY:             TypedRegister<u32>    = TypedRegister::new(Register { address: 37015, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "CHARGE_CAPACITY_DAY"              });
+            pub const DISCHARGE_CAPACITY_DAY:          TypedRegister<u32>    = TypedRegister::new(Register { address: 37017, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "DISCHARGE_CAPACITY_DAY"           });
+            pub const BUS_CURRENT:                     TypedRegister<i16>    = TypedRegister::new(Register { address: 37021, quantity:  1, gain: Gain::div(  10), unit: Some("A")   , access: Access::RO, typ: Type::I16, name: "BUS_CURRENT"                      });
+            pub const BATTERY_TEMPERATURE:             TypedRegister<i16>    = TypedRegister::new(Register { address: 37022, quantity:  1, gain: Gain::div(  10), unit: Some("°C")  , access: Access::RO, typ: Type::I16, name: "BATTERY_TEMPERATURE"              });
+            pub const REMAINING_CHARGE_DISCHARGE_TIME: TypedRegister<u16>    = TypedRegister::new(Register { address: 37025, quantity:  1, gain: Gain::div(   1), unit: Some("min") , access: Access::RO, typ: Type::U16, name: "REMAINING_CHARGE_DISCHARGE_TIME"  });
+            pub const DCDC_VERSION:                    TypedRegister<String> = TypedRegister::new(Register { address: 37026, quantity: 10, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::STR, name: "DCDC_VERSION"                     });
+            pub const BMS_VERSION:                     TypedRegister<String> = TypedRegister::new(Register { address: 37036, quantity: 10, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::STR, name: "BMS_VERSION"                      });
+            pub const MAXIMUM_CHARGE_POWER:            TypedRegister<u32>    = TypedRegister::new(Register { address: 37046, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RO, typ: Type::U32, name: "MAXIMUM_CHARGE_POWER"             });
+            pub const MAXIMUM_DISCHARGE_POWER:         TypedRegister<u32>    = TypedRegister::new(Register { address: 37048, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RO, typ: Type::U32, name: "MAXIMUM_DISCHARGE_POWER"          });
+            pub const SERIAL_NUMBER:                   TypedRegister<String> = TypedRegister::new(Register { address: 37052, quantity: 10, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::STR, name: "SERIAL_NUMBER"                    });
+            pub const TOTAL_CHARGE:                    TypedRegister<u32>    = TypedRegister::new(Register { address: 37066, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "TOTAL_CHARGE"                     });
+            pub const TOTAL_DISCHARGE:                 TypedRegister<u32>    = TypedRegister::new(Register { address: 37068, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "TOTAL_DISCHARGE"                  });
+            pub const PRODUCT_MODEL:                   TypedRegister<u16>    = TypedRegister::new(Register { address: 47000, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "PRODUCT_MODEL"                    });
+            pub const fn product_model_to_string(model: u16) -> Option<&'static str> {
+                match model {
+                    0 => Some("none"),
+                    1 => Some("LG-RESU"),
+                    2 => Some("LUNA2000"),
+                    _ => None
+                }
+            }
+
+            // ===== settings =====
+            pub const MAXIMUM_CHARGING_POWER:          TypedRegister<u32>    = TypedRegister::new(Register { address: 47075, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RW, typ: Type::U32, name: "MAXIMUM_CHARGING_POWER"           });
+            pub const MAXIMUM_DISCHARGING_POWER:       TypedRegister<u32>    = TypedRegister::new(Register { address: 47077, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RW, typ: Type::U32, name: "MAXIMUM_DISCHARGING_POWER"        });
+            pub const CHARGING_CUTOFF_CAPACITY:        TypedRegister<u16>    = TypedRegister::new(Register { address: 47081, quantity:  1, gain: Gain::div(  10), unit: Some("%")   , access: Access::RW, typ: Type::U16, name: "CHARGING_CUTOFF_CAPACITY"         });
+            pub const DISCHARGING_CUTOFF_CAPACITY:     TypedRegister<u16>    = TypedRegister::new(Register { address: 47082, quantity:  1, gain: Gain::div(  10), unit: Some("%")   , access: Access::RW, typ: Type::U16, name: "DISCHARGING_CUTOFF_CAPACITY"      });
+            pub const FORCED_CHARGE_DISCHARGE_PERIOD:  TypedRegister<u16>    = TypedRegister::new(Register { address: 47083, quantity:  1, gain: Gain::div(   1), unit: Some("min") , access: Access::RW, typ: Type::U16, name: "FORCED_CHARGE_DISCHARGE_PERIOD"   });
+            pub const WORKING_MODE_SETTINGS:           TypedRegister<WorkingMode> = TypedRegister::new(Register { address: 47086, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RW, typ: Type::U16, name: "WORKING_MODE_SETTINGS"            });
+            pub const CHARGE_FROM_GRID:                TypedRegister<u16>    = TypedRegister::new(Register { address: 47087, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RW, typ: Type::U16, name: "CHARGE_FROM_GRID"                 });
+            pub const GRID_CHARGE_CUTOFF_SOC:          TypedRegister<u16>    = TypedRegister::new(Register { address: 47088, quantity:  1, gain: Gain::div(  10), unit: Some("%")   , access: Access::RW, typ: Type::U16, name: "GRID_CHARGE_CUTOFF_SOC"           });
+            pub const FORCIBLE_CHARGE_DISCHARGE:       TypedRegister<u16>    = TypedRegister::new(Register { address: 47100, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::WO, typ: Type::U16, name: "FORCIBLE_CHARGE_DISCHARGE"        });
+            pub const FORCIBLE_CHARGE_DISCHARGE_SOC:   TypedRegister<u16>    = TypedRegister::new(Register { address: 47101, quantity:  1, gain: Gain::div(  10), unit: Some("%")   , access: Access::RW, typ: Type::U16, name: "FORCIBLE_CHARGE_DISCHARGE_SOC"    });
+            pub const BACKUP_POWER_SOC:                TypedRegister<u16>    = TypedRegister::new(Register { address: 47102, quantity:  1, gain: Gain::div(  10), unit: Some("%")   , access: Access::RW, typ: Type::U16, name: "BACKUP_POWER_SOC"                 });
+            pub const FORCIBLE_CHARGE_DISCHARGE_MODE:  TypedRegister<u16>    = TypedRegister::new(Register { address: 47246, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RW, typ: Type::U16, name: "FORCIBLE_CHARGE_DISCHARGE_MODE"   });
+            pub const FORCIBLE_CHARGE_POWER:           TypedRegister<u32>    = TypedRegister::new(Register { address: 47247, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RW, typ: Type::U32, name: "FORCIBLE_CHARGE_POWER"            });
+            pub const FORCIBLE_DISCHARGE_POWER:        TypedRegister<u32>    = TypedRegister::new(Register { address: 47249, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RW, typ: Type::U32, name: "FORCIBLE_DISCHARGE_POWER"         });
+            pub const TIME_OF_USE_PERIODS:             TypedRegister<TouPeriods> = TypedRegister::new(Register { address: 47255, quantity: 43, gain: Gain::div(   1), unit: None        , access: Access::RW, typ: Type::BF , name: "TIME_OF_USE_PERIODS"              });
+
+            pub mod unit_2 {
+                use crate::registers::{Access, Gain, Register, Type, TypedRegister};
+
+                pub const SERIAL_NUMBER:                   TypedRegister<String> = TypedRegister::new(Register { address: 37700, quantity: 10, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::STR, name: "UNIT_2_SERIAL_NUMBER"             });
+                pub const STATE_OF_CAPACITY:               TypedRegister<u16>    = TypedRegister::new(Register { address: 37738, quantity:  1, gain: Gain::div(  10), unit: Some("%")   , access: Access::RO, typ: Type::U16, name: "UNIT_2_STATE_OF_CAPACITY"         });
+                pub const RUNNING_STATUS:                  TypedRegister<u16>    = TypedRegister::new(Register { address: 37741, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "UNIT_2_RUNNING_STATUS"            });
+                pub const CHARGE_DISCHARGE_POWER:          TypedRegister<i32>    = TypedRegister::new(Register { address: 37743, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RO, typ: Type::I32, name: "UNIT_2_CHARGE_DISCHARGE_POWER"    });
+                pub const CHARGE_CAPACITY_DAY:             TypedRegister<u32>    = TypedRegister::new(Register { address: 37746, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "UNIT_2_CHARGE_CAPACITY_DAY"       });
+                pub const DISCHARGE_CAPACITY_DAY:          TypedRegister<u32>    = TypedRegister::new(Register { address: 37748, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "UNIT_2_DISCHARGE_CAPACITY_DAY"    });
+                pub const BUS_VOLTAGE:                     TypedRegister<u16>    = TypedRegister::new(Register { address: 37750, quantity:  1, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "UNIT_2_BUS_VOLTAGE"               });
+                pub const BUS_CURRENT:                     TypedRegister<i16>    = TypedRegister::new(Register { address: 37751, quantity:  1, gain: Gain::div(  10), unit: Some("A")   , access: Access::RO, typ: Type::I16, name: "UNIT_2_BUS_CURRENT"               });
+                pub const BATTERY_TEMPERATURE:             TypedRegister<i16>    = TypedRegister::new(Register { address: 37752, quantity:  1, gain: Gain::div(  10), unit: Some("°C")  , access: Access::RO, typ: Type::I16, name: "UNIT_2_BATTERY_TEMPERATURE"       });
+                pub const TOTAL_CHARGE:                    TypedRegister<u32>    = TypedRegister::new(Register { address: 37753, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "UNIT_2_TOTAL_CHARGE"              });
+                pub const TOTAL_DISCHARGE:                 TypedRegister<u32>    = TypedRegister::new(Register { address: 37755, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "UNIT_2_TOTAL_DISCHARGE"           });
+                pub const PRODUCT_MODEL:                   TypedRegister<u16>    = TypedRegister::new(Register { address: 47089, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "UNIT_2_PRODUCT_MODEL"             });
+            }
+
+            /// Values of both battery units together
+            pub mod combined {
+                use crate::registers::{Access, Gain, Register, Type, TypedRegister};
+
+                pub const RATED_CAPACITY:                  TypedRegister<u32>    = TypedRegister::new(Register { address: 37758, quantity:  2, gain: Gain::div(   1), unit: Some("Wh")  , access: Access::RO, typ: Type::U32, name: "COMBINED_RATED_CAPACITY"          });
+                pub const STATE_OF_CAPACITY:               TypedRegister<u16>    = TypedRegister::new(Register { address: 37760, quantity:  1, gain: Gain::div(  10), unit: Some("%")   , access: Access::RO, typ: Type::U16, name: "COMBINED_STATE_OF_CAPACITY"       });
+                pub const RUNNING_STATUS:                  TypedRegister<u16>    = TypedRegister::new(Register { address: 37762, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "COMBINED_RUNNING_STATUS"          });
+                pub const BUS_VOLTAGE:                     TypedRegister<u16>    = TypedRegister::new(Register { address: 37763, quantity:  1, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::U16, name: "COMBINED_BUS_VOLTAGE"             });
+                pub const BUS_CURRENT:                     TypedRegister<i16>    = TypedRegister::new(Register { address: 37764, quantity:  1, gain: Gain::div(  10), unit: Some("A")   , access: Access::RO, typ: Type::I16, name: "COMBINED_BUS_CURRENT"             });
+                pub const CHARGE_DISCHARGE_POWER:          TypedRegister<i32>    = TypedRegister::new(Register { address: 37765, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RO, typ: Type::I32, name: "COMBINED_CHARGE_DISCHARGE_POWER"  });
+                pub const TOTAL_CHARGE:                    TypedRegister<u32>    = TypedRegister::new(Register { address: 37780, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "COMBINED_TOTAL_CHARGE"            });
+                pub const TOTAL_DISCHARGE:                 TypedRegister<u32>    = TypedRegister::new(Register { address: 37782, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "COMBINED_TOTAL_DISCHARGE"         });
+                pub const CHARGE_CAPACITY_DAY:             TypedRegister<u32>    = TypedRegister::new(Register { address: 37784, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "COMBINED_CHARGE_CAPACITY_DAY"     });
+                pub const DISCHARGE_CAPACITY_DAY:          TypedRegister<u32>    = TypedRegister::new(Register { address: 37786, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: "COMBINED_DISCHARGE_CAPACITY_DAY"  });
+            }
+
+            /// Registers of one battery pack, `base` is the address of its
+            /// serial number and `temperature` of its maximum temperature
+            macro_rules! battery_pack {
+                ($module:ident, $name:literal, $base:literal, $temperature:literal) => {
+                    pub mod $module {
+                        use crate::registers::{Access, Gain, Register, Type, TypedRegister};
+
+                        pub const SERIAL_NUMBER:                   TypedRegister<String> = TypedRegister::new(Register { address: $base +  0, quantity: 10, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::STR, name: concat!($name, "SERIAL_NUMBER") });
+                        pub const FIRMWARE_VERSION:                TypedRegister<String> = TypedRegister::new(Register { address: $base + 10, quantity: 15, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::STR, name: concat!($name, "FIRMWARE_VERSION") });
+                        pub const WORKING_STATUS:                  TypedRegister<u16>    = TypedRegister::new(Register { address: $base + 28, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: concat!($name, "WORKING_STATUS") });
+                        pub const STATE_OF_CAPACITY:               TypedRegister<u16>    = TypedRegister::new(Register { address: $base + 29, quantity:  1, gain: Gain::div(  10), unit: Some("%")   , access: Access::RO, typ: Type::U16, name: concat!($name, "STATE_OF_CAPACITY") });
+                        pub const CHARGE_DISCHARGE_POWER:          TypedRegister<i32>    = TypedRegister::new(Register { address: $base + 33, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RO, typ: Type::I32, name: concat!($name, "CHARGE_DISCHARGE_POWER") });
+                        pub const VOLTAGE:                         TypedRegister<u16>    = TypedRegister::new(Register { address: $base + 35, quantity:  1, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::U16, name: concat!($name, "VOLTAGE") });
+                        pub const CURRENT:                         TypedRegister<i16>    = TypedRegister::new(Register { address: $base + 36, quantity:  1, gain: Gain::div(  10), unit: Some("A")   , access: Access::RO, typ: Type::I16, name: concat!($name, "CURRENT") });
+                        pub const TOTAL_CHARGE:                    TypedRegister<u32>    = TypedRegister::new(Register { address: $base + 38, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: concat!($name, "TOTAL_CHARGE") });
+                        pub const TOTAL_DISCHARGE:                 TypedRegister<u32>    = TypedRegister::new(Register { address: $base + 40, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::U32, name: concat!($name, "TOTAL_DISCHARGE") });
+                        pub const MAXIMUM_TEMPERATURE:             TypedRegister<i16>    = TypedRegister::new(Register { address: $temperature +  0, quantity:  1, gain: Gain::div(  10), unit: Some("°C")  , access: Access::RO, typ: Type::I16, name: concat!($name, "MAXIMUM_TEMPERATURE") });
+                        pub const MINIMUM_TEMPERATURE:             TypedRegister<i16>    = TypedRegister::new(Register { address: $temperature +  1, quantity:  1, gain: Gain::div(  10), unit: Some("°C")  , access: Access::RO, typ: Type::I16, name: concat!($name, "MINIMUM_TEMPERATURE") });
+                    }
+                };
+            }
+
+            battery_pack!(unit_1_pack_1, "UNIT_1_PACK_1_", 38200, 38452);
+            battery_pack!(unit_1_pack_2, "UNIT_1_PACK_2_", 38242, 38454);
+            battery_pack!(unit_1_pack_3, "UNIT_1_PACK_3_", 38284, 38456);
+            battery_pack!(unit_2_pack_1, "UNIT_2_PACK_1_", 38326, 38458);
+            battery_pack!(unit_2_pack_2, "UNIT_2_PACK_2_", 38368, 38460);
+            battery_pack!(unit_2_pack_3, "UNIT_2_PACK_3_", 38410, 38462);
+        }
+        /// Smart power meter (DTSU666-H) connected to the inverter's RS485
+        /// port, read through the inverter.
+        ///
+        /// Positive power flows into the grid, i.e. export. Positive active
+        /// energy is the energy exported to the grid, reverse active energy
+        /// the energy imported from it.
+        ///
+        /// The names carry a `METER_` prefix to tell them from the
+        /// inverter's registers of the same meaning.
+        pub mod meter {
+            use crate::registers::{Access, Gain, Register, Type, TypedRegister};
+
+            pub const STATUS:                       TypedRegister<u16>    = TypedRegister::new(Register { address: 37100, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "METER_STATUS"                     });
+            pub const fn status_to_string(status: u16) -> Option<&'static str> {
+                match status {
+                    0 => Some("offline"),
+                    1 => Some("normal"),
+                    _ => None
+                }
+            }
+            pub const PHASE_A_VOLTAGE:              TypedRegister<i32>    = TypedRegister::new(Register { address: 37101, quantity:  2, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::I32, name: "METER_PHASE_A_VOLTAGE"            });
+            pub const PHASE_B_VOLTAGE:              TypedRegister<i32>    = TypedRegister::new(Register { address: 37103, quantity:  2, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::I32, name: "METER_PHASE_B_VOLTAGE"            });
+            pub const PHASE_C_VOLTAGE:              TypedRegister<i32>    = TypedRegister::new(Register { address: 37105, quantity:  2, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::I32, name: "METER_PHASE_C_VOLTAGE"            });
+            pub const PHASE_A_CURRENT:              TypedRegister<i32>    = TypedRegister::new(Register { address: 37107, quantity:  2, gain: Gain::div( 100), unit: Some("A")   , access: Access::RO, typ: Type::I32, name: "METER_PHASE_A_CURRENT"            });
+            pub const PHASE_B_CURRENT:              TypedRegister<i32>    = TypedRegister::new(Register { address: 37109, quantity:  2, gain: Gain::div( 100), unit: Some("A")   , access: Access::RO, typ: Type::I32, name: "METER_PHASE_B_CURRENT"            });
+            pub const PHASE_C_CURRENT:              TypedRegister<i32>    = TypedRegister::new(Register { address: 37111, quantity:  2, gain: Gain::div( 100), unit: Some("A")   , access: Access::RO, typ: Type::I32, name: "METER_PHASE_C_CURRENT"            });
+            pub const ACTIVE_POWER:                 TypedRegister<i32>    = TypedRegister::new(Register { address: 37113, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RO, typ: Type::I32, name: "METER_ACTIVE_POWER"               });
+            pub const REACTIVE_POWER:               TypedRegister<i32>    = TypedRegister::new(Register { address: 37115, quantity:  2, gain: Gain::div(   1), unit: Some("var") , access: Access::RO, typ: Type::I32, name: "METER_REACTIVE_POWER"             });
+            pub const POWER_FACTOR:                 TypedRegister<i16>    = TypedRegister::new(Register { address: 37117, quantity:  1, gain: Gain::div(1000), unit: None        , access: Access::RO, typ: Type::I16, name: "METER_POWER_FACTOR"               });
+            pub const GRID_FREQUENCY:               TypedRegister<i16>    = TypedRegister::new(Register { address: 37118, quantity:  1, gain: Gain::div( 100), unit: Some("Hz")  , access: Access::RO, typ: Type::I16, name: "METER_GRID_FREQUENCY"             });
+            pub const POSITIVE_ACTIVE_ENERGY:       TypedRegister<i32>    = TypedRegister::new(Register { address: 37119, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::I32, name: "METER_POSITIVE_ACTIVE_ENERGY"     });
+            pub const REVERSE_ACTIVE_ENERGY:        TypedRegister<i32>    = TypedRegister::new(Register { address: 37121, quantity:  2, gain: Gain::div( 100), unit: Some("kWh") , access: Access::RO, typ: Type::I32, name: "METER_REVERSE_ACTIVE_ENERGY"      });
+            pub const ACCUMULATED_REACTIVE_ENERGY:  TypedRegister<i32>    = TypedRegister::new(Register { address: 37123, quantity:  2, gain: Gain::div( 100), unit: Some("kvarh"), access: Access::RO, typ: Type::I32, name: "METER_ACCUMULATED_REACTIVE_ENERGY" });
+            pub const METER_TYPE:                   TypedRegister<u16>    = TypedRegister::new(Register { address: 37125, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "METER_TYPE"                       });
+            pub const fn meter_type_to_string(meter_type: u16) -> Option<&'static str> {
+                match meter_type {
+                    0 => Some("single-phase"),
+                    1 => Some("three-phase"),
+                    _ => None
+                }
+            }
+            pub const LINE_VOLTAGE_A_B:             TypedRegister<i32>    = TypedRegister::new(Register { address: 37126, quantity:  2, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::I32, name: "METER_LINE_VOLTAGE_A_B"           });
+            pub const LINE_VOLTAGE_B_C:             TypedRegister<i32>    = TypedRegister::new(Register { address: 37128, quantity:  2, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::I32, name: "METER_LINE_VOLTAGE_B_C"           });
+            pub const LINE_VOLTAGE_C_A:             TypedRegister<i32>    = TypedRegister::new(Register { address: 37130, quantity:  2, gain: Gain::div(  10), unit: Some("V")   , access: Access::RO, typ: Type::I32, name: "METER_LINE_VOLTAGE_C_A"           });
+            pub const PHASE_A_ACTIVE_POWER:         TypedRegister<i32>    = TypedRegister::new(Register { address: 37132, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RO, typ: Type::I32, name: "METER_PHASE_A_ACTIVE_POWER"       });
+            pub const PHASE_B_ACTIVE_POWER:         TypedRegister<i32>    = TypedRegister::new(Register { address: 37134, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RO, typ: Type::I32, name: "METER_PHASE_B_ACTIVE_POWER"       });
+            pub const PHASE_C_ACTIVE_POWER:         TypedRegister<i32>    = TypedRegister::new(Register { address: 37136, quantity:  2, gain: Gain::div(   1), unit: Some("W")   , access: Access::RO, typ: Type::I32, name: "METER_PHASE_C_ACTIVE_POWER"       });
+            pub const METER_MODEL_DETECTION:        TypedRegister<u16>    = TypedRegister::new(Register { address: 37138, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RO, typ: Type::U16, name: "METER_MODEL_DETECTION"            });
+            pub const fn meter_model_detection_to_string(result: u16) -> Option<&'static str> {
+                match result {
+                    0 => Some("being identified"),
+                    1 => Some("matches"),
+                    2 => Some("does not match"),
+                    _ => None
+                }
+            }
+
+            /// All meter registers, e.g. to read them in one batch
+            pub const REGISTERS: [&Register<'static>; 22] = [
+                STATUS.erased(),
+                PHASE_A_VOLTAGE.erased(),
+                PHASE_B_VOLTAGE.erased(),
+                PHASE_C_VOLTAGE.erased(),
+                PHASE_A_CURRENT.erased(),
+                PHASE_B_CURRENT.erased(),
+                PHASE_C_CURRENT.erased(),
+                ACTIVE_POWER.erased(),
+                REACTIVE_POWER.erased(),
+                POWER_FACTOR.erased(),
+                GRID_FREQUENCY.erased(),
+                POSITIVE_ACTIVE_ENERGY.erased(),
+                REVERSE_ACTIVE_ENERGY.erased(),
+                ACCUMULATED_REACTIVE_ENERGY.erased(),
+                METER_TYPE.erased(),
+                LINE_VOLTAGE_A_B.erased(),
+                LINE_VOLTAGE_B_C.erased(),
+                LINE_VOLTAGE_C_A.erased(),
+                PHASE_A_ACTIVE_POWER.erased(),
+                PHASE_B_ACTIVE_POWER.erased(),
+                PHASE_C_ACTIVE_POWER.erased(),
+                METER_MODEL_DETECTION.erased(),
+            ];
         }
         // =======================================
         // ===== START OF READ-WRITE SECTION =====
         // =======================================
 
-        pub const STARTUP:                          Register = Register { address: 40200, quantity:  1, gain: 0, unit: None        , access: Access::WO, typ: Type::U16, name: "STARTUP"                          };
-        pub const SHUTDOWN:                         Register = Register { address: 40201, quantity:  1, gain: 0, unit: None        , access: Access::WO, typ: Type::U16, name: "SHUTDOWN"                         };
-        pub const GRID_CODE:                        Register = Register { address: 42000, quantity:  1, gain: 0, unit: None        , access: Access::RW, typ: Type::U16, name: "GRID_CODE"                        };
+        pub const REACTIVE_POWER_COMPENSATION_PF:   TypedRegister<i16>    = TypedRegister::new(Register { address: 40122, quantity:  1, gain: Gain::div(1000), unit: None        , access: Access::RW, typ: Type::I16, name: "REACTIVE_POWER_COMPENSATION_PF"   });
+        /// Reactive power relative to the apparent power
+        pub const REACTIVE_POWER_COMPENSATION_Q_S:  TypedRegister<i16>    = TypedRegister::new(Register { address: 40123, quantity:  1, gain: Gain::div(1000), unit: None        , access: Access::RW, typ: Type::I16, name: "REACTIVE_POWER_COMPENSATION_Q_S"  });
+        pub const ACTIVE_POWER_PERCENTAGE_DERATING: TypedRegister<u16>    = TypedRegister::new(Register { address: 40125, quantity:  1, gain: Gain::div(  10), unit: Some("%")   , access: Access::RW, typ: Type::U16, name: "ACTIVE_POWER_PERCENTAGE_DERATING" });
+        pub const ACTIVE_POWER_FIXED_VALUE_DERATING:TypedRegister<u32>    = TypedRegister::new(Register { address: 40126, quantity:  2, gain: Gain::div(1000), unit: Some("kW")  , access: Access::RW, typ: Type::U32, name: "ACTIVE_POWER_FIXED_VALUE_DERATING" });
+        pub const STARTUP:                          TypedRegister<u16>    = TypedRegister::new(Register { address: 40200, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::WO, typ: Type::U16, name: "STARTUP"                          });
+        pub const SHUTDOWN:                         TypedRegister<u16>    = TypedRegister::new(Register { address: 40201, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::WO, typ: Type::U16, name: "SHUTDOWN"                         });
+        pub const GRID_CODE:                        TypedRegister<u16>    = TypedRegister::new(Register { address: 42000, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RW, typ: Type::U16, name: "GRID_CODE"                        });
         
-        pub const TIME_ZONE:                        Register = Register { address: 43006, quantity:  1, gain: 0, unit: Some("min") , access: Access::RW, typ: Type::I16, name: "TIME_ZONE"                        };
+        pub const TIME_ZONE:                        TypedRegister<i16>    = TypedRegister::new(Register { address: 43006, quantity:  1, gain: Gain::div(   1), unit: Some("min") , access: Access::RW, typ: Type::I16, name: "TIME_ZONE"                        });
+        pub const ACTIVE_POWER_CONTROL_MODE:        TypedRegister<ActivePowerControlMode> = TypedRegister::new(Register { address: 47415, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::RW, typ: Type::U16, name: "ACTIVE_POWER_CONTROL_MODE"        });
+        pub const MAXIMUM_FEED_GRID_POWER:          TypedRegister<i32>    = TypedRegister::new(Register { address: 47416, quantity:  2, gain: Gain::div(1000), unit: Some("kW")  , access: Access::RW, typ: Type::I32, name: "MAXIMUM_FEED_GRID_POWER"          });
+        pub const MAXIMUM_FEED_GRID_POWER_PERCENT:  TypedRegister<i16>    = TypedRegister::new(Register { address: 47418, quantity:  1, gain: Gain::div(  10), unit: Some("%")   , access: Access::RW, typ: Type::I16, name: "MAXIMUM_FEED_GRID_POWER_PERCENT"  });
+        /// Keeps a [login](crate::Inverter::login) session alive
+        pub const HEARTBEAT:                        TypedRegister<u16>    = TypedRegister::new(Register { address: 49999, quantity:  1, gain: Gain::div(   1), unit: None        , access: Access::WO, typ: Type::U16, name: "HEARTBEAT"                        });
 
     }
+
+    #[cfg(test)]
+    mod gain_tests {
+        use super::*;
+
+        fn str_frame(s: &str, quantity: u8) -> Vec<u16> {
+            let mut bytes = s.as_bytes().to_vec();
+            bytes.resize(quantity as usize * 2, 0);
+            bytes
+                .chunks(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect()
+        }
+
+        /// Decode a raw frame as read from the inverter and compare the
+        /// engineering value with the one given in the Huawei register table
+        macro_rules! gain_test {
+            ($test:ident, $reg:expr, str $expected:expr) => {
+                #[test]
+                fn $test() {
+                    let reg = &$reg;
+                    let raw = str_frame($expected, reg.quantity);
+                    let val = RegValue {
+                        reg,
+                        val: reg.typ.convert(&raw).unwrap(),
+                    };
+                    assert_eq!(val.to_string().unwrap(), $expected);
+                }
+            };
+            ($test:ident, $reg:expr, bf $raw:expr) => {
+                #[test]
+                fn $test() {
+                    let reg = &$reg;
+                    let raw: &[u16] = &$raw;
+                    assert_eq!(raw.len(), reg.quantity as usize);
+                    let val = RegValue {
+                        reg,
+                        val: reg.typ.convert(raw).unwrap(),
+                    };
+                    assert!(val.to_float().is_err());
+                }
+            };
+            ($test:ident, $reg:expr, $raw:expr, $expected:expr) => {
+                #[test]
+                fn $test() {
+                    let reg = &$reg;
+                    let raw: &[u16] = &$raw;
+                    assert_eq!(raw.len(), reg.quantity as usize);
+                    let val = RegValue {
+                        reg,
+                        val: reg.typ.convert(raw).unwrap(),
+                    };
+                    let float = val.to_float().unwrap();
+                    assert!(
+                        (float - $expected).abs() < 1e-9,
+                        "{}: {} != {}",
+                        reg.name,
+                        float,
+                        $expected
+                    );
+                }
+            };
+        }
+
+        gain_test!(model, MODEL, str "SUN2000-10KTL-M1");
+        gain_test!(sn, SN, str "HV2140012345");
+        gain_test!(pn, PN, str "01074356");
+        gain_test!(model_id, MODEL_ID, [428], 428.0);
+        gain_test!(number_of_pv_strings, NUMBER_OF_PV_STRINGS, [2], 2.0);
+        gain_test!(number_of_mpp_trackers, NUMBER_OF_MPP_TRACKERS, [2], 2.0);
+        gain_test!(rated_power, RATED_POWER, [0x0000, 0x2710], 10.0);
+        gain_test!(
+            maximum_active_power,
+            MAXIMUM_ACTIVE_POWER,
+            [0x0000, 0x2AF8],
+            11.0
+        );
+        gain_test!(
+            maximum_apparent_power,
+            MAXIMUM_APPARENT_POWER,
+            [0x0000, 0x2AF8],
+            11.0
+        );
+        gain_test!(
+            maximum_reactive_power_to_grid,
+            MAXIMUM_REACTIVE_POWER_TO_GRID,
+            [0x0000, 0x1A2C],
+            6.7
+        );
+        gain_test!(
+            maximum_apparent_power_from_grid,
+            MAXIMUM_APPARENT_POWER_FROM_GRID,
+            [0x0000, 0x55F0],
+            22.0
+        );
+        gain_test!(state_1, STATE_1, bf[0x0000]);
+        gain_test!(state_2, STATE_2, bf[0x0000]);
+        gain_test!(state_3, STATE_3, bf [0x0000, 0x0000]);
+        gain_test!(alarm_1, ALARM_1, bf[0x0000]);
+        gain_test!(alarm_2, ALARM_2, bf[0x0000]);
+        gain_test!(alarm_3, ALARM_3, bf[0x0000]);
+        gain_test!(alarms, ALARMS, bf[0x0000, 0x0000, 0x0000]);
+        gain_test!(pv1_voltage, PV1_VOLTAGE, [0x0FA0], 400.0);
+        gain_test!(pv1_current, PV1_CURRENT, [0x0339], 8.25);
+        gain_test!(pv2_voltage, PV2_VOLTAGE, [0x0E42], 365.0);
+        gain_test!(pv2_current, PV2_CURRENT, [0x01F4], 5.0);
+        gain_test!(pv3_voltage, PV3_VOLTAGE, [0x0000], 0.0);
+        gain_test!(pv3_current, PV3_CURRENT, [0xFFFF], -0.01);
+        gain_test!(pv4_voltage, PV4_VOLTAGE, [0x0BB8], 300.0);
+        gain_test!(pv4_current, PV4_CURRENT, [0x0064], 1.0);
+        gain_test!(input_power, INPUT_POWER, [0x0000, 0x1F40], 8.0);
+        gain_test!(line_voltage_a_b, LINE_VOLTAGE_A_B, [0x0FA0], 400.0);
+        gain_test!(line_voltage_b_c, LINE_VOLTAGE_B_C, [0x0FAA], 401.0);
+        gain_test!(line_voltage_c_a, LINE_VOLTAGE_C_A, [0x0F96], 399.0);
+        gain_test!(phase_voltage_a, PHASE_VOLTAGE_A, [0x0906], 231.0);
+        gain_test!(phase_voltage_b, PHASE_VOLTAGE_B, [0x0901], 230.5);
+        gain_test!(phase_voltage_c, PHASE_VOLTAGE_C, [0x090B], 231.5);
+        gain_test!(phase_current_a, PHASE_CURRENT_A, [0x0000, 0x2E18], 11.8);
+        gain_test!(phase_current_b, PHASE_CURRENT_B, [0x0000, 0x2EE0], 12.0);
+        gain_test!(phase_current_c, PHASE_CURRENT_C, [0xFFFF, 0xFC18], -1.0);
+        gain_test!(
+            peak_active_power_day,
+            PEAK_ACTIVE_POWER_DAY,
+            [0x0000, 0x2148],
+            8.52
+        );
+        gain_test!(active_power, ACTIVE_POWER, [0x0000, 0x1D4C], 7.5);
+        gain_test!(reactive_power, REACTIVE_POWER, [0xFFFF, 0xFF38], -0.2);
+        gain_test!(power_factor, POWER_FACTOR, [0x03E0], 0.992);
+        gain_test!(grid_frequency, GRID_FREQUENCY, [0x1388], 50.0);
+        gain_test!(efficiency, EFFICIENCY, [0x26AC], 99.0);
+        gain_test!(internal_temperature, INTERNAL_TEMPERATURE, [0xFFCE], -5.0);
+        gain_test!(insulation_resistance, INSULATION_RESISTANCE, [0x0BB8], 3.0);
+        gain_test!(device_status, DEVICE_STATUS, [0x0200], 512.0);
+        gain_test!(fault_code, FAULT_CODE, [0x0000], 0.0);
+        gain_test!(startup_time, STARTUP_TIME, [0x6601, 0x2A80], 1711352448.0);
+        gain_test!(shutdown_time, SHUTDOWN_TIME, [0xFFFF, 0xFFFF], 4294967295.0);
+        gain_test!(acc_energy_yield, ACC_ENERGY_YIELD, [0x0001, 0x86A0], 1000.0);
+        gain_test!(energy_yield_day, ENERGY_YIELD_DAY, [0x0000, 0x0F3C], 39.0);
+        gain_test!(
+            storage_running_status,
+            storage::RUNNING_STATUS,
+            [0x0002],
+            2.0
+        );
+        gain_test!(
+            storage_charge_discharge_power,
+            storage::CHARGE_DISCHARGE_POWER,
+            [0xFFFF, 0xF830],
+            -2000.0
+        );
+        gain_test!(
+            storage_charge_capacity_day,
+            storage::CHARGE_CAPACITY_DAY,
+            [0x0000, 0x04B0],
+            12.0
+        );
+        gain_test!(
+            storage_discharge_capacity_day,
+            storage::DISCHARGE_CAPACITY_DAY,
+            [0x0000, 0x0384],
+            9.0
+        );
+        gain_test!(storage_bus_voltage, storage::BUS_VOLTAGE, [0x0EA6], 375.0);
+        gain_test!(
+            storage_state_of_capacity,
+            storage::STATE_OF_CAPACITY,
+            [0x0371],
+            88.1
+        );
+        gain_test!(storage_working_mode, storage::WORKING_MODE, [0x0004], 4.0);
+        gain_test!(
+            storage_rated_charge_power,
+            storage::RATED_CHARGE_POWER,
+            [0x0000, 0x0DAC],
+            3500.0
+        );
+        gain_test!(storage_bus_current, storage::BUS_CURRENT, [0xFFEC], -2.0);
+        gain_test!(
+            storage_battery_temperature,
+            storage::BATTERY_TEMPERATURE,
+            [0x00E6],
+            23.0
+        );
+        gain_test!(
+            storage_remaining_charge_discharge_time,
+            storage::REMAINING_CHARGE_DISCHARGE_TIME,
+            [0x005A],
+            90.0
+        );
+        gain_test!(
+            storage_maximum_discharge_power,
+            storage::MAXIMUM_DISCHARGE_POWER,
+            [0x0000, 0x1388],
+            5000.0
+        );
+        gain_test!(
+            storage_total_charge,
+            storage::TOTAL_CHARGE,
+            [0x0002, 0x3E4C],
+            1470.2
+        );
+        gain_test!(
+            storage_total_discharge,
+            storage::TOTAL_DISCHARGE,
+            [0x0001, 0xFBD0],
+            1300.0
+        );
+        gain_test!(
+            storage_charging_cutoff_capacity,
+            storage::CHARGING_CUTOFF_CAPACITY,
+            [0x03E8],
+            100.0
+        );
+        gain_test!(
+            storage_discharging_cutoff_capacity,
+            storage::DISCHARGING_CUTOFF_CAPACITY,
+            [0x0064],
+            10.0
+        );
+        gain_test!(
+            storage_working_mode_settings,
+            storage::WORKING_MODE_SETTINGS,
+            [0x0002],
+            2.0
+        );
+        gain_test!(
+            storage_grid_charge_cutoff_soc,
+            storage::GRID_CHARGE_CUTOFF_SOC,
+            [0x0032],
+            5.0
+        );
+        gain_test!(
+            storage_forcible_charge_power,
+            storage::FORCIBLE_CHARGE_POWER,
+            [0x0000, 0x09C4],
+            2500.0
+        );
+        gain_test!(
+            storage_unit_2_state_of_capacity,
+            storage::unit_2::STATE_OF_CAPACITY,
+            [0x0258],
+            60.0
+        );
+        gain_test!(
+            storage_unit_2_charge_discharge_power,
+            storage::unit_2::CHARGE_DISCHARGE_POWER,
+            [0xFFFF, 0xFC18],
+            -1000.0
+        );
+        gain_test!(
+            storage_unit_2_battery_temperature,
+            storage::unit_2::BATTERY_TEMPERATURE,
+            [0xFFCE],
+            -5.0
+        );
+        gain_test!(
+            storage_combined_rated_capacity,
+            storage::combined::RATED_CAPACITY,
+            [0x0000, 0x3A98],
+            15000.0
+        );
+        gain_test!(
+            storage_combined_state_of_capacity,
+            storage::combined::STATE_OF_CAPACITY,
+            [0x02EE],
+            75.0
+        );
+        gain_test!(
+            storage_combined_charge_discharge_power,
+            storage::combined::CHARGE_DISCHARGE_POWER,
+            [0x0000, 0x0BB8],
+            3000.0
+        );
+        gain_test!(
+            storage_pack_state_of_capacity,
+            storage::unit_1_pack_2::STATE_OF_CAPACITY,
+            [0x0384],
+            90.0
+        );
+        gain_test!(
+            storage_pack_voltage,
+            storage::unit_2_pack_3::VOLTAGE,
+            [0x0D05],
+            333.3
+        );
+        gain_test!(
+            storage_pack_current,
+            storage::unit_1_pack_1::CURRENT,
+            [0xFF9C],
+            -10.0
+        );
+        gain_test!(
+            storage_pack_maximum_temperature,
+            storage::unit_1_pack_3::MAXIMUM_TEMPERATURE,
+            [0x00FA],
+            25.0
+        );
+        gain_test!(storage_serial_number, storage::SERIAL_NUMBER, str "HV2150012345");
+        gain_test!(
+            storage_pack_firmware_version,
+            storage::unit_2_pack_1::FIRMWARE_VERSION,
+            str "V100R002C00SPC107"
+        );
+        gain_test!(
+            storage_rated_discharge_power,
+            storage::RATED_DISCHARGE_POWER,
+            [0x0000, 0x1388],
+            5000.0
+        );
+        gain_test!(storage_fault_id, storage::FAULT_ID, [0x0000], 0.0);
+        gain_test!(storage_dcdc_version, storage::DCDC_VERSION, str "V100R001C00SPC103");
+        gain_test!(storage_bms_version, storage::BMS_VERSION, str "V100R002C00SPC107");
+        gain_test!(
+            storage_maximum_charge_power,
+            storage::MAXIMUM_CHARGE_POWER,
+            [0x0000, 0x0DAC],
+            3500.0
+        );
+        gain_test!(storage_product_model, storage::PRODUCT_MODEL, [0x0002], 2.0);
+        gain_test!(
+            storage_maximum_charging_power,
+            storage::MAXIMUM_CHARGING_POWER,
+            [0x0000, 0x1388],
+            5000.0
+        );
+        gain_test!(
+            storage_maximum_discharging_power,
+            storage::MAXIMUM_DISCHARGING_POWER,
+            [0x0000, 0x1388],
+            5000.0
+        );
+        gain_test!(
+            storage_forced_charge_discharge_period,
+            storage::FORCED_CHARGE_DISCHARGE_PERIOD,
+            [0x003C],
+            60.0
+        );
+        gain_test!(
+            storage_charge_from_grid,
+            storage::CHARGE_FROM_GRID,
+            [0x0001],
+            1.0
+        );
+        gain_test!(
+            storage_forcible_charge_discharge,
+            storage::FORCIBLE_CHARGE_DISCHARGE,
+            [0x0001],
+            1.0
+        );
+        gain_test!(
+            storage_forcible_charge_discharge_soc,
+            storage::FORCIBLE_CHARGE_DISCHARGE_SOC,
+            [0x0384],
+            90.0
+        );
+        gain_test!(
+            storage_backup_power_soc,
+            storage::BACKUP_POWER_SOC,
+            [0x00C8],
+            20.0
+        );
+        gain_test!(
+            storage_forcible_charge_discharge_mode,
+            storage::FORCIBLE_CHARGE_DISCHARGE_MODE,
+            [0x0000],
+            0.0
+        );
+        gain_test!(
+            storage_forcible_discharge_power,
+            storage::FORCIBLE_DISCHARGE_POWER,
+            [0x0000, 0x07D0],
+            2000.0
+        );
+        gain_test!(storage_time_of_use_periods, storage::TIME_OF_USE_PERIODS, bf [0x0000; 43]);
+        gain_test!(storage_unit_2_serial_number, storage::unit_2::SERIAL_NUMBER, str "HV2150067890");
+        gain_test!(
+            storage_unit_2_running_status,
+            storage::unit_2::RUNNING_STATUS,
+            [0x0002],
+            2.0
+        );
+        gain_test!(
+            storage_unit_2_charge_capacity_day,
+            storage::unit_2::CHARGE_CAPACITY_DAY,
+            [0x0000, 0x0258],
+            6.0
+        );
+        gain_test!(
+            storage_unit_2_discharge_capacity_day,
+            storage::unit_2::DISCHARGE_CAPACITY_DAY,
+            [0x0000, 0x01F4],
+            5.0
+        );
+        gain_test!(
+            storage_unit_2_bus_voltage,
+            storage::unit_2::BUS_VOLTAGE,
+            [0x0E9C],
+            374.0
+        );
+        gain_test!(
+            storage_unit_2_bus_current,
+            storage::unit_2::BUS_CURRENT,
+            [0x0014],
+            2.0
+        );
+        gain_test!(
+            storage_unit_2_total_charge,
+            storage::unit_2::TOTAL_CHARGE,
+            [0x0001, 0x0D40],
+            689.28
+        );
+        gain_test!(
+            storage_unit_2_total_discharge,
+            storage::unit_2::TOTAL_DISCHARGE,
+            [0x0000, 0xEA60],
+            600.0
+        );
+        gain_test!(
+            storage_unit_2_product_model,
+            storage::unit_2::PRODUCT_MODEL,
+            [0x0002],
+            2.0
+        );
+        gain_test!(
+            storage_combined_running_status,
+            storage::combined::RUNNING_STATUS,
+            [0x0002],
+            2.0
+        );
+        gain_test!(
+            storage_combined_bus_voltage,
+            storage::combined::BUS_VOLTAGE,
+            [0x0EA6],
+            375.0
+        );
+        gain_test!(
+            storage_combined_bus_current,
+            storage::combined::BUS_CURRENT,
+            [0x0050],
+            8.0
+        );
+        gain_test!(
+            storage_combined_total_charge,
+            storage::combined::TOTAL_CHARGE,
+            [0x0003, 0x4B8C],
+            2159.48
+        );
+        gain_test!(
+            storage_combined_total_discharge,
+            storage::combined::TOTAL_DISCHARGE,
+            [0x0002, 0xF1A0],
+            1929.28
+        );
+        gain_test!(
+            storage_combined_charge_capacity_day,
+            storage::combined::CHARGE_CAPACITY_DAY,
+            [0x0000, 0x0708],
+            18.0
+        );
+        gain_test!(
+            storage_combined_discharge_capacity_day,
+            storage::combined::DISCHARGE_CAPACITY_DAY,
+            [0x0000, 0x0578],
+            14.0
+        );
+        gain_test!(storage_pack_serial_number, storage::unit_1_pack_1::SERIAL_NUMBER, str "HV2160011111");
+        gain_test!(
+            storage_pack_working_status,
+            storage::unit_1_pack_2::WORKING_STATUS,
+            [0x0002],
+            2.0
+        );
+        gain_test!(
+            storage_pack_charge_discharge_power,
+            storage::unit_1_pack_3::CHARGE_DISCHARGE_POWER,
+            [0xFFFF, 0xFE0C],
+            -500.0
+        );
+        gain_test!(
+            storage_pack_total_charge,
+            storage::unit_2_pack_1::TOTAL_CHARGE,
+            [0x0000, 0xC350],
+            500.0
+        );
+        gain_test!(
+            storage_pack_total_discharge,
+            storage::unit_2_pack_2::TOTAL_DISCHARGE,
+            [0x0000, 0xAFC8],
+            450.0
+        );
+        gain_test!(
+            storage_pack_minimum_temperature,
+            storage::unit_2_pack_3::MINIMUM_TEMPERATURE,
+            [0xFFF6],
+            -1.0
+        );
+        gain_test!(meter_status, meter::STATUS, [0x0001], 1.0);
+        gain_test!(
+            meter_phase_a_voltage,
+            meter::PHASE_A_VOLTAGE,
+            [0x0000, 0x0906],
+            231.0
+        );
+        gain_test!(
+            meter_phase_a_current,
+            meter::PHASE_A_CURRENT,
+            [0xFFFF, 0xFE0C],
+            -5.0
+        );
+        gain_test!(
+            meter_active_power,
+            meter::ACTIVE_POWER,
+            [0xFFFF, 0xF060],
+            -4000.0
+        );
+        gain_test!(
+            meter_reactive_power,
+            meter::REACTIVE_POWER,
+            [0x0000, 0x00C8],
+            200.0
+        );
+        gain_test!(meter_power_factor, meter::POWER_FACTOR, [0x03DE], 0.99);
+        gain_test!(meter_grid_frequency, meter::GRID_FREQUENCY, [0x1388], 50.0);
+        gain_test!(
+            meter_positive_active_energy,
+            meter::POSITIVE_ACTIVE_ENERGY,
+            [0x0001, 0x86A0],
+            1000.0
+        );
+        gain_test!(
+            meter_reverse_active_energy,
+            meter::REVERSE_ACTIVE_ENERGY,
+            [0x0000, 0x2710],
+            100.0
+        );
+        gain_test!(meter_meter_type, meter::METER_TYPE, [0x0001], 1.0);
+        gain_test!(
+            meter_line_voltage_a_b,
+            meter::LINE_VOLTAGE_A_B,
+            [0x0000, 0x0FA0],
+            400.0
+        );
+        gain_test!(
+            meter_phase_c_active_power,
+            meter::PHASE_C_ACTIVE_POWER,
+            [0x0000, 0x05DC],
+            1500.0
+        );
+        gain_test!(startup, STARTUP, [0x0000], 0.0);
+        gain_test!(shutdown, SHUTDOWN, [0x0000], 0.0);
+        gain_test!(
+            reactive_power_compensation_pf,
+            REACTIVE_POWER_COMPENSATION_PF,
+            [0xFC72],
+            -0.91
+        );
+        gain_test!(
+            active_power_percentage_derating,
+            ACTIVE_POWER_PERCENTAGE_DERATING,
+            [0x01F4],
+            50.0
+        );
+        gain_test!(
+            active_power_fixed_value_derating,
+            ACTIVE_POWER_FIXED_VALUE_DERATING,
+            [0x0000, 0x1388],
+            5.0
+        );
+        gain_test!(
+            maximum_feed_grid_power,
+            MAXIMUM_FEED_GRID_POWER,
+            [0xFFFF, 0xFC18],
+            -1.0
+        );
+        gain_test!(grid_code, GRID_CODE, [0x0011], 17.0);
+        gain_test!(time_zone, TIME_ZONE, [0xFFC4], -60.0);
+
+        #[test]
+        fn storage_pack_addresses() {
+            assert_eq!(storage::unit_1_pack_1::SERIAL_NUMBER.address, 38200);
+            assert_eq!(storage::unit_1_pack_2::WORKING_STATUS.address, 38270);
+            assert_eq!(storage::unit_2_pack_1::TOTAL_DISCHARGE.address, 38366);
+            assert_eq!(storage::unit_2_pack_3::MINIMUM_TEMPERATURE.address, 38463);
+            assert_eq!(
+                storage::unit_2_pack_2::VOLTAGE.name,
+                "UNIT_2_PACK_2_VOLTAGE"
+            );
+            assert_eq!(storage::product_model_to_string(2), Some("LUNA2000"));
+            assert_eq!(storage::FORCIBLE_CHARGE_DISCHARGE.access, Access::WO);
+        }
+
+        #[test]
+        fn pv_string_addresses() {
+            for n in 1..=24 {
+                let (voltage, current) = pv_string(n).unwrap();
+                assert_eq!(voltage.address, 32016 + 2 * (n as u16 - 1));
+                assert_eq!(current.address, voltage.address + 1);
+                assert_eq!(voltage.name, format!("PV{}_VOLTAGE", n));
+                assert_eq!(current.name, format!("PV{}_CURRENT", n));
+                assert_eq!((voltage.gain, voltage.unit), (Gain::div(10), Some("V")));
+                assert_eq!((current.gain, current.unit), (Gain::div(100), Some("A")));
+            }
+            assert_eq!(pv_string(24).unwrap().1.address, PV24_CURRENT.address);
+            // the last string ends where the input power starts
+            assert_eq!(PV24_CURRENT.address + 1, INPUT_POWER.address);
+            assert!(pv_string(0).is_none());
+            assert!(pv_string(25).is_none());
+        }
+
+        #[test]
+        fn meter_registers_contiguous() {
+            let regs = meter::REGISTERS;
+            for pair in regs.windows(2) {
+                assert_eq!(pair[0].address + pair[0].quantity as u16, pair[1].address);
+            }
+            assert_eq!(regs[0].address, 37100);
+            assert_eq!(meter::status_to_string(1), Some("normal"));
+            assert_eq!(meter::meter_type_to_string(2), None);
+        }
+
+        #[test]
+        fn display_applies_gain() {
+            let raw = [0x0FA0];
+            let val = RegValue {
+                reg: &PV1_VOLTAGE,
+                val: PV1_VOLTAGE.typ.convert(&raw).unwrap(),
+            };
+            assert_eq!(format!("{val}"), "400V");
+            let raw = [0x0339];
+            let val = RegValue {
+                reg: &PV1_CURRENT,
+                val: PV1_CURRENT.typ.convert(&raw).unwrap(),
+            };
+            assert_eq!(format!("{val}"), "8.25A");
+        }
+    }
 }
 
 // connect_timeout: "5s"
@@ -379,11 +1642,167 @@ pub mod registers {
 // host: "192.168.200.1"
 // port: 6607
 
+#[allow(clippy::upper_case_acronyms)]
 enum Client {
-    TCP(Transport),
+    TCP(tcp::Transport),
+    RTU(rtu::Transport),
+    Record(capture::Recorder),
+    Replay(capture::Replay),
 }
+impl Client {
+    fn modbus(&mut self) -> &mut dyn modbus::Client {
+        match self {
+            Client::TCP(tcp_client) => tcp_client,
+            Client::RTU(rtu_client) => rtu_client,
+            Client::Record(recorder) => recorder,
+            Client::Replay(replay) => replay,
+        }
+    }
+
+    /// Exchange a raw PDU, e.g. for custom function codes
+    fn transact(&mut self, pdu: &[u8]) -> Result<Vec<u8>, modbus::Error> {
+        use pdu::Transact;
+        match self {
+            Client::TCP(tcp_client) => tcp_client.transact(pdu),
+            Client::RTU(rtu_client) => rtu_client.transact(pdu),
+            Client::Record(recorder) => recorder.transact(pdu),
+            Client::Replay(replay) => replay.transact(pdu),
+        }
+    }
+
+    fn close(&mut self) -> Result<(), modbus::Error> {
+        match self {
+            Client::TCP(tcp_client) => tcp_client.close(),
+            Client::RTU(rtu_client) => rtu_client.close(),
+            Client::Record(recorder) => recorder.close(),
+            Client::Replay(_) => Ok(()),
+        }
+    }
+}
+
+/// Modbus connection shared by several devices, e.g. the inverters, power
+/// meter and battery behind an SDongle or SmartLogger.
+///
+/// Each device gets its own [Inverter] handle addressing its unit ID.
+/// Handles can be moved to other threads, requests of all handles are
+/// serialized on the connection.
+///
+/// # Examples
+/// ```no_run
+/// # use huawei_solar::{registers::*, Connection};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let connection = Connection::tcp(Some("192.168.1.10"), Some(502), None, None, None)?;
+/// let mut inverter_1 = connection.device(1);
+/// let mut inverter_2 = connection.device(2);
+/// let power_1: i32 = inverter_1.read(&ACTIVE_POWER)?;
+/// let power_2: i32 = inverter_2.read(&ACTIVE_POWER)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Connection {
+    client: Arc<Mutex<Client>>,
+}
+
+impl Connection {
+    /// Connect over Modbus TCP, defaults as for [Inverter::connect_tcp]
+    pub fn tcp(
+        addr: Option<&str>,
+        port: Option<u16>,
+        read_timeout: Option<Duration>,
+        write_timeout: Option<Duration>,
+        connect_timeout: Option<Duration>,
+    ) -> Result<Self, Error> {
+        let mb_client = tcp::Transport::new(
+            addr.unwrap_or("192.168.200.1"),
+            port.unwrap_or(6607),
+            0,
+            read_timeout.or(Some(Duration::from_secs(5))),
+            write_timeout.or(Some(Duration::from_secs(5))),
+            connect_timeout.or(Some(Duration::from_secs(5))),
+        )?;
+        Ok(Connection::new(Client::TCP(mb_client)))
+    }
+
+    /// Connect over Modbus RTU, defaults as for [Inverter::connect_rtu]
+    pub fn rtu(
+        path: &str,
+        baud_rate: Option<u32>,
+        parity: Option<rtu::Parity>,
+        timeout: Option<Duration>,
+    ) -> Result<Self, Error> {
+        let mb_client = rtu::Transport::new(
+            path,
+            baud_rate.unwrap_or(9600),
+            parity.unwrap_or(rtu::Parity::None),
+            1,
+            timeout.unwrap_or(Duration::from_secs(1)),
+        )?;
+        Ok(Connection::new(Client::RTU(mb_client)))
+    }
+
+    /// Answer requests from a [capture] instead of a device, see
+    /// [Connection::record]
+    pub fn replay<R: std::io::BufRead>(capture: R) -> Result<Self, Error> {
+        let exchanges = capture::parse(capture)?;
+        Ok(Connection::new(Client::Replay(capture::Replay::new(
+            exchanges,
+        ))))
+    }
+
+    /// Log every request of all device handles with its response or error
+    /// and timing to `log`, in the [capture] format.
+    ///
+    /// Recording stops at the first failed write to `log`, the requests
+    /// are unaffected and [Connection::close] reports the error.
+    pub fn record<W: std::io::Write + Send + 'static>(&self, log: W) -> Result<(), Error> {
+        let mut client = self.lock();
+        // an empty replay stands in while the client is moved into the recorder
+        let inner = std::mem::replace(&mut *client, Client::Replay(capture::Replay::new(vec![])));
+        let recorder = capture::Recorder::new(inner, Box::new(log));
+        match recorder {
+            Ok(recorder) => {
+                *client = Client::Record(recorder);
+                Ok(())
+            }
+            Err((inner, err)) => {
+                *client = inner;
+                Err(err.into())
+            }
+        }
+    }
+
+    fn new(client: Client) -> Self {
+        Connection {
+            client: Arc::new(Mutex::new(client)),
+        }
+    }
+
+    /// Handle for the device with the Modbus unit ID `unit_id`
+    pub fn device(&self, unit_id: u8) -> Inverter {
+        Inverter {
+            connection: self.clone(),
+            unit_id,
+            planner: batch::Planner::default(),
+        }
+    }
+
+    /// Close the connection of all device handles
+    pub fn close(&self) -> Result<(), modbus::Error> {
+        self.lock().close()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Client> {
+        // a panic during a request leaves nothing to clean up
+        self.client.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Handle of a single device on a [Connection]
 pub struct Inverter {
-    client: Client,
+    connection: Connection,
+    unit_id: u8,
+    planner: batch::Planner,
 }
 
 #[derive(Debug)]
@@ -391,6 +1810,19 @@ pub enum Error {
     Io(std::io::Error),
     Modbus(modbus::Error),
     Conversion,
+    Register(registers::RegisterError),
+    /// Attempted write to a read-only register
+    ReadOnly(String),
+    /// Attempted read of a write-only register
+    WriteOnly(String),
+    /// Value read back after a write differs from the written one
+    Verification(String),
+    /// The inverter rejected the login or failed to prove the password
+    Login(String),
+    /// File upload failed or the uploaded file is malformed
+    File(String),
+    /// Malformed line in a [capture]
+    Capture(String),
 }
 
 impl std::fmt::Display for Error {
@@ -399,6 +1831,15 @@ impl std::fmt::Display for Error {
             Error::Io(io_err) => io_err.fmt(f),
             Error::Modbus(mb_err) => mb_err.fmt(f),
             Error::Conversion => write!(f, "Failed to convert Vec<u16> result."),
+            Error::Register(reg_err) => reg_err.fmt(f),
+            Error::ReadOnly(name) => write!(f, "Register {} is read-only.", name),
+            Error::WriteOnly(name) => write!(f, "Register {} is write-only.", name),
+            Error::Verification(name) => {
+                write!(f, "Register {} does not hold the written value.", name)
+            }
+            Error::Login(reason) => write!(f, "Login failed: {}.", reason),
+            Error::File(reason) => write!(f, "File upload failed: {}.", reason),
+            Error::Capture(reason) => write!(f, "Invalid capture: {}.", reason),
         }
     }
 }
@@ -408,7 +1849,14 @@ impl std::error::Error for Error {
         match self {
             Error::Io(io_err) => Some(io_err),
             Error::Modbus(mb_err) => Some(mb_err),
-            Error::Conversion => None,
+            Error::Register(reg_err) => Some(reg_err),
+            Error::Conversion
+            | Error::ReadOnly(_)
+            | Error::WriteOnly(_)
+            | Error::Verification(_)
+            | Error::Login(_)
+            | Error::File(_)
+            | Error::Capture(_) => None,
         }
     }
 
@@ -431,6 +1879,11 @@ impl From<modbus::Error> for Error {
         Error::Modbus(err)
     }
 }
+impl From<registers::RegisterError> for Error {
+    fn from(err: registers::RegisterError) -> Error {
+        Error::Register(err)
+    }
+}
 ///
 /// # Examples
 /// ```
@@ -461,73 +1914,117 @@ impl Inverter {
         write_timeout: Option<Duration>,
         connect_timeout: Option<Duration>,
     ) -> Result<Self, Error> {
-        let mb_client = modbus::tcp::Transport::new_with_cfg(
-            addr.unwrap_or("192.168.200.1"),
-            modbus::Config {
-                modbus_uid: modbus_uid.unwrap_or(0),
-                tcp_port: port.unwrap_or(6607),
-                tcp_read_timeout: read_timeout.or(Some(Duration::from_secs(5))),
-                tcp_write_timeout: write_timeout.or(Some(Duration::from_secs(5))),
-                tcp_connect_timeout: connect_timeout.or(Some(Duration::from_secs(5))),
-            },
-        )?;
-        Ok(Inverter {
-            client: Client::TCP(mb_client),
-        })
-    }
-
-    // pub fn read<T: RegisterType>(self, reg: registers::Register<T>) -> Result<T, Error> {
-    //     let value = match self.client {
-    //         Client::TCP(mut tcp_client) => {
-    //             tcp_client.read_holding_registers(reg.address, reg.quantity.into())?
-    //         }
-    //     };
-    //     T::from(value).ok_or(Error::Conversion)
-    // }
-    pub fn read_raw(&mut self, reg: registers::Register) -> Result<Vec<u16>, Error> {
-        let value = match self.client {
-            Client::TCP(ref mut tcp_client) => modbus::Client::read_holding_registers(
-                tcp_client,
-                reg.address,
-                reg.quantity.into(),
-            )?,
-        };
+        Ok(
+            Connection::tcp(addr, port, read_timeout, write_timeout, connect_timeout)?
+                .device(modbus_uid.unwrap_or(0)),
+        )
+    }
+
+    /// Connect to Inverter over RS485 using Modbus RTU
+    ///
+    /// Defaults match the factory settings of the inverter's COM port:
+    /// 9600 baud, no parity and slave address 1.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// use huawei_solar::{rtu::Parity, Inverter};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = Inverter::connect_rtu(
+    ///     "/dev/ttyUSB0",
+    ///     Some(9600),
+    ///     Some(Parity::None),
+    ///     Some(1),
+    ///     Some(Duration::from_secs(1)),
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn connect_rtu(
+        path: &str,
+        baud_rate: Option<u32>,
+        parity: Option<rtu::Parity>,
+        modbus_uid: Option<u8>,
+        timeout: Option<Duration>,
+    ) -> Result<Self, Error> {
+        Ok(Connection::rtu(path, baud_rate, parity, timeout)?.device(modbus_uid.unwrap_or(1)))
+    }
+
+    /// Read a single register and convert it to its value type
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use huawei_solar::{registers::*, Inverter};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let mut inverter = Inverter::connect_tcp(None, None, None, None, None, None)?;
+    /// let model: String = inverter.read(&MODEL)?;
+    /// let rated_power: u32 = inverter.read(&RATED_POWER)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read<T: registers::RegisterType>(
+        &mut self,
+        reg: &registers::TypedRegister<T>,
+    ) -> Result<T, Error> {
+        let value = self.read_raw(reg)?;
+        T::convert(&value).ok_or(Error::Conversion)
+    }
+
+    /// Voltage and current registers of the PV strings the inverter
+    /// reports in [NUMBER_OF_PV_STRINGS](registers::NUMBER_OF_PV_STRINGS)
+    pub fn pv_strings(&mut self) -> Result<Vec<registers::PvString>, Error> {
+        let strings = self.read(&registers::NUMBER_OF_PV_STRINGS)?;
+        if strings as usize > registers::PV_STRINGS.len() {
+            return Err(registers::RegisterError::ValueConversion(format!(
+                "{} PV strings, the register table has {}",
+                strings,
+                registers::PV_STRINGS.len()
+            ))
+            .into());
+        }
+        Ok(registers::PV_STRINGS[..strings.into()].to_vec())
+    }
+
+    pub fn read_raw(&mut self, reg: &registers::Register) -> Result<Vec<u16>, Error> {
+        if reg.access == registers::Access::WO {
+            return Err(Error::WriteOnly(reg.name.to_string()));
+        }
+        let value = self
+            .client()
+            .modbus()
+            .read_holding_registers(reg.address, reg.quantity.into())?;
         Ok(value)
     }
 
+    /// Read the raw words of `regs`, in input order.
+    ///
+    /// The registers are read with as few requests as the [batch::Planner]
+    /// set with [Inverter::set_planner] allows.
     pub fn read_batch_raw(
         &mut self,
         regs: &[&registers::Register],
     ) -> Result<Vec<Vec<u16>>, modbus::Error> {
-        if regs.is_empty() {
-            return Ok(Vec::new());
-        }
-        let min = regs.iter().map(|r| r.address).min().unwrap();
-        // actually highest read address +1
-        let max = regs
-            .iter()
-            .map(|r| r.address + r.quantity as u16)
-            .max()
-            .unwrap();
-
-        let values = match self.client {
-            Client::TCP(ref mut tcp_client) => {
-                modbus::Client::read_holding_registers(tcp_client, min, max - min)?
-            }
-        };
-
-        let chunked = regs
-            .iter()
-            .map(|reg| {
-                let start = (reg.address - min) as usize;
-                let size = reg.quantity as usize;
-                values[start..start + size].to_vec()
-            })
-            .collect();
-
+        let mut chunked = vec![Vec::new(); regs.len()];
+        for request in self.planner.plan(regs) {
+            let values = self
+                .client()
+                .modbus()
+                .read_holding_registers(request.address, request.quantity)?;
+            if values.len() != request.quantity as usize {
+                return Err(modbus::Error::InvalidResponse);
+            }
+            for (i, offset) in request.parts {
+                chunked[i] = values[offset..offset + regs[i].quantity as usize].to_vec();
+            }
+        }
         Ok(chunked)
     }
 
+    /// Set how batch reads are split into requests
+    pub fn set_planner(&mut self, planner: batch::Planner) {
+        self.planner = planner;
+    }
+
     pub fn read_batch<'a, 'b>(
         &mut self,
         regs: &'a [&registers::Register<'b>],
@@ -540,7 +2037,7 @@ impl Inverter {
             .zip(regs)
             .map(|(val, reg)| -> Result<registers::RegValue, modbus::Error> {
                 Ok(registers::RegValue {
-                    reg: reg,
+                    reg,
                     val: reg
                         .typ
                         .convert(&val)
@@ -567,9 +2064,288 @@ impl Inverter {
         }
     }
 
-    pub fn disconnect(&mut self) -> Result<(), modbus::Error> {
-        match self.client {
-            Client::TCP(ref mut tcp_client) => modbus::Transport::close(tcp_client),
+    /// Write `value` to a [registers::Access::RW] or [registers::Access::WO] register
+    ///
+    /// Single registers are written with function code 0x06, values
+    /// spanning multiple registers with 0x10.
+    pub fn write(
+        &mut self,
+        reg: &registers::Register,
+        value: registers::Value,
+    ) -> Result<(), Error> {
+        if reg.access == registers::Access::RO {
+            return Err(Error::ReadOnly(reg.name.to_string()));
         }
+        let words = reg.encode(&value)?;
+        self.write_words(reg.address, &words)
     }
+
+    /// Write a typed value, the counterpart of [Inverter::read]
+    ///
+    /// Values which do not fit the register are rejected with
+    /// [RegisterError::ValueConversion](registers::RegisterError::ValueConversion)
+    /// before anything is sent.
+    pub fn write_typed<T: registers::RegisterType>(
+        &mut self,
+        reg: &registers::TypedRegister<T>,
+        value: &T,
+    ) -> Result<(), Error> {
+        if reg.access == registers::Access::RO {
+            return Err(Error::ReadOnly(reg.name.to_string()));
+        }
+        let words = value.encode(reg.quantity.into()).ok_or_else(|| {
+            registers::RegisterError::ValueConversion(format!("value does not fit {}", reg.name))
+        })?;
+        self.write_words(reg.address, &words)
+    }
+
+    /// Like [Inverter::write] and read the register back to confirm the
+    /// inverter accepted the value
+    pub fn write_verified(
+        &mut self,
+        reg: &registers::Register,
+        value: registers::Value,
+    ) -> Result<(), Error> {
+        if reg.access == registers::Access::WO {
+            return Err(Error::WriteOnly(reg.name.to_string()));
+        }
+        if reg.access == registers::Access::RO {
+            return Err(Error::ReadOnly(reg.name.to_string()));
+        }
+        let words = reg.encode(&value)?;
+        self.write_words(reg.address, &words)?;
+
+        let read_back = self
+            .client()
+            .modbus()
+            .read_holding_registers(reg.address, reg.quantity.into())?;
+        if read_back != words {
+            return Err(Error::Verification(reg.name.to_string()));
+        }
+        Ok(())
+    }
+
+    fn write_words(&mut self, address: u16, words: &[u16]) -> Result<(), Error> {
+        match words {
+            [word] => self
+                .client()
+                .modbus()
+                .write_single_register(address, *word)?,
+            _ => self
+                .client()
+                .modbus()
+                .write_multiple_registers(address, words)?,
+        }
+        Ok(())
+    }
+
+    /// Log in with the installer account, see [login] for the exchange.
+    ///
+    /// Required by newer firmware before writes over the inverter's Wi-Fi
+    /// access point are accepted. The returned [login::Session] keeps the
+    /// session alive until it is dropped.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use huawei_solar::{registers::*, Inverter};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut inverter = Inverter::connect_tcp(None, None, None, None, None, None)?;
+    /// let session = inverter.login("installer", "00000a")?;
+    /// inverter.write(&TIME_ZONE, Value::I16(60))?;
+    /// drop(session);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn login(&mut self, user: &str, password: &str) -> Result<login::Session, Error> {
+        let response = self.client().transact(&login::challenge_request())?;
+        let inverter_challenge = login::parse_challenge(&response)?;
+        let mut client_challenge = [0u8; 16];
+        getrandom::getrandom(&mut client_challenge).map_err(std::io::Error::from)?;
+
+        let request = login::login_request(user, password, &inverter_challenge, &client_challenge)?;
+        let response = self.client().transact(&request)?;
+        login::check_login(&response, password, &client_challenge)?;
+        Ok(login::Session::start(
+            self.connection.device(self.unit_id),
+            login::HEARTBEAT_INTERVAL,
+        ))
+    }
+
+    /// Upload the file `file_type` with the type specific `params`, see
+    /// [files] for the transfer and the known file types.
+    ///
+    /// The connection stays locked for the whole transfer.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use huawei_solar::{files, Inverter};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let mut inverter = Inverter::connect_tcp(None, None, None, None, None, None)?;
+    /// let file = inverter.upload_file(files::DAILY_YIELD, &files::yield_params(1704067200, 1706745600))?;
+    /// for record in files::parse_yield_history(&file)? {
+    ///     println!("{}: {} kWh", record.time, record.energy);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn upload_file(&mut self, file_type: u8, params: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut client = self.client();
+        files::upload(|pdu| client.transact(pdu), file_type, params)
+    }
+
+    /// Close the connection, also for other handles on the same [Connection]
+    pub fn disconnect(&mut self) -> Result<(), modbus::Error> {
+        self.connection.close()
+    }
+
+    /// Modbus unit ID of the device
+    pub fn unit_id(&self) -> u8 {
+        self.unit_id
+    }
+
+    /// Connection shared with other devices
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Lock the connection for a request to this device
+    fn client(&self) -> MutexGuard<'_, Client> {
+        let mut client = self.connection.lock();
+        client.modbus().set_uid(self.unit_id);
+        client
+    }
+}
+
+#[cfg(all(test, unix))]
+fn pty_inverter<F>(slave: F) -> (Inverter, std::thread::JoinHandle<serialport::TTYPort>)
+where
+    F: FnOnce(&mut serialport::TTYPort) + Send + 'static,
+{
+    let (transport, handle) = rtu::pty_transport(slave);
+    (Connection::new(Client::RTU(transport)).device(1), handle)
+}
+
+#[cfg(test)]
+pub(crate) fn tcp_inverter<F>(server: F) -> (Inverter, std::thread::JoinHandle<()>)
+where
+    F: FnOnce(&mut std::net::TcpStream) + Send + 'static,
+{
+    let (transport, server) = tcp::local_transport(server);
+    (Connection::new(Client::TCP(transport)).device(1), server)
+}
+
+#[test]
+#[cfg(unix)]
+fn write_access_checks() {
+    use registers::*;
+    let (mut inverter, slave) = pty_inverter(|_| {});
+    assert!(matches!(
+        inverter.write(&RATED_POWER, Value::U32(10000)),
+        Err(Error::ReadOnly(_))
+    ));
+    assert!(matches!(
+        inverter.write_verified(&STARTUP, Value::U16(0)),
+        Err(Error::WriteOnly(_))
+    ));
+    assert!(matches!(
+        inverter.write(&TIME_ZONE, Value::U16(60)),
+        Err(Error::Register(_))
+    ));
+    slave.join().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn write_single_and_verify() {
+    use registers::*;
+    let (mut inverter, slave) = pty_inverter(|port| {
+        rtu::reply(port, 8, &[0x01, 0x06, 0x9D, 0x08, 0x00, 0x00]);
+        rtu::reply(port, 8, &[0x01, 0x06, 0xA7, 0xFE, 0xFF, 0xC4]);
+        rtu::reply(port, 8, &[0x01, 0x03, 0x02, 0xFF, 0xC4]);
+        rtu::reply(port, 8, &[0x01, 0x06, 0xA7, 0xFE, 0x00, 0x3C]);
+        rtu::reply(port, 8, &[0x01, 0x03, 0x02, 0x00, 0x00]);
+    });
+    inverter.write(&STARTUP, Value::U16(0)).unwrap();
+    inverter
+        .write_verified(&TIME_ZONE, TIME_ZONE.value_from_float(-60.0).unwrap())
+        .unwrap();
+    assert!(matches!(
+        inverter.write_verified(&TIME_ZONE, Value::I16(60)),
+        Err(Error::Verification(_))
+    ));
+    slave.join().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn read_typed() {
+    use registers::*;
+    let (mut inverter, slave) = pty_inverter(|port| {
+        rtu::reply(port, 8, &[0x01, 0x03, 0x04, 0x00, 0x00, 0x27, 0x10]);
+        let mut model = vec![0x01, 0x03, 30];
+        model.extend_from_slice(b"SUN2000-10KTL-M1");
+        model.resize(33, 0);
+        rtu::reply(port, 8, &model);
+        rtu::reply(port, 8, &[0x01, 0x03, 0x02, 0xFF, 0xC4]);
+    });
+    let rated_power: u32 = inverter.read(&RATED_POWER).unwrap();
+    assert_eq!(rated_power, 10000);
+    assert_eq!(inverter.read(&MODEL).unwrap(), "SUN2000-10KTL-M1");
+    assert_eq!(inverter.read(&TIME_ZONE).unwrap(), -60i16);
+    assert!(matches!(inverter.read(&STARTUP), Err(Error::WriteOnly(_))));
+    slave.join().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn pv_strings_of_inverter() {
+    let (mut inverter, slave) = pty_inverter(|port| {
+        rtu::reply(port, 8, &[0x01, 0x03, 0x02, 0x00, 0x06]);
+        rtu::reply(port, 8, &[0x01, 0x03, 0x02, 0x00, 0x1A]);
+    });
+    let strings = inverter.pv_strings().unwrap();
+    assert_eq!(strings.len(), 6);
+    assert_eq!(strings[5].0.address, 32026);
+    assert!(matches!(inverter.pv_strings(), Err(Error::Register(_))));
+    slave.join().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn read_batch_split() {
+    use registers::*;
+    let (mut inverter, slave) = pty_inverter(|port| {
+        rtu::reply(port, 8, &[0x01, 0x03, 0x02, 0x01, 0x9A]);
+        rtu::reply(port, 8, &[0x01, 0x03, 0x04, 0x0F, 0xA0, 0x03, 0x39]);
+    });
+    let regs: [&Register; 3] = [&PV1_VOLTAGE, &MODEL_ID, &PV1_CURRENT];
+    let values = inverter.read_batch(&regs).unwrap();
+    assert_eq!(values[0].to_float().unwrap(), 400.0);
+    assert_eq!(values[1].to_u16().unwrap(), 410);
+    assert_eq!(values[2].to_float().unwrap(), 8.25);
+    slave.join().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn shared_connection() {
+    use registers::*;
+    let (inverter, slave) = pty_inverter(|port| {
+        // the transport rejects responses from another unit
+        rtu::reply(port, 8, &[0x01, 0x03, 0x02, 0x01, 0x9A]);
+        rtu::reply(port, 8, &[0x02, 0x03, 0x02, 0x01, 0x9B]);
+        rtu::reply(port, 8, &[0x02, 0x03, 0x02, 0x01, 0x9B]);
+        rtu::reply(port, 8, &[0x01, 0x03, 0x02, 0x01, 0x9A]);
+    });
+    let mut inverter_1 = inverter;
+    let mut inverter_2 = inverter_1.connection().device(2);
+    assert_eq!(inverter_2.unit_id(), 2);
+    assert_eq!(inverter_1.read(&MODEL_ID).unwrap(), 410);
+    assert_eq!(inverter_2.read(&MODEL_ID).unwrap(), 411);
+    // handles can be used from other threads
+    std::thread::spawn(move || assert_eq!(inverter_2.read(&MODEL_ID).unwrap(), 411))
+        .join()
+        .unwrap();
+    assert_eq!(inverter_1.read(&MODEL_ID).unwrap(), 410);
+    slave.join().unwrap();
 }
@@ -0,0 +1,278 @@
+//! Login to the inverter with the Huawei custom function 0x41.
+//!
+//! Newer firmware only accepts writes over the inverter's Wi-Fi access
+//! point after logging in with the installer account. The exchange is a
+//! mutual challenge/response on the password:
+//!
+//! 1. sub-command 0x24 returns a 16 byte challenge of the inverter
+//! 2. sub-command 0x25 sends the user name, a challenge of the client and
+//!    `HMAC-SHA256(SHA256(password), inverter challenge)`
+//! 3. the inverter answers with `HMAC-SHA256(SHA256(password), client
+//!    challenge)`, proving it knows the password as well
+//!
+//! Requests and responses of both sub-commands are
+//! `0x41 | sub-command | byte count | data`. The session times out unless
+//! [HEARTBEAT] is written regularly, which
+//! [Session] does in the background.
+
+use std::{
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    registers::{Value, HEARTBEAT},
+    Error, Inverter,
+};
+
+/// Huawei custom function code
+pub const FUNCTION: u8 = 0x41;
+/// Sub-command requesting the challenge of the inverter
+pub const CHALLENGE: u8 = 0x24;
+/// Sub-command sending the credentials
+pub const LOGIN: u8 = 0x25;
+
+/// Interval of the heartbeat writes, the inverter ends the session after
+/// about 30 seconds without one
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+const CHALLENGE_LEN: usize = 16;
+
+pub(crate) fn challenge_request() -> Vec<u8> {
+    vec![FUNCTION, CHALLENGE, 0x01, 0x00]
+}
+
+/// Extract the challenge of the inverter from the response to
+/// [challenge_request]
+pub(crate) fn parse_challenge(response: &[u8]) -> Result<[u8; CHALLENGE_LEN], Error> {
+    let data = data(response, CHALLENGE)?;
+    match data {
+        [0x11, challenge @ ..] => challenge
+            .get(..CHALLENGE_LEN)
+            .and_then(|challenge| challenge.try_into().ok())
+            .ok_or_else(|| Error::Login(String::from("challenge too short"))),
+        _ => Err(Error::Login(String::from("unexpected challenge format"))),
+    }
+}
+
+pub(crate) fn login_request(
+    user: &str,
+    password: &str,
+    inverter_challenge: &[u8; CHALLENGE_LEN],
+    client_challenge: &[u8; CHALLENGE_LEN],
+) -> Result<Vec<u8>, Error> {
+    let hash = mac(password, inverter_challenge);
+    let content_len = CHALLENGE_LEN + 1 + user.len() + 1 + hash.len();
+    if content_len + 1 > u8::MAX as usize {
+        return Err(Error::Login(String::from("user name too long")));
+    }
+    let mut pdu = vec![FUNCTION, LOGIN, content_len as u8 + 1, content_len as u8];
+    pdu.extend_from_slice(client_challenge);
+    pdu.push(user.len() as u8);
+    pdu.extend_from_slice(user.as_bytes());
+    pdu.push(hash.len() as u8);
+    pdu.extend_from_slice(&hash);
+    Ok(pdu)
+}
+
+/// Check the result and the proof of the inverter in the response to
+/// [login_request]
+pub(crate) fn check_login(
+    response: &[u8],
+    password: &str,
+    client_challenge: &[u8; CHALLENGE_LEN],
+) -> Result<(), Error> {
+    match data(response, LOGIN)? {
+        [_, 0, mac_len, proof @ ..] if proof.len() == *mac_len as usize => {
+            if proof == mac(password, client_challenge).as_slice() {
+                Ok(())
+            } else {
+                Err(Error::Login(String::from(
+                    "inverter failed to prove the password",
+                )))
+            }
+        }
+        [_, 0, ..] => Err(Error::Login(String::from("malformed response"))),
+        [_, code, ..] => Err(Error::Login(format!("rejected with code {}", code))),
+        _ => Err(Error::Login(String::from("malformed response"))),
+    }
+}
+
+/// Data of a response to the sub-command `sub_command`
+fn data(response: &[u8], sub_command: u8) -> Result<&[u8], Error> {
+    match response {
+        [FUNCTION, sub, count, data @ ..]
+            if *sub == sub_command && data.len() == *count as usize =>
+        {
+            Ok(data)
+        }
+        _ => Err(Error::Modbus(modbus::Error::InvalidResponse)),
+    }
+}
+
+/// `HMAC-SHA256` of `message` keyed with the SHA-256 hash of `password`
+fn mac(password: &str, message: &[u8]) -> Vec<u8> {
+    let key = Sha256::digest(password.as_bytes());
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Logged in session, kept alive by writing the heartbeat register until
+/// dropped or [ended](Session::end).
+///
+/// Failed heartbeats are not reported here, they surface as rejected
+/// writes once the inverter ended the session.
+pub struct Session {
+    stop: Option<Sender<()>>,
+    heartbeat: Option<JoinHandle<()>>,
+}
+
+impl Session {
+    pub(crate) fn start(mut device: Inverter, interval: Duration) -> Session {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let heartbeat = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let _ = device.write(&HEARTBEAT, Value::U16(1));
+            }
+        });
+        Session {
+            stop: Some(stop),
+            heartbeat: Some(heartbeat),
+        }
+    }
+
+    /// Stop the heartbeat, the inverter ends the session shortly after
+    pub fn end(self) {}
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(heartbeat) = self.heartbeat.take() {
+            let _ = heartbeat.join();
+        }
+    }
+}
+
+#[cfg(test)]
+const INVERTER_CHALLENGE: [u8; CHALLENGE_LEN] = *b"0123456789abcdef";
+
+#[test]
+fn login_messages() {
+    let mut response = vec![FUNCTION, CHALLENGE, 17, 0x11];
+    response.extend_from_slice(&INVERTER_CHALLENGE);
+    assert_eq!(parse_challenge(&response).unwrap(), INVERTER_CHALLENGE);
+    assert!(matches!(
+        parse_challenge(&response[..10]),
+        Err(Error::Modbus(_))
+    ));
+
+    let client_challenge = [7u8; CHALLENGE_LEN];
+    let request = login_request(
+        "installer",
+        "00000a",
+        &INVERTER_CHALLENGE,
+        &client_challenge,
+    )
+    .unwrap();
+    assert_eq!(request[..4], [FUNCTION, LOGIN, 60, 59]);
+    assert_eq!(request[4..20], client_challenge);
+    assert_eq!(request[20], 9);
+    assert_eq!(&request[21..30], b"installer");
+    assert_eq!(request[30], 32);
+    assert_eq!(request[31..], mac("00000a", &INVERTER_CHALLENGE));
+    assert_eq!(request.len(), 3 + request[2] as usize);
+}
+
+#[test]
+fn login_response() {
+    let client_challenge = [7u8; CHALLENGE_LEN];
+    let response = |code: u8, password: &str| {
+        let mut response = vec![FUNCTION, LOGIN, 34, 0x01, code, 32];
+        response.extend_from_slice(&mac(password, &client_challenge));
+        response[2] = (response.len() - 3) as u8;
+        response
+    };
+    check_login(&response(0, "00000a"), "00000a", &client_challenge).unwrap();
+    assert!(matches!(
+        check_login(&response(0, "other"), "00000a", &client_challenge),
+        Err(Error::Login(_))
+    ));
+    assert!(matches!(
+        check_login(&response(1, "00000a"), "00000a", &client_challenge),
+        Err(Error::Login(reason)) if reason == "rejected with code 1"
+    ));
+}
+
+#[test]
+fn mac_test() {
+    // computed with Python's hmac and hashlib modules
+    assert_eq!(
+        mac("key", &INVERTER_CHALLENGE),
+        vec![
+            0x4A, 0xD0, 0x2E, 0x99, 0x84, 0xC7, 0x86, 0x23, 0xE1, 0xE7, 0xBA, 0xD4, 0xA1, 0x5D,
+            0xB0, 0x15, 0xB4, 0xC2, 0x44, 0xB4, 0x2F, 0xD4, 0x3E, 0x83, 0xE4, 0xEE, 0x5D, 0x0F,
+            0xD0, 0x26, 0x56, 0xFD
+        ]
+    );
+}
+
+#[test]
+fn login_handshake() {
+    let (mut inverter, server) = crate::tcp_inverter(|stream| {
+        let mut response = vec![FUNCTION, CHALLENGE, 17, 0x11];
+        response.extend_from_slice(&INVERTER_CHALLENGE);
+        let request = crate::tcp::reply(stream, &response);
+        assert_eq!(request, challenge_request());
+
+        let mut header = [0u8; 7];
+        std::io::Read::read_exact(stream, &mut header).unwrap();
+        let mut request = vec![0u8; u16::from_be_bytes([header[4], header[5]]) as usize - 1];
+        std::io::Read::read_exact(stream, &mut request).unwrap();
+        assert_eq!(&request[21..30], b"installer");
+        assert_eq!(request[31..], mac("00000a", &INVERTER_CHALLENGE));
+        let mut response = vec![FUNCTION, LOGIN, 0, 0x01, 0, 32];
+        response.extend_from_slice(&mac("00000a", &request[4..20]));
+        response[2] = (response.len() - 3) as u8;
+        let mut frame = header[..4].to_vec();
+        frame.extend_from_slice(&(response.len() as u16 + 1).to_be_bytes());
+        frame.push(header[6]);
+        frame.extend_from_slice(&response);
+        std::io::Write::write_all(stream, &frame).unwrap();
+    });
+    let session = inverter.login("installer", "00000a").unwrap();
+    session.end();
+    server.join().unwrap();
+}
+
+#[test]
+fn login_rejected() {
+    let (mut inverter, server) = crate::tcp_inverter(|stream| {
+        crate::tcp::reply(stream, &[FUNCTION | 0x80, 0x01]);
+    });
+    assert!(matches!(
+        inverter.login("installer", "00000a"),
+        Err(Error::Modbus(modbus::Error::Exception(
+            modbus::ExceptionCode::IllegalFunction
+        )))
+    ));
+    server.join().unwrap();
+}
+
+#[test]
+fn session_heartbeat() {
+    let (inverter, server) = crate::tcp_inverter(|stream| {
+        for _ in 0..2 {
+            let request = crate::tcp::reply(stream, &[0x06, 0xC3, 0x4F, 0x00, 0x01]);
+            assert_eq!(request, [0x06, 0xC3, 0x4F, 0x00, 0x01]);
+        }
+    });
+    let session = Session::start(inverter, Duration::from_millis(20));
+    server.join().unwrap();
+    drop(session);
+}
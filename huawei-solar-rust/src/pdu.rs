@@ -0,0 +1,202 @@
+//! Modbus functions on top of the PDU exchange of a transport.
+
+use modbus::{Error, Reason, Result};
+
+/// Exchange of a single request and response PDU with the device
+pub(crate) trait Transact {
+    /// Send `pdu` and return the PDU of the response, exception responses
+    /// are returned as [Error::Exception]
+    fn transact(&mut self, pdu: &[u8]) -> Result<Vec<u8>>;
+}
+
+pub(crate) fn read<T: Transact>(
+    transport: &mut T,
+    code: u8,
+    addr: u16,
+    count: u16,
+    expected_bytes: usize,
+) -> Result<Vec<u8>> {
+    let mut pdu = vec![code];
+    pdu.extend_from_slice(&addr.to_be_bytes());
+    pdu.extend_from_slice(&count.to_be_bytes());
+    read_pdu(transport, &pdu, count, expected_bytes)
+}
+
+pub(crate) fn read_pdu<T: Transact>(
+    transport: &mut T,
+    pdu: &[u8],
+    count: u16,
+    expected_bytes: usize,
+) -> Result<Vec<u8>> {
+    if count < 1 {
+        return Err(Error::InvalidData(Reason::RecvBufferEmpty));
+    }
+    let reply = transport.transact(pdu)?;
+    if reply.len() < 2 || reply[1] as usize != expected_bytes || reply.len() != expected_bytes + 2 {
+        return Err(Error::InvalidData(Reason::UnexpectedReplySize));
+    }
+    Ok(reply[2..].to_vec())
+}
+
+pub(crate) fn write<T: Transact>(transport: &mut T, pdu: &[u8]) -> Result<()> {
+    let reply = transport.transact(pdu)?;
+    // the slave echoes function code, address and value or quantity
+    if reply[..] != pdu[..5] {
+        return Err(Error::InvalidResponse);
+    }
+    Ok(())
+}
+
+pub(crate) fn write_multiple<T: Transact>(
+    transport: &mut T,
+    code: u8,
+    addr: u16,
+    quantity: u16,
+    bytes: &[u8],
+) -> Result<()> {
+    if bytes.is_empty() {
+        return Err(Error::InvalidData(Reason::SendBufferEmpty));
+    }
+    if bytes.len() > 246 {
+        return Err(Error::InvalidData(Reason::SendBufferTooBig));
+    }
+    let mut pdu = vec![code];
+    pdu.extend_from_slice(&addr.to_be_bytes());
+    pdu.extend_from_slice(&quantity.to_be_bytes());
+    pdu.push(bytes.len() as u8);
+    pdu.extend_from_slice(bytes);
+    write(transport, &pdu)
+}
+
+/// Implement [modbus::Client] for a [Transact] transport with a `uid` field
+macro_rules! modbus_client {
+    ($transport:ty) => {
+        impl modbus::Client for $transport {
+            fn read_coils(&mut self, addr: u16, count: u16) -> modbus::Result<Vec<modbus::Coil>> {
+                let bytes = $crate::pdu::read(self, 0x01, addr, count, count.div_ceil(8) as usize)?;
+                Ok(modbus::binary::unpack_bits(&bytes, count))
+            }
+
+            fn read_discrete_inputs(
+                &mut self,
+                addr: u16,
+                count: u16,
+            ) -> modbus::Result<Vec<modbus::Coil>> {
+                let bytes = $crate::pdu::read(self, 0x02, addr, count, count.div_ceil(8) as usize)?;
+                Ok(modbus::binary::unpack_bits(&bytes, count))
+            }
+
+            fn read_holding_registers(
+                &mut self,
+                addr: u16,
+                count: u16,
+            ) -> modbus::Result<Vec<u16>> {
+                let bytes = $crate::pdu::read(self, 0x03, addr, count, 2 * count as usize)?;
+                modbus::binary::pack_bytes(&bytes)
+            }
+
+            fn read_input_registers(&mut self, addr: u16, count: u16) -> modbus::Result<Vec<u16>> {
+                let bytes = $crate::pdu::read(self, 0x04, addr, count, 2 * count as usize)?;
+                modbus::binary::pack_bytes(&bytes)
+            }
+
+            fn write_single_coil(&mut self, addr: u16, value: modbus::Coil) -> modbus::Result<()> {
+                let value: u16 = match value {
+                    modbus::Coil::On => 0xFF00,
+                    modbus::Coil::Off => 0x0000,
+                };
+                let mut pdu = vec![0x05];
+                pdu.extend_from_slice(&addr.to_be_bytes());
+                pdu.extend_from_slice(&value.to_be_bytes());
+                $crate::pdu::write(self, &pdu)
+            }
+
+            fn write_single_register(&mut self, addr: u16, value: u16) -> modbus::Result<()> {
+                let mut pdu = vec![0x06];
+                pdu.extend_from_slice(&addr.to_be_bytes());
+                pdu.extend_from_slice(&value.to_be_bytes());
+                $crate::pdu::write(self, &pdu)
+            }
+
+            fn write_multiple_coils(
+                &mut self,
+                addr: u16,
+                values: &[modbus::Coil],
+            ) -> modbus::Result<()> {
+                let bytes = modbus::binary::pack_bits(values);
+                $crate::pdu::write_multiple(self, 0x0F, addr, values.len() as u16, &bytes)
+            }
+
+            fn write_multiple_registers(
+                &mut self,
+                addr: u16,
+                values: &[u16],
+            ) -> modbus::Result<()> {
+                let bytes = modbus::binary::unpack_bytes(values);
+                $crate::pdu::write_multiple(self, 0x10, addr, values.len() as u16, &bytes)
+            }
+
+            fn write_read_multiple_registers(
+                &mut self,
+                write_addr: u16,
+                write_quantity: u16,
+                write_values: &[u16],
+                read_addr: u16,
+                read_quantity: u16,
+            ) -> modbus::Result<Vec<u16>> {
+                let bytes = modbus::binary::unpack_bytes(write_values);
+                if bytes.is_empty() {
+                    return Err(modbus::Error::InvalidData(modbus::Reason::SendBufferEmpty));
+                }
+                if bytes.len() > 242 {
+                    return Err(modbus::Error::InvalidData(modbus::Reason::SendBufferTooBig));
+                }
+                let mut pdu = vec![0x17];
+                pdu.extend_from_slice(&read_addr.to_be_bytes());
+                pdu.extend_from_slice(&read_quantity.to_be_bytes());
+                pdu.extend_from_slice(&write_addr.to_be_bytes());
+                pdu.extend_from_slice(&write_quantity.to_be_bytes());
+                pdu.push(bytes.len() as u8);
+                pdu.extend_from_slice(&bytes);
+                let bytes =
+                    $crate::pdu::read_pdu(self, &pdu, read_quantity, 2 * read_quantity as usize)?;
+                modbus::binary::pack_bytes(&bytes)
+            }
+
+            fn set_uid(&mut self, uid: u8) {
+                self.uid = uid;
+            }
+        }
+    };
+}
+pub(crate) use modbus_client;
+
+/// Check the function code of a response PDU to the request `pdu`
+pub(crate) fn check_response(pdu: &[u8], response: &[u8]) -> Result<()> {
+    match response.first() {
+        Some(&function) if function == pdu[0] => Ok(()),
+        Some(&function) if function == pdu[0] | 0x80 => Err(response
+            .get(1)
+            .and_then(|&code| exception(code))
+            .map_or(Error::InvalidResponse, Error::Exception)),
+        _ => Err(Error::InvalidResponse),
+    }
+}
+
+pub(crate) fn exception(code: u8) -> Option<modbus::ExceptionCode> {
+    use modbus::ExceptionCode;
+    match code {
+        0x01 => Some(ExceptionCode::IllegalFunction),
+        0x02 => Some(ExceptionCode::IllegalDataAddress),
+        0x03 => Some(ExceptionCode::IllegalDataValue),
+        0x04 => Some(ExceptionCode::SlaveOrServerFailure),
+        0x05 => Some(ExceptionCode::Acknowledge),
+        0x06 => Some(ExceptionCode::SlaveOrServerBusy),
+        0x07 => Some(ExceptionCode::NegativeAcknowledge),
+        0x08 => Some(ExceptionCode::MemoryParity),
+        0x09 => Some(ExceptionCode::NotDefined),
+        0x0A => Some(ExceptionCode::GatewayPath),
+        0x0B => Some(ExceptionCode::GatewayTarget),
+        _ => None,
+    }
+}
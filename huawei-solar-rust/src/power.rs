@@ -0,0 +1,264 @@
+//! Active and reactive power control and limitation of the power exported
+//! to the grid.
+
+use std::fmt::Display;
+
+use crate::{
+    registers::{self, RegisterError, RegisterType, Type, TypedRegister},
+    Error, Inverter,
+};
+
+/// Content of the [ACTIVE_POWER_CONTROL_MODE](registers::ACTIVE_POWER_CONTROL_MODE)
+/// register
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ActivePowerControlMode {
+    Unlimited,
+    /// Scheduled through the digital inputs of the inverter
+    DiActiveScheduling,
+    ZeroPowerGridConnection,
+    /// Export limited to [MAXIMUM_FEED_GRID_POWER](registers::MAXIMUM_FEED_GRID_POWER)
+    PowerLimitedGridConnection,
+    /// Export limited to [MAXIMUM_FEED_GRID_POWER_PERCENT](registers::MAXIMUM_FEED_GRID_POWER_PERCENT)
+    PowerLimitedGridConnectionPercent,
+    /// Code not documented in the register table, kept as read and never
+    /// written
+    Other(u16),
+}
+
+impl ActivePowerControlMode {
+    pub const fn code(&self) -> u16 {
+        match self {
+            ActivePowerControlMode::Unlimited => 0,
+            ActivePowerControlMode::DiActiveScheduling => 1,
+            ActivePowerControlMode::ZeroPowerGridConnection => 5,
+            ActivePowerControlMode::PowerLimitedGridConnection => 6,
+            ActivePowerControlMode::PowerLimitedGridConnectionPercent => 7,
+            ActivePowerControlMode::Other(code) => *code,
+        }
+    }
+
+    pub const fn from_code(code: u16) -> ActivePowerControlMode {
+        match code {
+            0 => ActivePowerControlMode::Unlimited,
+            1 => ActivePowerControlMode::DiActiveScheduling,
+            5 => ActivePowerControlMode::ZeroPowerGridConnection,
+            6 => ActivePowerControlMode::PowerLimitedGridConnection,
+            7 => ActivePowerControlMode::PowerLimitedGridConnectionPercent,
+            code => ActivePowerControlMode::Other(code),
+        }
+    }
+}
+
+impl Display for ActivePowerControlMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActivePowerControlMode::Unlimited => write!(f, "unlimited"),
+            ActivePowerControlMode::DiActiveScheduling => write!(f, "DI active scheduling"),
+            ActivePowerControlMode::ZeroPowerGridConnection => {
+                write!(f, "zero power grid connection")
+            }
+            ActivePowerControlMode::PowerLimitedGridConnection => {
+                write!(f, "power-limited grid connection (kW)")
+            }
+            ActivePowerControlMode::PowerLimitedGridConnectionPercent => {
+                write!(f, "power-limited grid connection (%)")
+            }
+            ActivePowerControlMode::Other(code) => write!(f, "undocumented mode {}", code),
+        }
+    }
+}
+
+impl RegisterType for ActivePowerControlMode {
+    const TYPE: Type = Type::U16;
+
+    fn convert(vec: &[u16]) -> Option<Self> {
+        u16::convert(vec).map(ActivePowerControlMode::from_code)
+    }
+
+    fn encode(&self, quantity: usize) -> Option<Vec<u16>> {
+        match self {
+            ActivePowerControlMode::Other(_) => None,
+            mode => mode.code().encode(quantity),
+        }
+    }
+}
+
+/// Limit of the power exported to the grid, see
+/// [Inverter::set_export_limit]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ExportLimit {
+    Unlimited,
+    /// No export at all
+    Zero,
+    /// Export at most this many kW
+    Power(f64),
+    /// Export at most this percentage of the maximum active power
+    Percent(f64),
+}
+
+impl Inverter {
+    /// Limit the active power to `percent` of the maximum active power
+    pub fn derate_percent(&mut self, percent: f64) -> Result<(), Error> {
+        check_range("active power derating", percent, 0.0, 100.0, "%")?;
+        self.write_float(&registers::ACTIVE_POWER_PERCENTAGE_DERATING, percent)
+    }
+
+    /// Limit the active power to `power` in kW, at most
+    /// [MAXIMUM_ACTIVE_POWER](registers::MAXIMUM_ACTIVE_POWER)
+    pub fn derate_power(&mut self, power: f64) -> Result<(), Error> {
+        let maximum = self.read_float(&registers::MAXIMUM_ACTIVE_POWER)?;
+        check_range("active power derating", power, 0.0, maximum, "kW")?;
+        self.write_float(&registers::ACTIVE_POWER_FIXED_VALUE_DERATING, power)
+    }
+
+    /// Operate at the fixed power factor `power_factor`, negative values
+    /// are underexcited.
+    ///
+    /// The inverter accepts power factors from 0.8 to 1 in both
+    /// directions.
+    pub fn set_power_factor(&mut self, power_factor: f64) -> Result<(), Error> {
+        check_range("power factor", power_factor.abs(), 0.8, 1.0, "")?;
+        self.write_float(&registers::REACTIVE_POWER_COMPENSATION_PF, power_factor)
+    }
+
+    /// Supply the fixed reactive power `power` in kVar, at most
+    /// [MAXIMUM_APPARENT_POWER](registers::MAXIMUM_APPARENT_POWER) in both
+    /// directions
+    pub fn set_reactive_power(&mut self, power: f64) -> Result<(), Error> {
+        let maximum = self.read_float(&registers::MAXIMUM_APPARENT_POWER)?;
+        check_range("reactive power", power, -maximum, maximum, "kVar")?;
+        // the register holds the reactive power relative to the apparent power
+        self.write_float(&registers::REACTIVE_POWER_COMPENSATION_Q_S, power / maximum)
+    }
+
+    /// Set how much power the plant may export to the grid.
+    ///
+    /// Power limits are checked against
+    /// [MAXIMUM_ACTIVE_POWER](registers::MAXIMUM_ACTIVE_POWER). The limit is
+    /// written before the control mode so the inverter never applies a
+    /// stale one.
+    pub fn set_export_limit(&mut self, limit: ExportLimit) -> Result<(), Error> {
+        let mode = match limit {
+            ExportLimit::Unlimited => ActivePowerControlMode::Unlimited,
+            ExportLimit::Zero => ActivePowerControlMode::ZeroPowerGridConnection,
+            ExportLimit::Power(power) => {
+                let maximum = self.read_float(&registers::MAXIMUM_ACTIVE_POWER)?;
+                check_range("export limit", power, 0.0, maximum, "kW")?;
+                self.write_float(&registers::MAXIMUM_FEED_GRID_POWER, power)?;
+                ActivePowerControlMode::PowerLimitedGridConnection
+            }
+            ExportLimit::Percent(percent) => {
+                check_range("export limit", percent, 0.0, 100.0, "%")?;
+                self.write_float(&registers::MAXIMUM_FEED_GRID_POWER_PERCENT, percent)?;
+                ActivePowerControlMode::PowerLimitedGridConnectionPercent
+            }
+        };
+        self.write_typed(&registers::ACTIVE_POWER_CONTROL_MODE, &mode)
+    }
+
+    fn read_float<T: RegisterType + Into<f64>>(
+        &mut self,
+        reg: &TypedRegister<T>,
+    ) -> Result<f64, Error> {
+        Ok(reg.gain.apply(self.read(reg)?.into()))
+    }
+
+    fn write_float<T>(&mut self, reg: &TypedRegister<T>, value: f64) -> Result<(), Error> {
+        let value = reg.value_from_float(value)?;
+        self.write(reg, value)
+    }
+}
+
+fn check_range(what: &str, value: f64, min: f64, max: f64, unit: &str) -> Result<(), Error> {
+    if (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(RegisterError::ValueConversion(format!(
+            "{} {}{} is not between {}{} and {}{}",
+            what, value, unit, min, unit, max, unit
+        ))
+        .into())
+    }
+}
+
+#[test]
+fn control_mode_codes() {
+    for code in 0..10 {
+        let mode = ActivePowerControlMode::from_code(code);
+        assert_eq!(mode.code(), code);
+        assert_eq!(ActivePowerControlMode::convert(&[code]), Some(mode));
+    }
+    assert_eq!(
+        ActivePowerControlMode::ZeroPowerGridConnection.encode(1),
+        Some(vec![5])
+    );
+    assert_eq!(ActivePowerControlMode::Other(2).encode(1), None);
+}
+
+#[test]
+fn power_control_sequence() {
+    let (mut inverter, server) = crate::tcp_inverter(|stream| {
+        // MAXIMUM_ACTIVE_POWER of 11 kW
+        let maximum = [0x03, 0x04, 0x00, 0x00, 0x2A, 0xF8];
+        let exchanges: [(&[u8], &[u8]); 7] = [
+            (
+                &[0x06, 0x9C, 0xBD, 0x01, 0xF4],
+                &[0x06, 0x9C, 0xBD, 0x01, 0xF4],
+            ),
+            (&[0x03, 0x75, 0x7B, 0x00, 0x02], &maximum),
+            (
+                &[0x10, 0x9C, 0xBE, 0x00, 0x02, 0x04, 0x00, 0x00, 0x13, 0x88],
+                &[0x10, 0x9C, 0xBE, 0x00, 0x02],
+            ),
+            (
+                &[0x06, 0x9C, 0xBA, 0xFC, 0x72],
+                &[0x06, 0x9C, 0xBA, 0xFC, 0x72],
+            ),
+            (&[0x03, 0x75, 0x7B, 0x00, 0x02], &maximum),
+            (
+                &[0x10, 0xB9, 0x38, 0x00, 0x02, 0x04, 0x00, 0x00, 0x0F, 0xA0],
+                &[0x10, 0xB9, 0x38, 0x00, 0x02],
+            ),
+            (
+                &[0x06, 0xB9, 0x37, 0x00, 0x06],
+                &[0x06, 0xB9, 0x37, 0x00, 0x06],
+            ),
+        ];
+        for (request, response) in exchanges {
+            assert_eq!(crate::tcp::reply(stream, response), request);
+        }
+        crate::tcp::reply(stream, &maximum);
+    });
+    inverter.derate_percent(50.0).unwrap();
+    inverter.derate_power(5.0).unwrap();
+    inverter.set_power_factor(-0.91).unwrap();
+    inverter.set_export_limit(ExportLimit::Power(4.0)).unwrap();
+    assert!(matches!(
+        inverter.set_export_limit(ExportLimit::Power(12.0)),
+        Err(Error::Register(_))
+    ));
+    // checked without asking the inverter
+    assert!(matches!(
+        inverter.derate_percent(101.0),
+        Err(Error::Register(_))
+    ));
+    assert!(matches!(
+        inverter.set_power_factor(0.5),
+        Err(Error::Register(_))
+    ));
+    assert!(matches!(
+        inverter.set_export_limit(ExportLimit::Percent(-1.0)),
+        Err(Error::Register(_))
+    ));
+    server.join().unwrap();
+}
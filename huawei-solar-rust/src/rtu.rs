@@ -0,0 +1,297 @@
+//! Modbus RTU transport for inverters connected over RS485.
+
+use std::{
+    io::{self, Read, Write},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use modbus::{Error, Reason, Result};
+pub use serialport::Parity;
+use serialport::{ClearBuffer, DataBits, SerialPort, StopBits};
+
+use crate::pdu::{self, Transact};
+
+/// Modbus RTU framer on top of a serial port.
+///
+/// Frames are `unit id | PDU | CRC16` and are delimited by at least
+/// 3.5 character times of silence on the line.
+pub struct Transport {
+    port: Box<dyn SerialPort>,
+    uid: u8,
+    silence: Duration,
+    last_frame: Option<Instant>,
+}
+
+impl Transport {
+    /// Open the serial port at `path` with 8 data bits and 1 stop bit
+    pub fn new(
+        path: &str,
+        baud_rate: u32,
+        parity: Parity,
+        uid: u8,
+        timeout: Duration,
+    ) -> io::Result<Transport> {
+        let port = serialport::new(path, baud_rate)
+            .data_bits(DataBits::Eight)
+            .stop_bits(StopBits::One)
+            .parity(parity)
+            .timeout(timeout)
+            .open()?;
+        Ok(Transport::with_port(port, uid, baud_rate))
+    }
+
+    /// Use an already opened serial port
+    pub fn with_port(port: Box<dyn SerialPort>, uid: u8, baud_rate: u32) -> Transport {
+        Transport {
+            port,
+            uid,
+            silence: silence(baud_rate),
+            last_frame: None,
+        }
+    }
+
+    /// Send `pdu` to the slave and return the PDU of its response
+    pub fn transact(&mut self, pdu: &[u8]) -> Result<Vec<u8>> {
+        let mut frame = Vec::with_capacity(pdu.len() + 3);
+        frame.push(self.uid);
+        frame.extend_from_slice(pdu);
+        frame.extend_from_slice(&crc16(&frame).to_le_bytes());
+
+        if let Some(elapsed) = self.last_frame.map(|t| t.elapsed()) {
+            if elapsed < self.silence {
+                sleep(self.silence - elapsed);
+            }
+        }
+        // drop whatever is left of a previous, timed out response
+        self.port
+            .clear(ClearBuffer::Input)
+            .map_err(io::Error::from)?;
+
+        let result = self.write_read(&frame);
+        self.last_frame = Some(Instant::now());
+        let reply = result?;
+
+        let (data, crc) = reply.split_at(reply.len() - 2);
+        if crc16(data).to_le_bytes() != crc {
+            return Err(Error::InvalidData(Reason::Custom(String::from(
+                "CRC mismatch",
+            ))));
+        }
+        if reply[0] != self.uid {
+            return Err(Error::InvalidResponse);
+        }
+        pdu::check_response(pdu, &data[1..])?;
+        Ok(data[1..].to_vec())
+    }
+
+    fn write_read(&mut self, frame: &[u8]) -> Result<Vec<u8>> {
+        self.port.write_all(frame)?;
+        self.port.flush()?;
+
+        // unit id, function code and the first data byte
+        let mut reply = vec![0; 3];
+        self.port.read_exact(&mut reply)?;
+        let remaining = if reply[1] & 0x80 != 0 {
+            2
+        } else {
+            match reply[1] {
+                0x01..=0x04 | 0x17 => reply[2] as usize + 2,
+                0x05 | 0x06 | 0x0F | 0x10 => 5,
+                // Huawei custom function: sub-command, byte count, data
+                0x41 => {
+                    reply.push(0);
+                    self.port.read_exact(&mut reply[3..])?;
+                    reply[3] as usize + 2
+                }
+                _ => return Err(Error::InvalidResponse),
+            }
+        };
+        let start = reply.len();
+        reply.resize(start + remaining, 0);
+        self.port.read_exact(&mut reply[start..])?;
+        Ok(reply)
+    }
+
+    pub fn close(&mut self) -> Result<()> {
+        self.port.flush().map_err(Error::Io)
+    }
+}
+
+impl Transact for Transport {
+    fn transact(&mut self, pdu: &[u8]) -> Result<Vec<u8>> {
+        Transport::transact(self, pdu)
+    }
+}
+
+pdu::modbus_client!(Transport);
+
+/// Minimum silence between two frames: 3.5 character times of 11 bits,
+/// fixed to 1.75ms above 19200 baud as recommended by the Modbus spec
+pub fn silence(baud_rate: u32) -> Duration {
+    if baud_rate > 19200 {
+        Duration::from_micros(1750)
+    } else {
+        Duration::from_micros(38_500_000 / baud_rate.max(1) as u64)
+    }
+}
+
+/// CRC-16/MODBUS, transmitted low byte first
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u16, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+#[test]
+fn crc16_test() {
+    assert_eq!(
+        crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]).to_le_bytes(),
+        [0xC5, 0xCD]
+    );
+    assert_eq!(
+        crc16(&[0x11, 0x06, 0x00, 0x01, 0x00, 0x03]).to_le_bytes(),
+        [0x9A, 0x9B]
+    );
+    assert_eq!(crc16(&[]), 0xFFFF);
+}
+
+#[test]
+fn silence_test() {
+    assert_eq!(silence(9600), Duration::from_micros(4010));
+    assert_eq!(silence(19200), Duration::from_micros(2005));
+    assert_eq!(silence(115200), Duration::from_micros(1750));
+}
+
+/// Run `slave` on one end of a pseudo terminal pair and return a transport
+/// for unit 1 on the other end. The slave end stays open until joined, as
+/// closing it hangs up the master.
+#[cfg(all(test, unix))]
+pub(crate) fn pty_transport<F>(
+    slave: F,
+) -> (Transport, std::thread::JoinHandle<serialport::TTYPort>)
+where
+    F: FnOnce(&mut serialport::TTYPort) + Send + 'static,
+{
+    let (mut master, mut slave_port) = serialport::TTYPort::pair().unwrap();
+    master.set_timeout(Duration::from_millis(500)).unwrap();
+    slave_port.set_timeout(Duration::from_secs(2)).unwrap();
+    let handle = std::thread::spawn(move || {
+        slave(&mut slave_port);
+        slave_port
+    });
+    (Transport::with_port(Box::new(master), 1, 9600), handle)
+}
+
+#[cfg(all(test, unix))]
+pub(crate) fn reply(port: &mut serialport::TTYPort, request_len: usize, reply: &[u8]) {
+    let mut request = vec![0; request_len];
+    port.read_exact(&mut request).unwrap();
+    let (data, crc) = request.split_at(request_len - 2);
+    assert_eq!(crc16(data).to_le_bytes(), crc);
+
+    let mut frame = reply.to_vec();
+    frame.extend_from_slice(&crc16(reply).to_le_bytes());
+    port.write_all(&frame).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn rtu_read_holding_registers() {
+    let (mut transport, slave) = pty_transport(|port| {
+        reply(port, 8, &[0x01, 0x03, 0x04, 0x0F, 0xA0, 0x03, 0x39]);
+        reply(port, 8, &[0x01, 0x03, 0x02, 0x0E, 0x42]);
+    });
+    let client: &mut dyn modbus::Client = &mut transport;
+    assert_eq!(
+        client.read_holding_registers(32016, 2).unwrap(),
+        vec![0x0FA0, 0x0339]
+    );
+    assert_eq!(
+        client.read_holding_registers(32018, 1).unwrap(),
+        vec![0x0E42]
+    );
+    slave.join().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn rtu_write_registers() {
+    let (mut transport, slave) = pty_transport(|port| {
+        reply(port, 8, &[0x01, 0x06, 0xA7, 0xFE, 0xFF, 0xC4]);
+        reply(port, 13, &[0x01, 0x10, 0x9C, 0x40, 0x00, 0x02]);
+    });
+    let client: &mut dyn modbus::Client = &mut transport;
+    client.write_single_register(43006, 0xFFC4).unwrap();
+    client
+        .write_multiple_registers(40000, &[0x6601, 0x2A80])
+        .unwrap();
+    slave.join().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn rtu_exception() {
+    let (mut transport, slave) = pty_transport(|port| {
+        reply(port, 8, &[0x01, 0x83, 0x02]);
+    });
+    let client: &mut dyn modbus::Client = &mut transport;
+    assert!(matches!(
+        client.read_holding_registers(31000, 1),
+        Err(Error::Exception(modbus::ExceptionCode::IllegalDataAddress))
+    ));
+    slave.join().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn rtu_crc_mismatch() {
+    let (mut transport, slave) = pty_transport(|port| {
+        let mut request = [0; 8];
+        port.read_exact(&mut request).unwrap();
+        port.write_all(&[0x01, 0x03, 0x02, 0x0E, 0x42, 0x00, 0x00])
+            .unwrap();
+    });
+    let client: &mut dyn modbus::Client = &mut transport;
+    assert!(matches!(
+        client.read_holding_registers(32018, 1),
+        Err(Error::InvalidData(Reason::Custom(_)))
+    ));
+    slave.join().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn rtu_timeout() {
+    let (mut transport, slave) = pty_transport(|port| {
+        let mut request = [0; 8];
+        port.read_exact(&mut request).unwrap();
+    });
+    let client: &mut dyn modbus::Client = &mut transport;
+    assert!(matches!(
+        client.read_holding_registers(32018, 1),
+        Err(Error::Io(_))
+    ));
+    slave.join().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn rtu_inter_frame_silence() {
+    let (mut transport, slave) = pty_transport(|port| {
+        reply(port, 8, &[0x01, 0x03, 0x02, 0x00, 0x01]);
+        let start = Instant::now();
+        reply(port, 8, &[0x01, 0x03, 0x02, 0x00, 0x02]);
+        assert!(start.elapsed() >= silence(9600));
+    });
+    let client: &mut dyn modbus::Client = &mut transport;
+    client.read_holding_registers(0, 1).unwrap();
+    client.read_holding_registers(0, 1).unwrap();
+    slave.join().unwrap();
+}
@@ -0,0 +1,417 @@
+//! Decoded status registers of the inverter.
+
+use std::{borrow::Cow, fmt::Display};
+
+use crate::registers::{RegisterError, RegisterType, Type};
+
+/// Coarse operating state of the inverter, grouping the [DeviceStatus] codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum StatusCategory {
+    Standby,
+    Starting,
+    OnGrid,
+    Shutdown,
+    GridScheduling,
+    SpotCheck,
+    Inspecting,
+    AfciSelfCheck,
+    IvScanning,
+    DcInputDetection,
+    OffGridCharging,
+    Unknown,
+}
+
+macro_rules! device_status {
+    ($($variant:ident = $code:literal, $category:ident, $name:literal, $text:literal;)*) => {
+        /// Content of the [DEVICE_STATUS](crate::registers::DEVICE_STATUS) register
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[cfg_attr(
+            feature = "serde",
+            derive(serde::Serialize, serde::Deserialize),
+            serde(rename_all = "snake_case")
+        )]
+        pub enum DeviceStatus {
+            $($variant,)*
+            /// Code not documented in the register table, kept as read
+            Unknown(u16),
+        }
+
+        impl DeviceStatus {
+            /// Raw register value
+            pub const fn code(&self) -> u16 {
+                match self {
+                    $(DeviceStatus::$variant => $code,)*
+                    DeviceStatus::Unknown(code) => *code,
+                }
+            }
+
+            pub const fn category(&self) -> StatusCategory {
+                match self {
+                    $(DeviceStatus::$variant => StatusCategory::$category,)*
+                    DeviceStatus::Unknown(_) => StatusCategory::Unknown,
+                }
+            }
+
+            /// Stable identifier, e.g. for a state column in the database.
+            /// Unknown codes are named `unknown_<code in hex>`.
+            pub fn name(&self) -> Cow<'static, str> {
+                match self {
+                    $(DeviceStatus::$variant => Cow::Borrowed($name),)*
+                    DeviceStatus::Unknown(code) => Cow::Owned(format!("unknown_{:04x}", code)),
+                }
+            }
+        }
+
+        impl TryFrom<u16> for DeviceStatus {
+            type Error = RegisterError;
+
+            /// Fails for codes not documented by Huawei, use
+            /// [RegisterType::convert] to keep them as [DeviceStatus::Unknown]
+            fn try_from(code: u16) -> Result<Self, Self::Error> {
+                match code {
+                    $($code => Ok(DeviceStatus::$variant),)*
+                    _ => Err(RegisterError::ValueConversion(format!(
+                        "Unknown device status {:#06x}",
+                        code
+                    ))),
+                }
+            }
+        }
+
+        impl Display for DeviceStatus {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $(DeviceStatus::$variant => write!(f, $text),)*
+                    DeviceStatus::Unknown(code) => write!(f, "Unknown status {:#06x}", code),
+                }
+            }
+        }
+    };
+}
+
+#[rustfmt::skip]
+device_status! {
+    StandbyInitializing               = 0x0000, Standby,          "standby_initializing",                 "Standby: initializing";
+    StandbyDetectingInsulation        = 0x0001, Standby,          "standby_detecting_insulation",         "Standby: detecting insulation resistance";
+    StandbyDetectingIrradiation       = 0x0002, Standby,          "standby_detecting_irradiation",        "Standby: detecting irradiation";
+    StandbyDetectingGrid              = 0x0003, Standby,          "standby_detecting_grid",               "Standby: grid detecting";
+    Starting                          = 0x0100, Starting,         "starting",                             "Starting";
+    OnGrid                            = 0x0200, OnGrid,           "on_grid",                              "On-grid (Off-grid mode: running)";
+    OnGridPowerLimited                = 0x0201, OnGrid,           "on_grid_power_limited",                "Grid connection: power limited (Off-grid mode: running: power limited)";
+    OnGridSelfDerating                = 0x0202, OnGrid,           "on_grid_self_derating",                "Grid connection: self-derating (Off-grid mode: running: self-derating)";
+    ShutdownFault                     = 0x0300, Shutdown,         "shutdown_fault",                       "Shutdown: fault";
+    ShutdownCommand                   = 0x0301, Shutdown,         "shutdown_command",                     "Shutdown: command";
+    ShutdownOvgr                      = 0x0302, Shutdown,         "shutdown_ovgr",                        "Shutdown: OVGR";
+    ShutdownCommunicationDisconnected = 0x0303, Shutdown,         "shutdown_communication_disconnected",  "Shutdown: communication disconnected";
+    ShutdownPowerLimited              = 0x0304, Shutdown,         "shutdown_power_limited",               "Shutdown: power limited";
+    ShutdownManualStartupRequired     = 0x0305, Shutdown,         "shutdown_manual_startup_required",     "Shutdown: manual startup required";
+    ShutdownDcSwitchesDisconnected    = 0x0306, Shutdown,         "shutdown_dc_switches_disconnected",    "Shutdown: DC switches disconnected";
+    ShutdownRapidCutoff               = 0x0307, Shutdown,         "shutdown_rapid_cutoff",                "Shutdown: rapid cutoff";
+    ShutdownInputUnderpower           = 0x0308, Shutdown,         "shutdown_input_underpower",            "Shutdown: input underpower";
+    GridSchedulingCosPhiP             = 0x0401, GridScheduling,   "grid_scheduling_cos_phi_p",            "Grid scheduling: cosφ-P curve";
+    GridSchedulingQU                  = 0x0402, GridScheduling,   "grid_scheduling_q_u",                  "Grid scheduling: Q-U curve";
+    GridSchedulingPfU                 = 0x0403, GridScheduling,   "grid_scheduling_pf_u",                 "Grid scheduling: PF-U curve";
+    GridSchedulingDryContact          = 0x0404, GridScheduling,   "grid_scheduling_dry_contact",          "Grid scheduling: dry contact";
+    GridSchedulingQP                  = 0x0405, GridScheduling,   "grid_scheduling_q_p",                  "Grid scheduling: Q-P curve";
+    SpotCheckReady                    = 0x0500, SpotCheck,        "spot_check_ready",                     "Spot-check ready";
+    SpotChecking                      = 0x0501, SpotCheck,        "spot_checking",                        "Spot-checking";
+    Inspecting                        = 0x0600, Inspecting,       "inspecting",                           "Inspecting";
+    AfciSelfCheck                     = 0x0700, AfciSelfCheck,    "afci_self_check",                      "AFCI self check";
+    IvScanning                        = 0x0800, IvScanning,       "iv_scanning",                          "I-V scanning";
+    DcInputDetection                  = 0x0900, DcInputDetection, "dc_input_detection",                   "DC input detection";
+    OffGridCharging                   = 0x0A00, OffGridCharging,  "off_grid_charging",                    "Running: off-grid charging";
+    StandbyNoIrradiation              = 0xA000, Standby,          "standby_no_irradiation",               "Standby: no irradiation";
+}
+
+impl DeviceStatus {
+    /// The inverter feeds into the grid
+    pub const fn is_producing(&self) -> bool {
+        matches!(self.category(), StatusCategory::OnGrid)
+    }
+}
+
+impl From<DeviceStatus> for u16 {
+    fn from(status: DeviceStatus) -> Self {
+        status.code()
+    }
+}
+
+impl RegisterType for DeviceStatus {
+    const TYPE: Type = Type::U16;
+
+    fn convert(vec: &[u16]) -> Option<Self> {
+        let code = u16::convert(vec)?;
+        Some(DeviceStatus::try_from(code).unwrap_or(DeviceStatus::Unknown(code)))
+    }
+
+    fn encode(&self, quantity: usize) -> Option<Vec<u16>> {
+        self.code().encode(quantity)
+    }
+}
+
+#[test]
+fn device_status_from_code() {
+    assert_eq!(
+        DeviceStatus::try_from(0x0000).unwrap(),
+        DeviceStatus::StandbyInitializing
+    );
+    assert_eq!(
+        DeviceStatus::try_from(0x0201).unwrap(),
+        DeviceStatus::OnGridPowerLimited
+    );
+    assert_eq!(
+        DeviceStatus::try_from(0x0308).unwrap(),
+        DeviceStatus::ShutdownInputUnderpower
+    );
+    assert_eq!(
+        DeviceStatus::try_from(0xA000).unwrap(),
+        DeviceStatus::StandbyNoIrradiation
+    );
+    assert!(DeviceStatus::try_from(0x0309).is_err());
+    assert!(DeviceStatus::try_from(0x0400).is_err());
+}
+
+#[test]
+fn device_status_unknown_is_preserved() {
+    let status = DeviceStatus::convert(&[0x1234]).unwrap();
+    assert_eq!(status, DeviceStatus::Unknown(0x1234));
+    assert_eq!(status.code(), 0x1234);
+    assert_eq!(status.category(), StatusCategory::Unknown);
+    assert_eq!(status.name(), "unknown_1234");
+    assert_eq!(status.encode(1), Some(vec![0x1234]));
+}
+
+#[test]
+fn device_status_roundtrip() {
+    for code in 0..=u16::MAX {
+        if let Ok(status) = DeviceStatus::try_from(code) {
+            assert_eq!(status.code(), code);
+            assert_eq!(DeviceStatus::convert(&[code]), Some(status));
+        }
+    }
+}
+
+#[test]
+fn device_status_category() {
+    assert_eq!(
+        DeviceStatus::OnGridSelfDerating.category(),
+        StatusCategory::OnGrid
+    );
+    assert_eq!(
+        DeviceStatus::StandbyNoIrradiation.category(),
+        StatusCategory::Standby
+    );
+    assert_eq!(
+        DeviceStatus::GridSchedulingQP.category(),
+        StatusCategory::GridScheduling
+    );
+    assert!(DeviceStatus::OnGrid.is_producing());
+    assert!(!DeviceStatus::ShutdownCommand.is_producing());
+    assert_eq!(DeviceStatus::ShutdownOvgr.to_string(), "Shutdown: OVGR");
+    assert_eq!(DeviceStatus::ShutdownOvgr.name(), "shutdown_ovgr");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn device_status_serde() {
+    let json = serde_json::to_string(&[DeviceStatus::OnGrid, DeviceStatus::Unknown(7)]).unwrap();
+    assert_eq!(json, r#"["on_grid",{"unknown":7}]"#);
+    let status: DeviceStatus = serde_json::from_str(r#""shutdown_fault""#).unwrap();
+    assert_eq!(status, DeviceStatus::ShutdownFault);
+    // database state names and serde names agree
+    for code in 0..=u16::MAX {
+        if let Ok(status) = DeviceStatus::try_from(code) {
+            let json = serde_json::to_string(&status).unwrap();
+            assert_eq!(json.trim_matches('"'), status.name());
+        }
+    }
+}
+
+#[test]
+fn device_status_from_reg_value() {
+    use crate::registers::{RegValue, Value, DEVICE_STATUS};
+
+    let val = RegValue {
+        reg: &DEVICE_STATUS,
+        val: DEVICE_STATUS.typ.convert(&[0x0305]).unwrap(),
+    };
+    assert_eq!(
+        val.to_device_status().unwrap(),
+        DeviceStatus::ShutdownManualStartupRequired
+    );
+    let val = RegValue {
+        reg: &DEVICE_STATUS,
+        val: Value::STR(String::new()),
+    };
+    assert!(val.to_device_status().is_err());
+}
+
+bitflags::bitflags! {
+    /// Content of the [STATE_1](crate::registers::STATE_1) register
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct State1: u16 {
+        const STANDBY = 1 << 0;
+        const GRID_CONNECTED = 1 << 1;
+        const GRID_CONNECTED_NORMALLY = 1 << 2;
+        /// Derating due to power rationing
+        const DERATING_POWER_RATIONING = 1 << 3;
+        /// Derating due to internal causes of the inverter
+        const DERATING_INTERNAL = 1 << 4;
+        const NORMAL_STOP = 1 << 5;
+        const STOP_FAULT = 1 << 6;
+        const STOP_POWER_RATIONING = 1 << 7;
+        const SHUTDOWN = 1 << 8;
+        const SPOT_CHECK = 1 << 9;
+    }
+
+    /// Content of the [STATE_2](crate::registers::STATE_2) register
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct State2: u16 {
+        /// Cleared while the inverter is locked
+        const UNLOCKED = 1 << 0;
+        const PV_CONNECTED = 1 << 1;
+        const DSP_DATA_COLLECTION = 1 << 2;
+    }
+
+    /// Content of the [STATE_3](crate::registers::STATE_3) register
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct State3: u32 {
+        /// Cleared while on-grid
+        const OFF_GRID = 1 << 0;
+        const OFF_GRID_SWITCH_ENABLED = 1 << 1;
+    }
+}
+
+/// Write the active flags as lower case words, e.g. `standby, pv connected`.
+/// Undocumented bits are written as hex value.
+fn write_flags<'a>(
+    f: &mut std::fmt::Formatter<'_>,
+    names: impl Iterator<Item = &'a str>,
+    undocumented: u32,
+) -> std::fmt::Result {
+    let mut first = true;
+    for name in names {
+        if !first {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", name.to_lowercase().replace('_', " "))?;
+        first = false;
+    }
+    if undocumented != 0 {
+        if !first {
+            write!(f, ", ")?;
+        }
+        write!(f, "undocumented {:#x}", undocumented)?;
+        first = false;
+    }
+    if first {
+        write!(f, "none")?;
+    }
+    Ok(())
+}
+
+macro_rules! state_register {
+    ($flags:ident, $bits:ty) => {
+        impl Display for $flags {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write_flags(
+                    f,
+                    self.iter_names().map(|(name, _)| name),
+                    (self.bits() & !$flags::all().bits()).into(),
+                )
+            }
+        }
+
+        impl RegisterType for $flags {
+            const TYPE: Type = Type::BF;
+
+            fn convert(vec: &[u16]) -> Option<Self> {
+                <$bits>::convert(vec).map($flags::from_bits_retain)
+            }
+
+            fn encode(&self, quantity: usize) -> Option<Vec<u16>> {
+                self.bits().encode(quantity)
+            }
+        }
+    };
+}
+
+state_register!(State1, u16);
+state_register!(State2, u16);
+state_register!(State3, u32);
+
+#[test]
+#[rustfmt::skip]
+fn state_1_bits() {
+    let bit = |n: u16| State1::convert(&[1 << n]).unwrap();
+    assert_eq!(bit(0), State1::STANDBY);
+    assert_eq!(bit(1), State1::GRID_CONNECTED);
+    assert_eq!(bit(2), State1::GRID_CONNECTED_NORMALLY);
+    assert_eq!(bit(3), State1::DERATING_POWER_RATIONING);
+    assert_eq!(bit(4), State1::DERATING_INTERNAL);
+    assert_eq!(bit(5), State1::NORMAL_STOP);
+    assert_eq!(bit(6), State1::STOP_FAULT);
+    assert_eq!(bit(7), State1::STOP_POWER_RATIONING);
+    assert_eq!(bit(8), State1::SHUTDOWN);
+    assert_eq!(bit(9), State1::SPOT_CHECK);
+    assert_eq!(State1::convert(&[0x0006]).unwrap().to_string(), "grid connected, grid connected normally");
+}
+
+#[test]
+fn state_2_bits() {
+    let bit = |n: u16| State2::convert(&[1 << n]).unwrap();
+    assert_eq!(bit(0), State2::UNLOCKED);
+    assert_eq!(bit(1), State2::PV_CONNECTED);
+    assert_eq!(bit(2), State2::DSP_DATA_COLLECTION);
+    assert_eq!(State2::empty().to_string(), "none");
+    assert_eq!(
+        State2::convert(&[0x0003]).unwrap().to_string(),
+        "unlocked, pv connected"
+    );
+}
+
+#[test]
+fn state_3_bits() {
+    // 32 bit register, bit 0 is in the second word
+    assert_eq!(State3::convert(&[0, 1]).unwrap(), State3::OFF_GRID);
+    assert_eq!(
+        State3::convert(&[0, 2]).unwrap(),
+        State3::OFF_GRID_SWITCH_ENABLED
+    );
+    assert_eq!(State3::convert(&[1, 0]).unwrap().bits(), 0x0001_0000);
+    assert_eq!(
+        State3::convert(&[1, 1]).unwrap().to_string(),
+        "off grid, undocumented 0x10000"
+    );
+    assert_eq!(State3::OFF_GRID.encode(2), Some(vec![0, 1]));
+}
+
+#[test]
+fn state_from_reg_value() {
+    use crate::registers::{RegValue, STATE_1, STATE_3};
+
+    let val = RegValue {
+        reg: &STATE_1,
+        val: STATE_1.typ.convert(&[0x0120]).unwrap(),
+    };
+    assert_eq!(
+        val.decode::<State1>().unwrap(),
+        State1::NORMAL_STOP | State1::SHUTDOWN
+    );
+    let val = RegValue {
+        reg: &STATE_3,
+        val: STATE_3.typ.convert(&[0x0000, 0x0002]).unwrap(),
+    };
+    assert_eq!(
+        val.decode::<State3>().unwrap(),
+        State3::OFF_GRID_SWITCH_ENABLED
+    );
+}
@@ -0,0 +1,219 @@
+//! Modbus TCP transport for inverters reached over the network.
+
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use modbus::{Error, Result};
+
+use crate::pdu::{self, Transact};
+
+/// Size of the MBAP header including the unit ID
+const HEADER_LEN: usize = 7;
+
+/// Modbus TCP framer on top of a TCP stream.
+///
+/// Frames are `MBAP header | PDU`. Unlike the transport of the `modbus`
+/// crate this one passes arbitrary function codes, as needed for the
+/// Huawei specific function 0x41.
+pub struct Transport {
+    stream: TcpStream,
+    uid: u8,
+    transaction_id: u16,
+}
+
+impl Transport {
+    /// Connect to `addr:port`, the timeouts apply to each request
+    pub fn new(
+        addr: &str,
+        port: u16,
+        uid: u8,
+        read_timeout: Option<Duration>,
+        write_timeout: Option<Duration>,
+        connect_timeout: Option<Duration>,
+    ) -> io::Result<Transport> {
+        let stream = match connect_timeout {
+            Some(timeout) => {
+                let mut last_err = None;
+                let mut stream = None;
+                for addr in (addr, port).to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&addr, timeout) {
+                        Ok(s) => {
+                            stream = Some(s);
+                            break;
+                        }
+                        Err(err) => last_err = Some(err),
+                    }
+                }
+                match stream {
+                    Some(stream) => stream,
+                    None => {
+                        return Err(last_err.unwrap_or_else(|| {
+                            io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to")
+                        }))
+                    }
+                }
+            }
+            None => TcpStream::connect((addr, port))?,
+        };
+        stream.set_read_timeout(read_timeout)?;
+        stream.set_write_timeout(write_timeout)?;
+        stream.set_nodelay(true)?;
+        Ok(Transport::with_stream(stream, uid))
+    }
+
+    /// Use an already connected stream
+    pub fn with_stream(stream: TcpStream, uid: u8) -> Transport {
+        Transport {
+            stream,
+            uid,
+            transaction_id: 0,
+        }
+    }
+
+    /// Send `pdu` to the slave and return the PDU of its response
+    pub fn transact(&mut self, pdu: &[u8]) -> Result<Vec<u8>> {
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        let mut frame = Vec::with_capacity(HEADER_LEN + pdu.len());
+        frame.extend_from_slice(&self.transaction_id.to_be_bytes());
+        frame.extend_from_slice(&0u16.to_be_bytes());
+        frame.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+        frame.push(self.uid);
+        frame.extend_from_slice(pdu);
+        self.stream.write_all(&frame)?;
+
+        loop {
+            let mut header = [0u8; HEADER_LEN];
+            self.stream.read_exact(&mut header)?;
+            let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+            if len < 2 {
+                return Err(Error::InvalidResponse);
+            }
+            let mut response = vec![0u8; len - 1];
+            self.stream.read_exact(&mut response)?;
+            // response to a request which timed out earlier
+            if header[0..2] != frame[0..2] {
+                continue;
+            }
+            if header[2..4] != [0, 0] || header[6] != self.uid {
+                return Err(Error::InvalidResponse);
+            }
+            pdu::check_response(pdu, &response)?;
+            return Ok(response);
+        }
+    }
+
+    pub fn close(&mut self) -> Result<()> {
+        self.stream.shutdown(Shutdown::Both).map_err(Error::Io)
+    }
+}
+
+impl Transact for Transport {
+    fn transact(&mut self, pdu: &[u8]) -> Result<Vec<u8>> {
+        Transport::transact(self, pdu)
+    }
+}
+
+pdu::modbus_client!(Transport);
+
+/// Accept a single connection on a local port and run `server` on it,
+/// returning a transport for unit 1 connected to it
+#[cfg(test)]
+pub(crate) fn local_transport<F>(server: F) -> (Transport, std::thread::JoinHandle<()>)
+where
+    F: FnOnce(&mut TcpStream) + Send + 'static,
+{
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        server(&mut stream);
+    });
+    let transport = Transport::new(
+        "127.0.0.1",
+        port,
+        1,
+        Some(Duration::from_millis(500)),
+        Some(Duration::from_millis(500)),
+        Some(Duration::from_millis(500)),
+    )
+    .unwrap();
+    (transport, handle)
+}
+
+/// Read a request and answer it with the PDU `reply`, returning the PDU
+/// of the request
+#[cfg(test)]
+pub(crate) fn reply(stream: &mut TcpStream, reply: &[u8]) -> Vec<u8> {
+    let mut header = [0u8; HEADER_LEN];
+    stream.read_exact(&mut header).unwrap();
+    let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let mut request = vec![0u8; len - 1];
+    stream.read_exact(&mut request).unwrap();
+
+    let mut frame = header[0..4].to_vec();
+    frame.extend_from_slice(&(reply.len() as u16 + 1).to_be_bytes());
+    frame.push(header[6]);
+    frame.extend_from_slice(reply);
+    stream.write_all(&frame).unwrap();
+    request
+}
+
+#[test]
+fn tcp_read_holding_registers() {
+    let (mut transport, server) = local_transport(|stream| {
+        let request = reply(stream, &[0x03, 0x04, 0x0F, 0xA0, 0x03, 0x39]);
+        assert_eq!(request, [0x03, 0x7D, 0x10, 0x00, 0x02]);
+    });
+    let client: &mut dyn modbus::Client = &mut transport;
+    assert_eq!(
+        client.read_holding_registers(32016, 2).unwrap(),
+        vec![0x0FA0, 0x0339]
+    );
+    server.join().unwrap();
+}
+
+#[test]
+fn tcp_custom_function_and_exception() {
+    let (mut transport, server) = local_transport(|stream| {
+        reply(stream, &[0x41, 0x24, 0x02, 0x11, 0x00]);
+        reply(stream, &[0xC1, 0x01]);
+        reply(stream, &[0x86, 0x02]);
+    });
+    assert_eq!(
+        transport.transact(&[0x41, 0x24, 0x01, 0x00]).unwrap(),
+        vec![0x41, 0x24, 0x02, 0x11, 0x00]
+    );
+    assert!(matches!(
+        transport.transact(&[0x41, 0x24, 0x01, 0x00]),
+        Err(Error::Exception(modbus::ExceptionCode::IllegalFunction))
+    ));
+    let client: &mut dyn modbus::Client = &mut transport;
+    assert!(matches!(
+        client.write_single_register(49999, 1),
+        Err(Error::Exception(modbus::ExceptionCode::IllegalDataAddress))
+    ));
+    server.join().unwrap();
+}
+
+#[test]
+fn tcp_skips_stale_response() {
+    let (mut transport, server) = local_transport(|stream| {
+        let mut header = [0u8; HEADER_LEN];
+        stream.read_exact(&mut header).unwrap();
+        let mut request = [0u8; 5];
+        stream.read_exact(&mut request).unwrap();
+        // late answer to transaction 0, then the real one
+        stream
+            .write_all(&[0, 0, 0, 0, 0, 5, 1, 0x03, 0x02, 0x00, 0x07])
+            .unwrap();
+        stream
+            .write_all(&[0, 1, 0, 0, 0, 5, 1, 0x03, 0x02, 0x00, 0x2A])
+            .unwrap();
+    });
+    let client: &mut dyn modbus::Client = &mut transport;
+    assert_eq!(client.read_holding_registers(0, 1).unwrap(), vec![0x2A]);
+    server.join().unwrap();
+}
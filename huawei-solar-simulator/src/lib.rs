@@ -0,0 +1,94 @@
+//! Simulated SUN2000 inverter serving the [register table](huawei_solar::registers)
+//! over Modbus TCP, for testing clients without a real plant.
+//!
+//! ```no_run
+//! use std::sync::{Arc, Mutex};
+//! use huawei_solar_simulator::{model::{Scenario, Simulation}, server::{Memory, Server}};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let simulation = Simulation::new(Scenario::default());
+//! let mut memory = Memory::default();
+//! simulation.init(&mut memory)?;
+//! simulation.write(&mut memory)?;
+//! let server = Server::bind("127.0.0.1:5020", 0, Arc::new(Mutex::new(memory)))?;
+//! server.spawn().join().unwrap();
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod model;
+pub mod server;
+pub mod table;
+
+#[test]
+fn serve_simulated_inverter() {
+    use std::sync::{Arc, Mutex};
+
+    use huawei_solar::{
+        registers::{self, storage, DeviceStatus, Value},
+        Error, Inverter,
+    };
+
+    use model::{Battery, Scenario, Simulation};
+    use server::{Memory, Server};
+
+    let mut simulation = Simulation::new(Scenario {
+        start: 13.0 * 3600.0,
+        battery: Some(Battery {
+            capacity: 10.0,
+            soc: 50.0,
+            power: 5.0,
+        }),
+        ..Scenario::default()
+    });
+    let mut memory = Memory::default();
+    simulation.init(&mut memory).unwrap();
+    simulation.write(&mut memory).unwrap();
+    let memory = Arc::new(Mutex::new(memory));
+    let server = Server::bind("127.0.0.1:0", 1, memory.clone()).unwrap();
+    let port = server.local_addr().unwrap().port();
+    server.spawn();
+
+    let mut inverter =
+        Inverter::connect_tcp(Some("127.0.0.1"), Some(port), Some(1), None, None, None).unwrap();
+    let info = inverter.identify().unwrap();
+    assert_eq!(info.model, "SUN2000-10KTL-M1");
+    assert_eq!(info.rated_power, 10.0);
+    assert!(info.capabilities.battery);
+    assert_eq!(inverter.pv_strings().unwrap().len(), 2);
+    assert_eq!(
+        inverter.read(&registers::DEVICE_STATUS).unwrap(),
+        DeviceStatus::OnGrid
+    );
+    // 7.2 kW DC at noon, 5 kW of it charging the battery
+    assert_eq!(inverter.read(&registers::INPUT_POWER).unwrap(), 7200);
+    assert_eq!(inverter.read(&registers::ACTIVE_POWER).unwrap(), 1984);
+    assert_eq!(inverter.read(&storage::STATE_OF_CAPACITY).unwrap(), 500);
+
+    inverter
+        .write_verified(&registers::TIME_ZONE, Value::I16(60))
+        .unwrap();
+    inverter.derate_percent(10.0).unwrap();
+    simulation.step(&mut memory.lock().unwrap(), 60.0).unwrap();
+    assert_eq!(
+        inverter.read(&registers::DEVICE_STATUS).unwrap(),
+        DeviceStatus::OnGridPowerLimited
+    );
+    assert_eq!(inverter.read(&registers::INPUT_POWER).unwrap(), 7200);
+    assert_eq!(
+        inverter.read(&storage::CHARGE_DISCHARGE_POWER).unwrap(),
+        500
+    );
+
+    // read-only and gaps are rejected by the server
+    assert!(matches!(
+        inverter.write(registers::MODEL_ID.erased(), Value::U16(1)),
+        Err(Error::ReadOnly(_))
+    ));
+    let gap = registers::Register {
+        quantity: 2,
+        ..*registers::TIME_ZONE
+    };
+    assert!(matches!(inverter.read_raw(&gap), Err(Error::Modbus(_))));
+    // the connection survives exceptions
+    assert_eq!(inverter.read(&registers::TIME_ZONE).unwrap(), 60);
+}
@@ -0,0 +1,60 @@
+use std::{
+    env, fs,
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use huawei_solar_simulator::{
+    model::{Scenario, Simulation},
+    server::{Memory, Server},
+};
+
+const DEFAULT_ADDR: &str = "127.0.0.1:5020";
+const DEFAULT_UNIT_ID: u8 = 1;
+const TICK: Duration = Duration::from_secs(1);
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // usage: huawei-solar-simulator [scenario.yaml] [address] [unit id]
+    let mut args = env::args().skip(1);
+    let scenario = match args.next() {
+        Some(path) => {
+            println!("Loading scenario from {}", path);
+            serde_yaml::from_str(&fs::read_to_string(path)?)?
+        }
+        None => Scenario::default(),
+    };
+    let addr = args.next().unwrap_or_else(|| String::from(DEFAULT_ADDR));
+    let unit_id = match args.next() {
+        Some(unit_id) => unit_id.parse()?,
+        None => DEFAULT_UNIT_ID,
+    };
+
+    let mut simulation = Simulation::new(scenario);
+    let mut memory = Memory::default();
+    simulation.init(&mut memory)?;
+    simulation.write(&mut memory)?;
+    let memory = Arc::new(Mutex::new(memory));
+
+    let server = Server::bind(&addr, unit_id, memory.clone())?;
+    println!(
+        "Simulating {} as unit {} on {}",
+        simulation.scenario().model,
+        unit_id,
+        server.local_addr()?
+    );
+    server.spawn();
+
+    let speed = simulation.scenario().speed;
+    let mut last = Instant::now();
+    loop {
+        sleep(TICK);
+        let now = Instant::now();
+        let seconds = (now - last).as_secs_f64() * speed;
+        last = now;
+        let mut memory = memory
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        simulation.step(&mut memory, seconds)?;
+    }
+}
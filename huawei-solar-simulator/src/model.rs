@@ -0,0 +1,652 @@
+//! Scriptable state model of an inverter with PV strings, an optional
+//! LUNA2000 battery and a smart power meter.
+//!
+//! PV power follows a sine from sunrise to sunset, the battery maximises
+//! self consumption of a constant house load and [events](Event) raise
+//! alarms or force a device status for a period of the simulated day.
+
+use std::f64::consts::PI;
+
+use huawei_solar::{
+    alarms::{Alarm, Severity},
+    registers::{self, meter, storage, DeviceStatus, Register, RegisterError, Value},
+};
+use serde::{
+    de::{self, Deserializer},
+    Deserialize,
+};
+
+use crate::server::Memory;
+
+const DAY: f64 = 86_400.0;
+/// Longest step the energy and SOC are integrated over
+const MAX_STEP: f64 = 60.0;
+const EFFICIENCY: f64 = 0.97;
+const PHASE_VOLTAGE: f64 = 230.0;
+const LINE_VOLTAGE: f64 = 400.0;
+const BATTERY_BUS_VOLTAGE: f64 = 450.0;
+
+/// Configuration of a simulation, every field has a default
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Scenario {
+    pub model: String,
+    pub serial_number: String,
+    /// Rated power in kW
+    pub rated_power: f64,
+    /// Simulated time of day the simulation starts at
+    #[serde(deserialize_with = "time_of_day")]
+    pub start: f64,
+    /// Simulated seconds per real second
+    pub speed: f64,
+    #[serde(deserialize_with = "time_of_day")]
+    pub sunrise: f64,
+    #[serde(deserialize_with = "time_of_day")]
+    pub sunset: f64,
+    pub strings: Vec<PvString>,
+    /// Constant house consumption in kW
+    pub load: f64,
+    pub battery: Option<Battery>,
+    pub events: Vec<Event>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            model: String::from("SUN2000-10KTL-M1"),
+            serial_number: String::from("HV2140012345"),
+            rated_power: 10.0,
+            start: 6.0 * 3600.0,
+            speed: 60.0,
+            sunrise: 6.0 * 3600.0,
+            sunset: 20.0 * 3600.0,
+            strings: vec![PvString::default(); 2],
+            load: 0.5,
+            battery: None,
+            events: Vec::new(),
+        }
+    }
+}
+
+/// PV string at full irradiation
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct PvString {
+    /// Voltage in V
+    pub voltage: f64,
+    /// Current in A
+    pub current: f64,
+}
+
+impl Default for PvString {
+    fn default() -> Self {
+        PvString {
+            voltage: 400.0,
+            current: 9.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct Battery {
+    /// Usable capacity in kWh
+    pub capacity: f64,
+    /// State of charge in % at the start
+    pub soc: f64,
+    /// Maximum charge and discharge power in kW
+    pub power: f64,
+}
+
+/// Alarm or device status for a period of each simulated day
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct Event {
+    #[serde(deserialize_with = "time_of_day")]
+    pub from: f64,
+    #[serde(deserialize_with = "time_of_day")]
+    pub to: f64,
+    /// Alarm ID of the [catalogue](huawei_solar::alarms::CATALOGUE), major
+    /// alarms shut the inverter down
+    pub alarm: Option<u16>,
+    /// [DeviceStatus] code, anything but on-grid stops production
+    pub status: Option<u16>,
+}
+
+impl Event {
+    fn is_active(&self, time_of_day: f64) -> bool {
+        (self.from..self.to).contains(&time_of_day)
+    }
+}
+
+/// Instantaneous values of the simulated plant, power in kW
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    /// Voltage and current of each string
+    pub strings: Vec<(f64, f64)>,
+    pub input_power: f64,
+    /// Fed into the grid by the inverter
+    pub active_power: f64,
+    /// Positive while charging
+    pub battery_power: f64,
+    /// Exported to the grid as seen by the meter
+    pub grid_power: f64,
+    pub status: DeviceStatus,
+    pub alarms: Vec<&'static Alarm>,
+}
+
+/// Simulated inverter, advanced in simulated time and written to a
+/// [Memory]
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    scenario: Scenario,
+    /// Simulated seconds since midnight of the first day
+    time: f64,
+    soc: f64,
+    /// Active power in % of the maximum, as written by a client
+    derating: f64,
+    /// Lowest SOC in % the battery discharges to, as written by a client
+    discharge_cutoff: f64,
+    energy_total: f64,
+    energy_day: f64,
+    peak_power_day: f64,
+    charge_total: f64,
+    discharge_total: f64,
+    charge_day: f64,
+    discharge_day: f64,
+    export_total: f64,
+    import_total: f64,
+}
+
+impl Simulation {
+    pub fn new(scenario: Scenario) -> Simulation {
+        Simulation {
+            time: scenario.start,
+            soc: scenario.battery.map_or(0.0, |battery| battery.soc),
+            derating: 100.0,
+            discharge_cutoff: 5.0,
+            scenario,
+            energy_total: 0.0,
+            energy_day: 0.0,
+            peak_power_day: 0.0,
+            charge_total: 0.0,
+            discharge_total: 0.0,
+            charge_day: 0.0,
+            discharge_day: 0.0,
+            export_total: 0.0,
+            import_total: 0.0,
+        }
+    }
+
+    pub fn scenario(&self) -> &Scenario {
+        &self.scenario
+    }
+
+    /// Simulated seconds since midnight of the current day
+    pub fn time_of_day(&self) -> f64 {
+        self.time % DAY
+    }
+
+    /// State of charge of the battery in %
+    pub fn soc(&self) -> f64 {
+        self.soc
+    }
+
+    /// Irradiation from 0 at night to 1 at noon
+    fn irradiation(&self) -> f64 {
+        let (sunrise, sunset) = (self.scenario.sunrise, self.scenario.sunset);
+        let time = self.time_of_day();
+        if time <= sunrise || time >= sunset {
+            return 0.0;
+        }
+        (PI * (time - sunrise) / (sunset - sunrise)).sin()
+    }
+
+    pub fn state(&self) -> State {
+        let time = self.time_of_day();
+        let events = self
+            .scenario
+            .events
+            .iter()
+            .filter(|event| event.is_active(time));
+        let alarms = events
+            .clone()
+            .filter_map(|event| event.alarm.and_then(Alarm::by_id))
+            .collect::<Vec<_>>();
+        let forced = events.filter_map(|event| event.status).next_back();
+
+        let irradiation = self.irradiation();
+        let status = match forced {
+            Some(code) => DeviceStatus::try_from(code).unwrap_or(DeviceStatus::Unknown(code)),
+            None if irradiation == 0.0 => DeviceStatus::StandbyNoIrradiation,
+            None if alarms.iter().any(|alarm| alarm.severity == Severity::Major) => {
+                DeviceStatus::ShutdownFault
+            }
+            None if self.derating < 100.0 => DeviceStatus::OnGridPowerLimited,
+            None => DeviceStatus::OnGrid,
+        };
+        let producing = status.is_producing();
+
+        let strings = self
+            .scenario
+            .strings
+            .iter()
+            .map(|string| match irradiation {
+                irradiation if irradiation > 0.0 => (
+                    string.voltage * (0.85 + 0.15 * irradiation),
+                    if producing {
+                        string.current * irradiation
+                    } else {
+                        0.0
+                    },
+                ),
+                _ => (0.0, 0.0),
+            })
+            .collect::<Vec<_>>();
+        let input_power = strings.iter().map(|(u, i)| u * i).sum::<f64>() / 1000.0;
+        let limit = self.scenario.rated_power * self.derating / 100.0;
+        let pv_power = (input_power * EFFICIENCY).min(limit);
+
+        let battery_power = match self.scenario.battery {
+            Some(battery) if producing || irradiation == 0.0 => {
+                let power = (pv_power - self.scenario.load).clamp(-battery.power, battery.power);
+                if (power > 0.0 && self.soc >= 100.0)
+                    || (power < 0.0 && self.soc <= self.discharge_cutoff)
+                {
+                    0.0
+                } else {
+                    power
+                }
+            }
+            _ => 0.0,
+        };
+        let active_power = pv_power - battery_power;
+        State {
+            strings,
+            input_power,
+            active_power,
+            battery_power,
+            grid_power: active_power - self.scenario.load,
+            status,
+            alarms,
+        }
+    }
+
+    /// Advance the simulated time by `seconds`, integrating energies and the
+    /// state of charge
+    pub fn advance(&mut self, seconds: f64) {
+        let mut remaining = seconds;
+        while remaining > 0.0 {
+            let step = remaining.min(MAX_STEP).min(DAY - self.time_of_day());
+            let state = self.state();
+            let hours = step / 3600.0;
+            self.energy_total += state.active_power.max(0.0) * hours;
+            self.energy_day += state.active_power.max(0.0) * hours;
+            self.peak_power_day = self.peak_power_day.max(state.active_power);
+            let charged = state.battery_power * hours;
+            if charged > 0.0 {
+                self.charge_total += charged;
+                self.charge_day += charged;
+            } else {
+                self.discharge_total -= charged;
+                self.discharge_day -= charged;
+            }
+            if let Some(battery) = self.scenario.battery {
+                self.soc = (self.soc + charged / battery.capacity * 100.0).clamp(0.0, 100.0);
+            }
+            if state.grid_power > 0.0 {
+                self.export_total += state.grid_power * hours;
+            } else {
+                self.import_total -= state.grid_power * hours;
+            }
+
+            self.time += step;
+            remaining -= step;
+            if self.time_of_day() == 0.0 {
+                self.energy_day = 0.0;
+                self.peak_power_day = 0.0;
+                self.charge_day = 0.0;
+                self.discharge_day = 0.0;
+            }
+        }
+    }
+
+    /// Write the registers which do not change while simulating and the
+    /// defaults of the settings
+    pub fn init(&self, memory: &mut Memory) -> Result<(), RegisterError> {
+        let scenario = &self.scenario;
+        let strings = scenario.strings.len() as f64;
+        set_str(memory, &registers::MODEL, &scenario.model)?;
+        set_str(memory, &registers::SN, &scenario.serial_number)?;
+        set_str(memory, &registers::PN, "01074356")?;
+        memory.set(&registers::MODEL_ID, 428.0)?;
+        memory.set(&registers::NUMBER_OF_PV_STRINGS, strings)?;
+        memory.set(&registers::NUMBER_OF_MPP_TRACKERS, (strings / 2.0).ceil())?;
+        memory.set(&registers::RATED_POWER, scenario.rated_power)?;
+        memory.set(&registers::MAXIMUM_ACTIVE_POWER, scenario.rated_power * 1.1)?;
+        memory.set(
+            &registers::MAXIMUM_APPARENT_POWER,
+            scenario.rated_power * 1.1,
+        )?;
+        memory.set(
+            &registers::MAXIMUM_REACTIVE_POWER_TO_GRID,
+            scenario.rated_power * 0.6,
+        )?;
+        memory.set(
+            &registers::MAXIMUM_APPARENT_POWER_FROM_GRID,
+            scenario.rated_power,
+        )?;
+        memory.set(&registers::SHUTDOWN_TIME, u32::MAX.into())?;
+        memory.set(&registers::ACTIVE_POWER_PERCENTAGE_DERATING, self.derating)?;
+        memory.set(&registers::INSULATION_RESISTANCE, 3.0)?;
+
+        memory.set(&meter::STATUS, 1.0)?;
+        memory.set(&meter::METER_TYPE, 1.0)?;
+
+        if let Some(battery) = scenario.battery {
+            memory.set(&storage::PRODUCT_MODEL, 2.0)?;
+            memory.set(&storage::WORKING_MODE, 4.0)?;
+            memory.set(&storage::WORKING_MODE_SETTINGS, 2.0)?;
+            for reg in [
+                &storage::RATED_CHARGE_POWER,
+                &storage::RATED_DISCHARGE_POWER,
+                &storage::MAXIMUM_CHARGE_POWER,
+                &storage::MAXIMUM_DISCHARGE_POWER,
+                &storage::MAXIMUM_CHARGING_POWER,
+                &storage::MAXIMUM_DISCHARGING_POWER,
+            ] {
+                memory.set(reg, battery.power * 1000.0)?;
+            }
+            memory.set(&storage::CHARGING_CUTOFF_CAPACITY, 100.0)?;
+            memory.set(&storage::DISCHARGING_CUTOFF_CAPACITY, self.discharge_cutoff)?;
+            memory.set(
+                &storage::combined::RATED_CAPACITY,
+                battery.capacity * 1000.0,
+            )?;
+            memory.set(&storage::BATTERY_TEMPERATURE, 25.0)?;
+            memory.set(&storage::BUS_VOLTAGE, BATTERY_BUS_VOLTAGE)?;
+            memory.set(&storage::combined::BUS_VOLTAGE, BATTERY_BUS_VOLTAGE)?;
+            set_str(memory, &storage::SERIAL_NUMBER, "HV2150098765")?;
+        }
+        Ok(())
+    }
+
+    /// Take over settings written by clients, advance by `seconds` and write
+    /// the new state
+    pub fn step(&mut self, memory: &mut Memory, seconds: f64) -> Result<(), RegisterError> {
+        self.derating = memory
+            .get(&registers::ACTIVE_POWER_PERCENTAGE_DERATING)
+            .clamp(0.0, 100.0);
+        if self.scenario.battery.is_some() {
+            self.discharge_cutoff = memory.get(&storage::DISCHARGING_CUTOFF_CAPACITY);
+        }
+        self.advance(seconds);
+        self.write(memory)
+    }
+
+    /// Write the current state to `memory`
+    pub fn write(&self, memory: &mut Memory) -> Result<(), RegisterError> {
+        let state = self.state();
+        let producing = state.status.is_producing();
+
+        for (n, &(voltage, current)) in state.strings.iter().enumerate() {
+            if let Some((voltage_reg, current_reg)) = registers::pv_string(n + 1) {
+                memory.set(&voltage_reg, voltage)?;
+                memory.set(&current_reg, current)?;
+            }
+        }
+        memory.set(&registers::INPUT_POWER, state.input_power)?;
+        let phase_voltage = if producing { PHASE_VOLTAGE } else { 0.0 };
+        let line_voltage = if producing { LINE_VOLTAGE } else { 0.0 };
+        let phase_current = state.active_power * 1000.0 / 3.0 / PHASE_VOLTAGE;
+        for reg in [
+            &registers::LINE_VOLTAGE_A_B,
+            &registers::LINE_VOLTAGE_B_C,
+            &registers::LINE_VOLTAGE_C_A,
+        ] {
+            memory.set(reg, line_voltage)?;
+        }
+        for reg in [
+            &registers::PHASE_VOLTAGE_A,
+            &registers::PHASE_VOLTAGE_B,
+            &registers::PHASE_VOLTAGE_C,
+        ] {
+            memory.set(reg, phase_voltage)?;
+        }
+        for reg in [
+            &registers::PHASE_CURRENT_A,
+            &registers::PHASE_CURRENT_B,
+            &registers::PHASE_CURRENT_C,
+        ] {
+            memory.set(reg, phase_current)?;
+        }
+        memory.set(&registers::ACTIVE_POWER, state.active_power)?;
+        memory.set(&registers::PEAK_ACTIVE_POWER_DAY, self.peak_power_day)?;
+        memory.set(&registers::REACTIVE_POWER, 0.0)?;
+        memory.set(&registers::POWER_FACTOR, if producing { 1.0 } else { 0.0 })?;
+        memory.set(
+            &registers::GRID_FREQUENCY,
+            if producing { 50.0 } else { 0.0 },
+        )?;
+        memory.set(
+            &registers::EFFICIENCY,
+            if producing { EFFICIENCY * 100.0 } else { 0.0 },
+        )?;
+        memory.set(
+            &registers::INTERNAL_TEMPERATURE,
+            25.0 + 20.0 * state.input_power / self.scenario.rated_power,
+        )?;
+        memory.set(&registers::DEVICE_STATUS, state.status.code().into())?;
+        memory.set(&registers::ACC_ENERGY_YIELD, self.energy_total)?;
+        memory.set(&registers::ENERGY_YIELD_DAY, self.energy_day)?;
+
+        let mut alarms = [0u16; 3];
+        for alarm in &state.alarms {
+            alarms[alarm.register as usize] |= 1 << alarm.bit;
+        }
+        memory.store(registers::ALARMS.address, &alarms);
+
+        let grid_current = state.grid_power * 1000.0 / 3.0 / PHASE_VOLTAGE;
+        for reg in [
+            &meter::PHASE_A_VOLTAGE,
+            &meter::PHASE_B_VOLTAGE,
+            &meter::PHASE_C_VOLTAGE,
+        ] {
+            memory.set(reg, PHASE_VOLTAGE)?;
+        }
+        for reg in [
+            &meter::LINE_VOLTAGE_A_B,
+            &meter::LINE_VOLTAGE_B_C,
+            &meter::LINE_VOLTAGE_C_A,
+        ] {
+            memory.set(reg, LINE_VOLTAGE)?;
+        }
+        for reg in [
+            &meter::PHASE_A_CURRENT,
+            &meter::PHASE_B_CURRENT,
+            &meter::PHASE_C_CURRENT,
+        ] {
+            memory.set(reg, grid_current)?;
+        }
+        for reg in [
+            &meter::PHASE_A_ACTIVE_POWER,
+            &meter::PHASE_B_ACTIVE_POWER,
+            &meter::PHASE_C_ACTIVE_POWER,
+        ] {
+            memory.set(reg, state.grid_power * 1000.0 / 3.0)?;
+        }
+        memory.set(&meter::ACTIVE_POWER, state.grid_power * 1000.0)?;
+        memory.set(&meter::POWER_FACTOR, 1.0)?;
+        memory.set(&meter::GRID_FREQUENCY, 50.0)?;
+        memory.set(&meter::POSITIVE_ACTIVE_ENERGY, self.export_total)?;
+        memory.set(&meter::REVERSE_ACTIVE_ENERGY, self.import_total)?;
+
+        if let Some(battery) = self.scenario.battery {
+            let power = state.battery_power * 1000.0;
+            let running = if power == 0.0 { 1.0 } else { 2.0 };
+            for (status, power_reg, soc_reg, current_reg) in [
+                (
+                    &storage::RUNNING_STATUS,
+                    &storage::CHARGE_DISCHARGE_POWER,
+                    &storage::STATE_OF_CAPACITY,
+                    &storage::BUS_CURRENT,
+                ),
+                (
+                    &storage::combined::RUNNING_STATUS,
+                    &storage::combined::CHARGE_DISCHARGE_POWER,
+                    &storage::combined::STATE_OF_CAPACITY,
+                    &storage::combined::BUS_CURRENT,
+                ),
+            ] {
+                memory.set(status, running)?;
+                memory.set(power_reg, power)?;
+                memory.set(soc_reg, self.soc)?;
+                memory.set(current_reg, power / BATTERY_BUS_VOLTAGE)?;
+            }
+            let remaining = if power < 0.0 {
+                (self.soc - self.discharge_cutoff).max(0.0) / 100.0 * battery.capacity
+                    / -state.battery_power
+                    * 60.0
+            } else {
+                0.0
+            };
+            memory.set(
+                &storage::REMAINING_CHARGE_DISCHARGE_TIME,
+                remaining.min(65535.0),
+            )?;
+            memory.set(&storage::CHARGE_CAPACITY_DAY, self.charge_day)?;
+            memory.set(&storage::DISCHARGE_CAPACITY_DAY, self.discharge_day)?;
+            memory.set(&storage::TOTAL_CHARGE, self.charge_total)?;
+            memory.set(&storage::TOTAL_DISCHARGE, self.discharge_total)?;
+            memory.set(&storage::combined::CHARGE_CAPACITY_DAY, self.charge_day)?;
+            memory.set(
+                &storage::combined::DISCHARGE_CAPACITY_DAY,
+                self.discharge_day,
+            )?;
+            memory.set(&storage::combined::TOTAL_CHARGE, self.charge_total)?;
+            memory.set(&storage::combined::TOTAL_DISCHARGE, self.discharge_total)?;
+        }
+        Ok(())
+    }
+}
+
+fn set_str(memory: &mut Memory, reg: &Register, value: &str) -> Result<(), RegisterError> {
+    memory.set_value(reg, Value::STR(value.to_string()))
+}
+
+/// [Deserialize] seconds since midnight from `"HH:MM"` or `"HH:MM:SS"`
+fn time_of_day<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_time_of_day(&s).ok_or_else(|| de::Error::custom(format!("invalid time of day {}", s)))
+}
+
+fn parse_time_of_day(s: &str) -> Option<f64> {
+    let parts = s
+        .split(':')
+        .map(|part| part.parse::<u32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let (hours, minutes, seconds) = match parts[..] {
+        [hours, minutes] => (hours, minutes, 0),
+        [hours, minutes, seconds] => (hours, minutes, seconds),
+        _ => return None,
+    };
+    if hours > 24 || minutes > 59 || seconds > 59 || (hours == 24 && minutes + seconds > 0) {
+        return None;
+    }
+    Some((hours * 3600 + minutes * 60 + seconds) as f64)
+}
+
+#[test]
+fn parse_time_of_day_test() {
+    assert_eq!(parse_time_of_day("06:30"), Some(23400.0));
+    assert_eq!(parse_time_of_day("23:59:59"), Some(86399.0));
+    assert_eq!(parse_time_of_day("24:00"), Some(DAY));
+    assert_eq!(parse_time_of_day("24:01"), None);
+    assert_eq!(parse_time_of_day("6"), None);
+    assert_eq!(parse_time_of_day("06:60"), None);
+}
+
+#[test]
+fn day_cycle() {
+    let mut simulation = Simulation::new(Scenario {
+        start: 0.0,
+        ..Scenario::default()
+    });
+    let night = simulation.state();
+    assert_eq!(night.status, DeviceStatus::StandbyNoIrradiation);
+    assert_eq!(night.input_power, 0.0);
+    assert_eq!(night.grid_power, -0.5);
+
+    simulation.advance(13.0 * 3600.0);
+    let noon = simulation.state();
+    assert_eq!(noon.status, DeviceStatus::OnGrid);
+    // 2 strings of 400 V and 9 A at full irradiation
+    assert!((noon.input_power - 7.2).abs() < 1e-9);
+    assert!((noon.active_power - 7.2 * EFFICIENCY).abs() < 1e-9);
+    assert!(simulation.energy_day > 30.0);
+
+    simulation.advance(11.0 * 3600.0);
+    assert_eq!(simulation.time_of_day(), 0.0);
+    assert_eq!(simulation.energy_day, 0.0);
+    assert!(simulation.energy_total > 30.0);
+}
+
+#[test]
+fn battery_self_consumption() {
+    let mut simulation = Simulation::new(Scenario {
+        start: 13.0 * 3600.0,
+        load: 1.0,
+        battery: Some(Battery {
+            capacity: 5.0,
+            soc: 50.0,
+            power: 5.0,
+        }),
+        ..Scenario::default()
+    });
+    let noon = simulation.state();
+    assert_eq!(noon.battery_power, 5.0);
+    assert!((noon.grid_power - (7.2 * EFFICIENCY - 6.0)).abs() < 1e-6);
+
+    simulation.advance(3600.0);
+    assert!(simulation.soc() > 90.0);
+    simulation.advance(3600.0);
+    assert_eq!(simulation.soc(), 100.0);
+    assert_eq!(simulation.state().battery_power, 0.0);
+
+    // discharges at night to cover the load down to the cut-off
+    simulation.advance(6.0 * 3600.0);
+    assert_eq!(simulation.state().battery_power, -1.0);
+    simulation.advance(8.0 * 3600.0);
+    assert!((simulation.soc() - 5.0).abs() < 0.5);
+    let state = simulation.state();
+    assert_eq!(state.battery_power, 0.0);
+    assert_eq!(state.grid_power, -1.0);
+}
+
+#[test]
+fn events() {
+    let scenario: Scenario = serde_yaml::from_str(
+        "start: '12:00'\nevents:\n  - {from: '11:00', to: '13:00', alarm: 2063}\n  - {from: '12:30', to: '13:00', alarm: 2001}\n  - {from: '14:00', to: '15:00', status: 0x0303}",
+    )
+    .unwrap();
+    let mut simulation = Simulation::new(scenario);
+    let state = simulation.state();
+    assert_eq!(state.status, DeviceStatus::OnGrid);
+    assert_eq!(state.alarms.len(), 1);
+
+    simulation.advance(1800.0);
+    let state = simulation.state();
+    assert_eq!(state.status, DeviceStatus::ShutdownFault);
+    assert_eq!(state.alarms.len(), 2);
+    assert_eq!(state.active_power, 0.0);
+
+    simulation.advance(7200.0);
+    let state = simulation.state();
+    assert_eq!(
+        state.status,
+        DeviceStatus::ShutdownCommunicationDisconnected
+    );
+    assert!(state.alarms.is_empty());
+    assert_eq!(state.active_power, 0.0);
+    assert!(state.strings[0].0 > 0.0);
+}
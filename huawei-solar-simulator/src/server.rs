@@ -0,0 +1,294 @@
+//! Modbus TCP server answering from a [Memory] shared with the
+//! simulation.
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use huawei_solar::registers::{Access, Register, RegisterError, Value};
+
+use crate::table::REGISTERS;
+
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+const GATEWAY_TARGET: u8 = 0x0B;
+
+/// Register words of the simulated inverter.
+///
+/// Only addresses of the register table are served. Reads may span gaps
+/// between registers, which read as zero, but must start and end in a
+/// register. Reading a write-only register, writing a read-only one or
+/// writing a gap fails with an illegal data address exception like on the
+/// inverter.
+#[derive(Debug, Clone)]
+pub struct Memory {
+    words: HashMap<u16, u16>,
+    access: HashMap<u16, Access>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        let mut access = HashMap::new();
+        for reg in REGISTERS {
+            for address in reg.address..reg.address + reg.quantity as u16 {
+                access.entry(address).or_insert(reg.access);
+            }
+        }
+        Memory {
+            words: HashMap::new(),
+            access,
+        }
+    }
+}
+
+impl Memory {
+    /// Store the engineering value `value` in `reg`
+    pub fn set(&mut self, reg: &Register, value: f64) -> Result<(), RegisterError> {
+        self.set_value(reg, reg.value_from_float(value)?)
+    }
+
+    pub fn set_value(&mut self, reg: &Register, value: Value) -> Result<(), RegisterError> {
+        let words = reg.encode(&value)?;
+        self.store(reg.address, &words);
+        Ok(())
+    }
+
+    /// Engineering value of `reg`, as last set or written by a client
+    pub fn get(&self, reg: &Register) -> f64 {
+        let value = reg
+            .typ
+            .convert(&self.load(reg.address, reg.quantity.into()))
+            .expect("register sizes match their types");
+        huawei_solar::registers::RegValue { reg, val: value }
+            .to_float()
+            .unwrap_or(0.0)
+    }
+
+    pub fn store(&mut self, address: u16, words: &[u16]) {
+        for (address, &word) in (address..).zip(words) {
+            self.words.insert(address, word);
+        }
+    }
+
+    pub fn load(&self, address: u16, count: u16) -> Vec<u16> {
+        (address..address + count)
+            .map(|address| self.words.get(&address).copied().unwrap_or(0))
+            .collect()
+    }
+
+    fn read(&self, address: u16, count: u16) -> Result<Vec<u16>, u8> {
+        // `None` for gaps
+        let readable = |address| {
+            self.access
+                .get(&address)
+                .map(|&access| access != Access::WO)
+        };
+        let Some(last) = address.checked_add(count.saturating_sub(1)) else {
+            return Err(ILLEGAL_DATA_ADDRESS);
+        };
+        if count == 0
+            || readable(address) != Some(true)
+            || readable(last) != Some(true)
+            || (address..=last).any(|address| readable(address) == Some(false))
+        {
+            return Err(ILLEGAL_DATA_ADDRESS);
+        }
+        Ok(self.load(address, count))
+    }
+
+    fn write(&mut self, address: u16, words: &[u16]) -> Result<(), u8> {
+        let Some(end) = address.checked_add(words.len() as u16) else {
+            return Err(ILLEGAL_DATA_ADDRESS);
+        };
+        let writable = (address..end).all(|address| {
+            self.access
+                .get(&address)
+                .is_some_and(|&access| access != Access::RO)
+        });
+        if !writable {
+            return Err(ILLEGAL_DATA_ADDRESS);
+        }
+        self.store(address, words);
+        Ok(())
+    }
+}
+
+/// Modbus TCP server for one simulated inverter
+pub struct Server {
+    listener: TcpListener,
+    memory: Arc<Mutex<Memory>>,
+    unit_id: u8,
+}
+
+impl Server {
+    /// Listen on `addr` for requests to `unit_id`, unit ID 0 is answered as
+    /// well like the SDongle does
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        unit_id: u8,
+        memory: Arc<Mutex<Memory>>,
+    ) -> io::Result<Server> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            memory,
+            unit_id,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept connections in the background, each served by its own thread
+    pub fn spawn(self) -> JoinHandle<()> {
+        thread::spawn(move || {
+            for stream in self.listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                let memory = self.memory.clone();
+                let unit_id = self.unit_id;
+                thread::spawn(move || {
+                    if let Err(err) = serve(stream, &memory, unit_id) {
+                        eprintln!("connection closed: {}", err);
+                    }
+                });
+            }
+        })
+    }
+}
+
+fn serve(mut stream: TcpStream, memory: &Mutex<Memory>, unit_id: u8) -> io::Result<()> {
+    loop {
+        let mut header = [0u8; 7];
+        match stream.read_exact(&mut header) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+        let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if len < 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty frame"));
+        }
+        let mut request = vec![0u8; len - 1];
+        stream.read_exact(&mut request)?;
+
+        let response = if header[6] == unit_id || header[6] == 0 {
+            let mut memory = memory
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            respond(&request, &mut memory)
+        } else {
+            Err(GATEWAY_TARGET)
+        };
+        let response = response.unwrap_or_else(|code| vec![request[0] | 0x80, code]);
+
+        let mut frame = header[..4].to_vec();
+        frame.extend_from_slice(&(response.len() as u16 + 1).to_be_bytes());
+        frame.push(header[6]);
+        frame.extend_from_slice(&response);
+        stream.write_all(&frame)?;
+    }
+}
+
+/// Response PDU to the request `pdu`, or the exception code
+fn respond(pdu: &[u8], memory: &mut Memory) -> Result<Vec<u8>, u8> {
+    let word = |i: usize| -> Result<u16, u8> {
+        pdu.get(i..i + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .ok_or(ILLEGAL_DATA_VALUE)
+    };
+    match pdu[0] {
+        0x03 => {
+            let (address, count) = (word(1)?, word(3)?);
+            if !(1..=125).contains(&count) {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let words = memory.read(address, count)?;
+            let mut response = vec![0x03, 2 * count as u8];
+            response.extend(words.iter().flat_map(|word| word.to_be_bytes()));
+            Ok(response)
+        }
+        0x06 => {
+            memory.write(word(1)?, &[word(3)?])?;
+            Ok(pdu[..5].to_vec())
+        }
+        0x10 => {
+            let (address, count) = (word(1)?, word(3)?);
+            if !(1..=123).contains(&count)
+                || pdu.get(5).map(|&len| len as usize) != Some(2 * count as usize)
+                || pdu.len() != 6 + 2 * count as usize
+            {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            let words = (0..count as usize)
+                .map(|i| word(6 + 2 * i))
+                .collect::<Result<Vec<_>, _>>()?;
+            memory.write(address, &words)?;
+            Ok(pdu[..5].to_vec())
+        }
+        _ => Err(ILLEGAL_FUNCTION),
+    }
+}
+
+#[test]
+fn memory_access() {
+    use huawei_solar::registers::{MODEL_ID, STARTUP, TIME_ZONE};
+    let mut memory = Memory::default();
+    memory.set(&TIME_ZONE, -60.0).unwrap();
+    assert_eq!(memory.get(&TIME_ZONE), -60.0);
+    assert_eq!(memory.read(TIME_ZONE.address, 1), Ok(vec![0xFFC4]));
+    // gap behind TIME_ZONE
+    assert_eq!(memory.read(TIME_ZONE.address, 2), Err(ILLEGAL_DATA_ADDRESS));
+    assert_eq!(memory.read(STARTUP.address, 1), Err(ILLEGAL_DATA_ADDRESS));
+    assert_eq!(
+        memory.write(MODEL_ID.address, &[1]),
+        Err(ILLEGAL_DATA_ADDRESS)
+    );
+    assert_eq!(memory.write(STARTUP.address, &[0]), Ok(()));
+    assert_eq!(memory.read(u16::MAX, 2), Err(ILLEGAL_DATA_ADDRESS));
+}
+
+#[test]
+fn respond_to_requests() {
+    use huawei_solar::registers::{MODEL_ID, TIME_ZONE};
+    let mut memory = Memory::default();
+    memory.set(&MODEL_ID, 428.0).unwrap();
+    assert_eq!(
+        respond(&[0x03, 0x75, 0x76, 0x00, 0x01], &mut memory),
+        Ok(vec![0x03, 0x02, 0x01, 0xAC])
+    );
+    assert_eq!(
+        respond(&[0x03, 0x75, 0x76, 0x00, 0x00], &mut memory),
+        Err(ILLEGAL_DATA_VALUE)
+    );
+    assert_eq!(
+        respond(
+            &[0x10, 0xA7, 0xFE, 0x00, 0x01, 0x02, 0x00, 0x3C],
+            &mut memory
+        ),
+        Ok(vec![0x10, 0xA7, 0xFE, 0x00, 0x01])
+    );
+    assert_eq!(memory.get(&TIME_ZONE), 60.0);
+    assert_eq!(
+        respond(
+            &[0x10, 0xA7, 0xFE, 0x00, 0x01, 0x04, 0x00, 0x3C],
+            &mut memory
+        ),
+        Err(ILLEGAL_DATA_VALUE)
+    );
+    // a count whose byte count does not fit in the length byte
+    let mut request = vec![0x10, 0xA7, 0xFE, 0x00, 0x80, 0x00];
+    request.resize(6 + 256, 0);
+    assert_eq!(respond(&request, &mut memory), Err(ILLEGAL_DATA_VALUE));
+    assert_eq!(
+        respond(&[0x10, 0xA7, 0xFE, 0x00, 0x00, 0x00], &mut memory),
+        Err(ILLEGAL_DATA_VALUE)
+    );
+    assert_eq!(
+        respond(&[0x41, 0x24, 0x01, 0x00], &mut memory),
+        Err(ILLEGAL_FUNCTION)
+    );
+}
@@ -0,0 +1,263 @@
+//! Registers of the library served by the simulator.
+
+use huawei_solar::registers::*;
+
+/// Every register of [huawei_solar::registers], readable or writable
+/// according to its [Access]
+pub const REGISTERS: &[&Register<'static>] = &[
+    MODEL.erased(),
+    SN.erased(),
+    PN.erased(),
+    MODEL_ID.erased(),
+    NUMBER_OF_PV_STRINGS.erased(),
+    NUMBER_OF_MPP_TRACKERS.erased(),
+    RATED_POWER.erased(),
+    MAXIMUM_ACTIVE_POWER.erased(),
+    MAXIMUM_APPARENT_POWER.erased(),
+    MAXIMUM_REACTIVE_POWER_TO_GRID.erased(),
+    MAXIMUM_APPARENT_POWER_FROM_GRID.erased(),
+    STATE_1.erased(),
+    STATE_2.erased(),
+    STATE_3.erased(),
+    ALARM_1.erased(),
+    ALARM_2.erased(),
+    ALARM_3.erased(),
+    ALARMS.erased(),
+    PV1_VOLTAGE.erased(),
+    PV1_CURRENT.erased(),
+    PV2_VOLTAGE.erased(),
+    PV2_CURRENT.erased(),
+    PV3_VOLTAGE.erased(),
+    PV3_CURRENT.erased(),
+    PV4_VOLTAGE.erased(),
+    PV4_CURRENT.erased(),
+    PV5_VOLTAGE.erased(),
+    PV5_CURRENT.erased(),
+    PV6_VOLTAGE.erased(),
+    PV6_CURRENT.erased(),
+    PV7_VOLTAGE.erased(),
+    PV7_CURRENT.erased(),
+    PV8_VOLTAGE.erased(),
+    PV8_CURRENT.erased(),
+    PV9_VOLTAGE.erased(),
+    PV9_CURRENT.erased(),
+    PV10_VOLTAGE.erased(),
+    PV10_CURRENT.erased(),
+    PV11_VOLTAGE.erased(),
+    PV11_CURRENT.erased(),
+    PV12_VOLTAGE.erased(),
+    PV12_CURRENT.erased(),
+    PV13_VOLTAGE.erased(),
+    PV13_CURRENT.erased(),
+    PV14_VOLTAGE.erased(),
+    PV14_CURRENT.erased(),
+    PV15_VOLTAGE.erased(),
+    PV15_CURRENT.erased(),
+    PV16_VOLTAGE.erased(),
+    PV16_CURRENT.erased(),
+    PV17_VOLTAGE.erased(),
+    PV17_CURRENT.erased(),
+    PV18_VOLTAGE.erased(),
+    PV18_CURRENT.erased(),
+    PV19_VOLTAGE.erased(),
+    PV19_CURRENT.erased(),
+    PV20_VOLTAGE.erased(),
+    PV20_CURRENT.erased(),
+    PV21_VOLTAGE.erased(),
+    PV21_CURRENT.erased(),
+    PV22_VOLTAGE.erased(),
+    PV22_CURRENT.erased(),
+    PV23_VOLTAGE.erased(),
+    PV23_CURRENT.erased(),
+    PV24_VOLTAGE.erased(),
+    PV24_CURRENT.erased(),
+    INPUT_POWER.erased(),
+    LINE_VOLTAGE_A_B.erased(),
+    LINE_VOLTAGE_B_C.erased(),
+    LINE_VOLTAGE_C_A.erased(),
+    PHASE_VOLTAGE_A.erased(),
+    PHASE_VOLTAGE_B.erased(),
+    PHASE_VOLTAGE_C.erased(),
+    PHASE_CURRENT_A.erased(),
+    PHASE_CURRENT_B.erased(),
+    PHASE_CURRENT_C.erased(),
+    PEAK_ACTIVE_POWER_DAY.erased(),
+    ACTIVE_POWER.erased(),
+    REACTIVE_POWER.erased(),
+    POWER_FACTOR.erased(),
+    GRID_FREQUENCY.erased(),
+    EFFICIENCY.erased(),
+    INTERNAL_TEMPERATURE.erased(),
+    INSULATION_RESISTANCE.erased(),
+    DEVICE_STATUS.erased(),
+    FAULT_CODE.erased(),
+    STARTUP_TIME.erased(),
+    SHUTDOWN_TIME.erased(),
+    ACC_ENERGY_YIELD.erased(),
+    ENERGY_YIELD_DAY.erased(),
+    storage::RUNNING_STATUS.erased(),
+    storage::CHARGE_DISCHARGE_POWER.erased(),
+    storage::BUS_VOLTAGE.erased(),
+    storage::STATE_OF_CAPACITY.erased(),
+    storage::WORKING_MODE.erased(),
+    storage::RATED_CHARGE_POWER.erased(),
+    storage::RATED_DISCHARGE_POWER.erased(),
+    storage::FAULT_ID.erased(),
+    storage::CHARGE_CAPACITY_DAY.erased(),
+    storage::DISCHARGE_CAPACITY_DAY.erased(),
+    storage::BUS_CURRENT.erased(),
+    storage::BATTERY_TEMPERATURE.erased(),
+    storage::REMAINING_CHARGE_DISCHARGE_TIME.erased(),
+    storage::DCDC_VERSION.erased(),
+    storage::BMS_VERSION.erased(),
+    storage::MAXIMUM_CHARGE_POWER.erased(),
+    storage::MAXIMUM_DISCHARGE_POWER.erased(),
+    storage::SERIAL_NUMBER.erased(),
+    storage::TOTAL_CHARGE.erased(),
+    storage::TOTAL_DISCHARGE.erased(),
+    storage::PRODUCT_MODEL.erased(),
+    storage::MAXIMUM_CHARGING_POWER.erased(),
+    storage::MAXIMUM_DISCHARGING_POWER.erased(),
+    storage::CHARGING_CUTOFF_CAPACITY.erased(),
+    storage::DISCHARGING_CUTOFF_CAPACITY.erased(),
+    storage::FORCED_CHARGE_DISCHARGE_PERIOD.erased(),
+    storage::WORKING_MODE_SETTINGS.erased(),
+    storage::CHARGE_FROM_GRID.erased(),
+    storage::GRID_CHARGE_CUTOFF_SOC.erased(),
+    storage::FORCIBLE_CHARGE_DISCHARGE.erased(),
+    storage::FORCIBLE_CHARGE_DISCHARGE_SOC.erased(),
+    storage::BACKUP_POWER_SOC.erased(),
+    storage::FORCIBLE_CHARGE_DISCHARGE_MODE.erased(),
+    storage::FORCIBLE_CHARGE_POWER.erased(),
+    storage::FORCIBLE_DISCHARGE_POWER.erased(),
+    storage::TIME_OF_USE_PERIODS.erased(),
+    storage::unit_2::SERIAL_NUMBER.erased(),
+    storage::unit_2::STATE_OF_CAPACITY.erased(),
+    storage::unit_2::RUNNING_STATUS.erased(),
+    storage::unit_2::CHARGE_DISCHARGE_POWER.erased(),
+    storage::unit_2::CHARGE_CAPACITY_DAY.erased(),
+    storage::unit_2::DISCHARGE_CAPACITY_DAY.erased(),
+    storage::unit_2::BUS_VOLTAGE.erased(),
+    storage::unit_2::BUS_CURRENT.erased(),
+    storage::unit_2::BATTERY_TEMPERATURE.erased(),
+    storage::unit_2::TOTAL_CHARGE.erased(),
+    storage::unit_2::TOTAL_DISCHARGE.erased(),
+    storage::unit_2::PRODUCT_MODEL.erased(),
+    storage::combined::RATED_CAPACITY.erased(),
+    storage::combined::STATE_OF_CAPACITY.erased(),
+    storage::combined::RUNNING_STATUS.erased(),
+    storage::combined::BUS_VOLTAGE.erased(),
+    storage::combined::BUS_CURRENT.erased(),
+    storage::combined::CHARGE_DISCHARGE_POWER.erased(),
+    storage::combined::TOTAL_CHARGE.erased(),
+    storage::combined::TOTAL_DISCHARGE.erased(),
+    storage::combined::CHARGE_CAPACITY_DAY.erased(),
+    storage::combined::DISCHARGE_CAPACITY_DAY.erased(),
+    storage::unit_1_pack_1::SERIAL_NUMBER.erased(),
+    storage::unit_1_pack_1::FIRMWARE_VERSION.erased(),
+    storage::unit_1_pack_1::WORKING_STATUS.erased(),
+    storage::unit_1_pack_1::STATE_OF_CAPACITY.erased(),
+    storage::unit_1_pack_1::CHARGE_DISCHARGE_POWER.erased(),
+    storage::unit_1_pack_1::VOLTAGE.erased(),
+    storage::unit_1_pack_1::CURRENT.erased(),
+    storage::unit_1_pack_1::TOTAL_CHARGE.erased(),
+    storage::unit_1_pack_1::TOTAL_DISCHARGE.erased(),
+    storage::unit_1_pack_1::MAXIMUM_TEMPERATURE.erased(),
+    storage::unit_1_pack_1::MINIMUM_TEMPERATURE.erased(),
+    storage::unit_1_pack_2::SERIAL_NUMBER.erased(),
+    storage::unit_1_pack_2::FIRMWARE_VERSION.erased(),
+    storage::unit_1_pack_2::WORKING_STATUS.erased(),
+    storage::unit_1_pack_2::STATE_OF_CAPACITY.erased(),
+    storage::unit_1_pack_2::CHARGE_DISCHARGE_POWER.erased(),
+    storage::unit_1_pack_2::VOLTAGE.erased(),
+    storage::unit_1_pack_2::CURRENT.erased(),
+    storage::unit_1_pack_2::TOTAL_CHARGE.erased(),
+    storage::unit_1_pack_2::TOTAL_DISCHARGE.erased(),
+    storage::unit_1_pack_2::MAXIMUM_TEMPERATURE.erased(),
+    storage::unit_1_pack_2::MINIMUM_TEMPERATURE.erased(),
+    storage::unit_1_pack_3::SERIAL_NUMBER.erased(),
+    storage::unit_1_pack_3::FIRMWARE_VERSION.erased(),
+    storage::unit_1_pack_3::WORKING_STATUS.erased(),
+    storage::unit_1_pack_3::STATE_OF_CAPACITY.erased(),
+    storage::unit_1_pack_3::CHARGE_DISCHARGE_POWER.erased(),
+    storage::unit_1_pack_3::VOLTAGE.erased(),
+    storage::unit_1_pack_3::CURRENT.erased(),
+    storage::unit_1_pack_3::TOTAL_CHARGE.erased(),
+    storage::unit_1_pack_3::TOTAL_DISCHARGE.erased(),
+    storage::unit_1_pack_3::MAXIMUM_TEMPERATURE.erased(),
+    storage::unit_1_pack_3::MINIMUM_TEMPERATURE.erased(),
+    storage::unit_2_pack_1::SERIAL_NUMBER.erased(),
+    storage::unit_2_pack_1::FIRMWARE_VERSION.erased(),
+    storage::unit_2_pack_1::WORKING_STATUS.erased(),
+    storage::unit_2_pack_1::STATE_OF_CAPACITY.erased(),
+    storage::unit_2_pack_1::CHARGE_DISCHARGE_POWER.erased(),
+    storage::unit_2_pack_1::VOLTAGE.erased(),
+    storage::unit_2_pack_1::CURRENT.erased(),
+    storage::unit_2_pack_1::TOTAL_CHARGE.erased(),
+    storage::unit_2_pack_1::TOTAL_DISCHARGE.erased(),
+    storage::unit_2_pack_1::MAXIMUM_TEMPERATURE.erased(),
+    storage::unit_2_pack_1::MINIMUM_TEMPERATURE.erased(),
+    storage::unit_2_pack_2::SERIAL_NUMBER.erased(),
+    storage::unit_2_pack_2::FIRMWARE_VERSION.erased(),
+    storage::unit_2_pack_2::WORKING_STATUS.erased(),
+    storage::unit_2_pack_2::STATE_OF_CAPACITY.erased(),
+    storage::unit_2_pack_2::CHARGE_DISCHARGE_POWER.erased(),
+    storage::unit_2_pack_2::VOLTAGE.erased(),
+    storage::unit_2_pack_2::CURRENT.erased(),
+    storage::unit_2_pack_2::TOTAL_CHARGE.erased(),
+    storage::unit_2_pack_2::TOTAL_DISCHARGE.erased(),
+    storage::unit_2_pack_2::MAXIMUM_TEMPERATURE.erased(),
+    storage::unit_2_pack_2::MINIMUM_TEMPERATURE.erased(),
+    storage::unit_2_pack_3::SERIAL_NUMBER.erased(),
+    storage::unit_2_pack_3::FIRMWARE_VERSION.erased(),
+    storage::unit_2_pack_3::WORKING_STATUS.erased(),
+    storage::unit_2_pack_3::STATE_OF_CAPACITY.erased(),
+    storage::unit_2_pack_3::CHARGE_DISCHARGE_POWER.erased(),
+    storage::unit_2_pack_3::VOLTAGE.erased(),
+    storage::unit_2_pack_3::CURRENT.erased(),
+    storage::unit_2_pack_3::TOTAL_CHARGE.erased(),
+    storage::unit_2_pack_3::TOTAL_DISCHARGE.erased(),
+    storage::unit_2_pack_3::MAXIMUM_TEMPERATURE.erased(),
+    storage::unit_2_pack_3::MINIMUM_TEMPERATURE.erased(),
+    meter::STATUS.erased(),
+    meter::PHASE_A_VOLTAGE.erased(),
+    meter::PHASE_B_VOLTAGE.erased(),
+    meter::PHASE_C_VOLTAGE.erased(),
+    meter::PHASE_A_CURRENT.erased(),
+    meter::PHASE_B_CURRENT.erased(),
+    meter::PHASE_C_CURRENT.erased(),
+    meter::ACTIVE_POWER.erased(),
+    meter::REACTIVE_POWER.erased(),
+    meter::POWER_FACTOR.erased(),
+    meter::GRID_FREQUENCY.erased(),
+    meter::POSITIVE_ACTIVE_ENERGY.erased(),
+    meter::REVERSE_ACTIVE_ENERGY.erased(),
+    meter::ACCUMULATED_REACTIVE_ENERGY.erased(),
+    meter::METER_TYPE.erased(),
+    meter::LINE_VOLTAGE_A_B.erased(),
+    meter::LINE_VOLTAGE_B_C.erased(),
+    meter::LINE_VOLTAGE_C_A.erased(),
+    meter::PHASE_A_ACTIVE_POWER.erased(),
+    meter::PHASE_B_ACTIVE_POWER.erased(),
+    meter::PHASE_C_ACTIVE_POWER.erased(),
+    meter::METER_MODEL_DETECTION.erased(),
+    REACTIVE_POWER_COMPENSATION_PF.erased(),
+    REACTIVE_POWER_COMPENSATION_Q_S.erased(),
+    ACTIVE_POWER_PERCENTAGE_DERATING.erased(),
+    STARTUP.erased(),
+    SHUTDOWN.erased(),
+    GRID_CODE.erased(),
+    TIME_ZONE.erased(),
+    ACTIVE_POWER_CONTROL_MODE.erased(),
+    MAXIMUM_FEED_GRID_POWER.erased(),
+    MAXIMUM_FEED_GRID_POWER_PERCENT.erased(),
+    HEARTBEAT.erased(),
+];
+
+#[test]
+fn unique_names() {
+    let mut names = std::collections::HashSet::new();
+    for reg in REGISTERS {
+        assert!(names.insert(reg.name), "{} is not unique", reg.name);
+    }
+}